@@ -1,39 +1,660 @@
+use memmap2::Mmap;
+use std::cell::Cell;
+use std::env;
 use std::error::Error;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
-    println!("With text:\n{}", contents);
-    Ok(())
+mod matcher;
+mod pool;
+mod printer;
+mod replace;
+mod searcher;
+mod walk;
+
+pub use matcher::{Case, MatchOptions, Matcher, Syntax};
+pub use printer::{Mode, Printer};
+pub use replace::Rewrite;
+pub use searcher::{Event, Line, SearchOptions, Searcher};
+pub use walk::{WalkOptions, Walker};
+
+// The exit status of a search, following grep: 0 if a line matched, 1 if no
+// line matched and 2 if an error occurred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Matched,
+    NoMatch,
+    Error,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        match self {
+            Status::Matched => 0,
+            Status::NoMatch => 1,
+            Status::Error => 2,
+        }
+    }
+}
+
+// Search every file of `config`, printing the matching lines to stdout and the
+// errors to stderr.
+// With `--color=auto` the output is colored if stdout is a terminal.
+pub fn run(config: &Config) -> Status {
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+    let color = config.color(stdout.is_terminal());
+    search_all(config, color, &mut stdout.lock(), &mut stderr.lock())
+}
+
+// Like `run`, with the output going to `out` and the errors to `err`. `out` is
+// not taken to be a terminal, so only `--color=always` colors it.
+pub fn run_with<W: Write, E: Write>(config: &Config, out: &mut W, err: &mut E) -> Status {
+    search_all(config, config.color(false), out, err)
+}
+
+// Search the files and print the results. A file that can't be read doesn't
+// stop the search of the other files, but a closed output stops all of it.
+//
+// With one thread, one file or stdin the results are printed as they are
+// found. Otherwise the files are searched on `config.threads` threads and each
+// file's output is collected and printed in one piece, in the order the files
+// were found unless `--sort=none` is given.
+fn search_all<W: Write, E: Write>(
+    config: &Config,
+    color: bool,
+    out: &mut W,
+    err: &mut E,
+) -> Status {
+    let single = config.paths.len() == 1 && !Path::new(&config.paths[0]).is_dir();
+    let printer = Printer {
+        mode: config.mode,
+        with_filename: config.with_filename.unwrap_or(!single),
+        line_number: config.line_number,
+        byte_offset: config.byte_offset,
+        color,
+        replace: config.replace.clone(),
+    };
+    let search = |path: Result<PathBuf, String>, mut out: &mut dyn Write| {
+        let path = path.map_err(Failure::Error)?;
+        search_file(config, &printer, &path, &mut out).map_err(|e| Failure::new(&path, e))
+    };
+    let mut matched = false;
+    let mut failed = false;
+    let closed = Cell::new(false);
+    let mut finish = |result| match result {
+        Ok(file_matched) => matched |= file_matched,
+        Err(Failure::Closed) => {
+            // output is only printed for a file that counts as a match, except
+            // with `-c`
+            matched |= config.mode != Mode::Count;
+            closed.set(true);
+        }
+        Err(Failure::Error(e)) => {
+            failed = true;
+            let _ = writeln!(err, "minigrep: {}", e);
+        }
+    };
+    let files = config
+        .walker
+        .files(&config.paths)
+        .take_while(|_| !closed.get());
+    // stdin is read as it comes, so it isn't waited for in a worker
+    let stdin = config.paths.iter().any(|path| path == "-");
+    if config.threads == 1 || single || stdin {
+        for path in files {
+            finish(search(path, out));
+        }
+    } else {
+        let ordered = config.sort == Sort::Path;
+        pool::for_each(
+            files,
+            config.threads,
+            ordered,
+            |path| {
+                let mut output = Vec::new();
+                let result = search(path, &mut output);
+                (output, result)
+            },
+            |(output, result)| {
+                // the files searched before the output was closed are dropped
+                if closed.get() {
+                    return;
+                }
+                let written = out.write_all(&output).map_err(Failure::from);
+                finish(written.and(result))
+            },
+        );
+    }
+    if failed {
+        Status::Error
+    } else if matched {
+        Status::Matched
+    } else {
+        Status::NoMatch
+    }
+}
+
+// Why the search of a file didn't finish.
+enum Failure {
+    // The output was closed, like by `head` after the lines it wanted. The
+    // search stops without an error message.
+    Closed,
+    // The file couldn't be searched or the results couldn't be printed.
+    Error(String),
+}
+
+impl Failure {
+    fn new(path: &Path, e: Box<dyn Error>) -> Failure {
+        match e.downcast::<io::Error>() {
+            Ok(e) if e.kind() == io::ErrorKind::BrokenPipe => Failure::Closed,
+            Ok(e) => Failure::Error(format!("{}: {}", path.display(), e)),
+            Err(e) => Failure::Error(format!("{}: {}", path.display(), e)),
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Failure {
+        if e.kind() == io::ErrorKind::BrokenPipe {
+            Failure::Closed
+        } else {
+            Failure::Error(e.to_string())
+        }
+    }
 }
 
+// Files at least this big are memory-mapped with `--mmap`. Mapping smaller
+// files costs more than reading them.
+const MMAP_MIN_LEN: u64 = 1 << 20;
+
+// How much of the start of a file is checked for a NUL byte.
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+// The programs that decompress files with `-z`, by file extension. They write
+// the decompressed file to stdout.
+const DECOMPRESSORS: &[(&str, &[&str])] = &[
+    ("gz", &["gzip", "-d", "-c"]),
+    ("tgz", &["gzip", "-d", "-c"]),
+    ("xz", &["xz", "-d", "-c"]),
+    ("txz", &["xz", "-d", "-c"]),
+];
+
+// Search the file at `path`, or stdin if it is `-`, and print the results.
+// The file is read in chunks, so it is never held in memory whole.
+fn search_file<W: Write>(
+    config: &Config,
+    printer: &Printer,
+    path: &Path,
+    out: &mut W,
+) -> Result<bool, Box<dyn Error>> {
+    if path == Path::new("-") {
+        if config.rewrite.is_some() {
+            return Err("standard input can't be rewritten in place".into());
+        }
+        let stdin = io::stdin();
+        return search_reader(
+            config,
+            printer,
+            Path::new("(standard input)"),
+            stdin.lock(),
+            out,
+        );
+    }
+    if let Some(rewrite) = &config.rewrite {
+        return Ok(rewrite.rewrite(&config.matcher, path)? > 0);
+    }
+    if config.search_zip {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let decompressor = DECOMPRESSORS
+            .iter()
+            .find(|&&(known, _)| extension == Some(known));
+        if let Some((_, command)) = decompressor {
+            return search_decompressed(config, printer, path, command, out);
+        }
+    }
+    let file = File::open(path)?;
+    if config.mmap {
+        let metadata = file.metadata()?;
+        if metadata.is_file() && metadata.len() >= MMAP_MIN_LEN {
+            // the map is only read, but if another process truncates the file
+            // meanwhile reading past its new end kills us with SIGBUS, which
+            // is why mapping is opt-in
+            let map = unsafe { Mmap::map(&file)? };
+            return search_reader(config, printer, path, &map[..], out);
+        }
+    }
+    search_reader(config, printer, path, BufReader::new(file), out)
+}
+
+// Search the output of `command` run on the file at `path`.
+fn search_decompressed<W: Write>(
+    config: &Config,
+    printer: &Printer,
+    path: &Path,
+    command: &[&str],
+    out: &mut W,
+) -> Result<bool, Box<dyn Error>> {
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{}: {}", command[0], e))?;
+    let mut reader = BufReader::new(child.stdout.take().unwrap());
+    let matched = search_reader(config, printer, path, &mut reader, out)?;
+    // read what the search left so the command isn't killed by SIGPIPE and
+    // its exit status tells whether the file was decompressed fine
+    io::copy(&mut reader, &mut io::sink())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{}: {}", command[0], message.trim()).into());
+    }
+    Ok(matched)
+}
+
+// Search `reader`, the contents of the file at `path`, and print the results.
+fn search_reader<R: BufRead, W: Write>(
+    config: &Config,
+    printer: &Printer,
+    path: &Path,
+    mut reader: R,
+    out: &mut W,
+) -> Result<bool, Box<dyn Error>> {
+    // Like grep, take a NUL byte in the first block for a binary file. These
+    // are skipped, while a NUL byte further on is searched like any other. A
+    // memory map is a single buffer, so only its start is checked.
+    let buf = reader.fill_buf()?;
+    if buf[..buf.len().min(BINARY_CHECK_LEN)].contains(&0) {
+        return Ok(false);
+    }
+    let searcher = Searcher::new(&config.matcher, &config.search_options);
+    Ok(printer.print(&searcher, path, reader, out)?)
+}
 
 pub struct Config {
-    query: String,
-    filename: String,
+    matcher: Matcher,
+    walker: Walker,
+    // The files and directories to search, `-` for stdin.
+    paths: Vec<String>,
+    // Search compressed files decompressed (`-z`).
+    search_zip: bool,
+    // Memory-map big files (`--mmap`).
+    mmap: bool,
+    search_options: SearchOptions,
+    mode: Mode,
+    // Prefix lines with their line number (`-n`).
+    line_number: bool,
+    // Prefix lines with their byte offset (`-b`).
+    byte_offset: bool,
+    // Prefix lines with the filename (`-H`) or not (`-h`). By default only
+    // when searching more than one file or a directory.
+    with_filename: Option<bool>,
+    // The number of files searched at once (`-j`).
+    threads: usize,
+    sort: Sort,
+    color: ColorChoice,
+    // Replace the matches in the printed lines (`--replace`).
+    replace: Option<String>,
+    // Replace the matches in the files instead of printing them
+    // (`--in-place`).
+    rewrite: Option<Rewrite>,
 }
 
+// When to color the output (`--color`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColorChoice {
+    // Only on a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+// The order in which the files are printed (`--sort`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Sort {
+    // By path, the order of the directory walk.
+    Path,
+    // As soon as they are searched.
+    None,
+}
+
+const USAGE: &str = "usage: minigrep [OPTION]... PATTERN [PATH]...
+       minigrep [OPTION]... -e PATTERN... [PATH]...";
+
+// The short options and the long options they stand for.
+const SHORT_OPTIONS: &[(char, &str)] = &[
+    ('A', "after-context"),
+    ('B', "before-context"),
+    ('C', "context"),
+    ('E', "extended-regexp"),
+    ('F', "fixed-strings"),
+    ('H', "with-filename"),
+    ('L', "files-without-match"),
+    ('S', "smart-case"),
+    ('T', "type-not"),
+    ('b', "byte-offset"),
+    ('c', "count"),
+    ('e', "regexp"),
+    ('f', "file"),
+    ('g', "glob"),
+    ('h', "no-filename"),
+    ('r', "replace"),
+    ('i', "ignore-case"),
+    ('j', "threads"),
+    ('l', "files-with-matches"),
+    ('m', "max-count"),
+    ('n', "line-number"),
+    ('s', "case-sensitive"),
+    ('t', "type"),
+    ('v', "invert-match"),
+    ('w', "word-regexp"),
+    ('x', "line-regexp"),
+    ('z', "search-zip"),
+];
+
+// The long options without a short form.
+const LONG_OPTIONS: &[&str] = &[
+    "backup",
+    "color",
+    "hidden",
+    "in-place",
+    "json",
+    "mmap",
+    "no-ignore",
+    "sort",
+];
+
+// The options that take a value.
+const VALUE_OPTIONS: &[&str] = &[
+    "after-context",
+    "backup",
+    "before-context",
+    "color",
+    "context",
+    "file",
+    "glob",
+    "max-count",
+    "regexp",
+    "replace",
+    "sort",
+    "threads",
+    "type",
+    "type-not",
+];
+
+// An option by its long name, with its value if it takes one.
+type ParsedOption = (&'static str, Option<String>);
+
 impl Config {
-    pub fn parse_from_env(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("need moar args");
+    // Parse the command line. Setting the `CASE_INSENSITIVE` environment
+    // variable makes the search case-insensitive unless an option says
+    // otherwise.
+    pub fn parse_from_env(args: &[String]) -> Result<Config, String> {
+        let case = if env::var_os("CASE_INSENSITIVE").is_some() {
+            Case::Insensitive
+        } else {
+            Case::Sensitive
+        };
+        Config::parse(args, case)
+    }
+
+    fn parse(args: &[String], case: Case) -> Result<Config, String> {
+        let (options, mut positional) = parse_options(args)?;
+        let mut match_options = MatchOptions {
+            case,
+            ..MatchOptions::default()
+        };
+        let mut walk_options = WalkOptions::default();
+        let mut patterns = Vec::new();
+        let mut patterns_given = false;
+        let mut search_options = SearchOptions::default();
+        // `-A` and `-B` take precedence over `-C` wherever they are
+        let (mut before, mut after, mut context) = (None, None, 0);
+        let mut mode = Mode::Lines;
+        let mut line_number = false;
+        let mut byte_offset = false;
+        let mut with_filename = None;
+        let mut threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let mut sort = Sort::Path;
+        let mut color = ColorChoice::Auto;
+        let mut replace = None;
+        let mut in_place = false;
+        let mut backup = None;
+        let mut search_zip = false;
+        let mut mmap = false;
+        for (name, value) in options {
+            let value = value.unwrap_or_default();
+            match name {
+                "extended-regexp" => match_options.syntax = Syntax::Regex,
+                "fixed-strings" => match_options.syntax = Syntax::Fixed,
+                "word-regexp" => match_options.word = true,
+                "line-regexp" => match_options.line = true,
+                "invert-match" => match_options.invert = true,
+                "ignore-case" => match_options.case = Case::Insensitive,
+                "case-sensitive" => match_options.case = Case::Sensitive,
+                "smart-case" => match_options.case = Case::Smart,
+                "regexp" => {
+                    patterns.push(value);
+                    patterns_given = true;
+                }
+                "file" => {
+                    let contents =
+                        fs::read_to_string(&value).map_err(|e| format!("{}: {}", value, e))?;
+                    patterns.extend(contents.lines().map(String::from));
+                    patterns_given = true;
+                }
+                "glob" => walk_options.globs.push(value),
+                "type" => walk_options.types.push(value),
+                "type-not" => walk_options.types_not.push(value),
+                "hidden" => walk_options.hidden = true,
+                "no-ignore" => walk_options.no_ignore = true,
+                "search-zip" => search_zip = true,
+                "mmap" => mmap = true,
+                "threads" => {
+                    threads = match value.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("invalid number of threads: {}", value)),
+                    }
+                }
+                "sort" => {
+                    sort = match value.as_str() {
+                        "path" => Sort::Path,
+                        "none" => Sort::None,
+                        _ => return Err(format!("invalid sort order: {}", value)),
+                    }
+                }
+                "after-context" => after = Some(number("context length", &value)?),
+                "before-context" => before = Some(number("context length", &value)?),
+                "context" => context = number("context length", &value)?,
+                "max-count" => search_options.max_count = Some(number("max count", &value)?),
+                "count" => mode = Mode::Count,
+                "files-with-matches" => mode = Mode::FilesWithMatches,
+                "files-without-match" => mode = Mode::FilesWithoutMatch,
+                "json" => mode = Mode::Json,
+                "replace" => replace = Some(value),
+                "in-place" => in_place = true,
+                "backup" => backup = Some(value),
+                "color" => {
+                    color = match value.as_str() {
+                        "auto" => ColorChoice::Auto,
+                        "always" => ColorChoice::Always,
+                        "never" => ColorChoice::Never,
+                        _ => return Err(format!("invalid color choice: {}", value)),
+                    }
+                }
+                "line-number" => line_number = true,
+                "byte-offset" => byte_offset = true,
+                "with-filename" => with_filename = Some(true),
+                "no-filename" => with_filename = Some(false),
+                _ => unreachable!("option {} is not handled", name),
+            }
+        }
+        if !patterns_given {
+            if positional.is_empty() {
+                return Err(USAGE.to_string());
+            }
+            patterns.push(positional.remove(0));
+        }
+        if positional.is_empty() {
+            positional.push("-".to_string());
+        }
+        if in_place && search_zip {
+            return Err("--in-place can't rewrite compressed files".to_string());
+        }
+        let rewrite = match (in_place, &replace) {
+            (true, Some(template)) => Some(Rewrite::new(
+                template.clone(),
+                search_options.max_count,
+                backup,
+            )),
+            (true, None) => return Err("--in-place requires --replace".to_string()),
+            (false, _) if backup.is_some() => {
+                return Err("--backup requires --in-place".to_string())
+            }
+            (false, _) => None,
+        };
+        search_options.before = before.unwrap_or(context);
+        search_options.after = after.unwrap_or(context);
+        let matcher = Matcher::new(&patterns, &match_options).map_err(|e| e.to_string())?;
+        let walker = Walker::new(&walk_options).map_err(|e| e.to_string())?;
+        Ok(Config {
+            matcher,
+            walker,
+            paths: positional,
+            search_zip,
+            mmap,
+            search_options,
+            mode,
+            line_number,
+            byte_offset,
+            with_filename,
+            threads,
+            sort,
+            color,
+            replace,
+            rewrite,
+        })
+    }
+
+    // Whether to color output going to a terminal or not.
+    fn color(&self, terminal: bool) -> bool {
+        match self.color {
+            ColorChoice::Auto => terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
         }
-        let query = args[1].clone();
-        let filename = args[2].clone();
-        Ok(Config { query, filename })
     }
 }
 
+// Parse the value of an option that takes a number, described as `what` in
+// the error.
+fn number<T: FromStr>(what: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid {}: {}", what, value))
+}
+
+// Split the arguments after the program name into options, named by their
+// long name and with their value, and the other arguments. Short options can
+// be combined as in `-nv`, and a value can follow its option directly as in
+// `-efoo` or `--regexp=foo`.
+fn parse_options(args: &[String]) -> Result<(Vec<ParsedOption>, Vec<String>), String> {
+    let mut options = Vec::new();
+    let mut positional = Vec::new();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.cloned());
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            positional.push(arg.clone());
+            continue;
+        }
+        let mut missing_value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("option {} requires a value", name))
+        };
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.find('=') {
+                Some(i) => (&long[..i], Some(long[i + 1..].to_string())),
+                None => (long, None),
+            };
+            let name = SHORT_OPTIONS
+                .iter()
+                .map(|&(_, name)| name)
+                .chain(LONG_OPTIONS.iter().copied())
+                .find(|&known| known == name)
+                .ok_or_else(|| format!("unknown option --{}", name))?;
+            let value = match (VALUE_OPTIONS.contains(&name), value) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(missing_value(arg)?),
+                (false, Some(_)) => return Err(format!("option --{} takes no value", name)),
+                (false, None) => None,
+            };
+            options.push((name, value));
+            continue;
+        }
+        for (i, c) in arg.char_indices().skip(1) {
+            let name = SHORT_OPTIONS
+                .iter()
+                .find(|&&(short, _)| short == c)
+                .map(|&(_, name)| name)
+                .ok_or_else(|| format!("unknown option -{}", c))?;
+            if !VALUE_OPTIONS.contains(&name) {
+                options.push((name, None));
+                continue;
+            }
+            let rest = &arg[i + c.len_utf8()..];
+            let value = if rest.is_empty() {
+                missing_value(&format!("-{}", c))?
+            } else {
+                rest.to_string()
+            };
+            options.push((name, Some(value)));
+            break;
+        }
+    }
+    Ok((options, positional))
+}
+
+fn is_match(query: &str, line: &str) -> bool {
+    line.contains(query)
+}
+
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     let mut results = Vec::new();
     for line in contents.lines() {
-        if line.contains(query) {
+        if is_match(query, line) {
             results.push(line);
         }
     }
     results
 }
 
+// Like `search`, with letters matching in any case. Case is folded for all of
+// Unicode and not just ASCII, so `σίσυφος` finds `ΣΊΣΥΦΟΣ`.
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
+    let options = MatchOptions {
+        case: Case::Insensitive,
+        ..MatchOptions::default()
+    };
+    let matcher = Matcher::new(&[query], &options).expect("escaped query is a valid regex");
+    contents
+        .lines()
+        .filter(|line| matcher.is_match(line))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -45,9 +666,462 @@ mod tests {
 Rust:
 safe, fast, productive.
 Pick three.";
+        assert_eq!(vec!["safe, fast, productive."], search(query, contents));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let query = "rUsT";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        assert_eq!(
+            vec!["Rust:", "Trust me."],
+            search_case_insensitive(query, contents)
+        );
+        assert_eq!(
+            vec!["ΣΊΣΥΦΟΣ"],
+            search_case_insensitive("σίσυφος", "ΣΊΣΥΦΟΣ\nsisyphus")
+        );
+    }
+
+    #[test]
+    fn parse_case() {
+        let parse = |args_: &[&str], case| Config::parse(&args(args_), case).unwrap();
+        assert!(!parse(&["minigrep", "who", "a"], Case::Sensitive)
+            .matcher
+            .is_match("Who"));
+        assert!(parse(&["minigrep", "who", "a"], Case::Insensitive)
+            .matcher
+            .is_match("Who"));
+        assert!(parse(&["minigrep", "-i", "who", "a"], Case::Sensitive)
+            .matcher
+            .is_match("Who"));
+        assert!(!parse(&["minigrep", "-s", "who", "a"], Case::Insensitive)
+            .matcher
+            .is_match("Who"));
+        let smart = parse(
+            &["minigrep", "-S", "-e", "who", "-e", "Are", "a"],
+            Case::Sensitive,
+        );
+        assert!(!smart.matcher.is_match("WHO"));
+        assert!(
+            parse(&["minigrep", "--smart-case", "who", "a"], Case::Sensitive)
+                .matcher
+                .is_match("WHO")
+        );
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn run_args(args_: &[&str]) -> (Status, String, String) {
+        let config = Config::parse_from_env(&args(args_)).unwrap();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let status = run_with(&config, &mut out, &mut err);
+        (
+            status,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn parse_args() {
+        let config = Config::parse_from_env(&args(&["minigrep", "-n", "to", "a", "b"])).unwrap();
+        assert!(config.matcher.is_match("today"));
+        assert_eq!(config.paths, vec!["a", "b"]);
+        assert!(config.line_number);
+        assert!(Config::parse_from_env(&args(&["minigrep"])).is_err());
+        let config = Config::parse_from_env(&args(&["minigrep", "to"])).unwrap();
+        assert_eq!(config.paths, vec!["-"]);
+        assert!(Config::parse_from_env(&args(&["minigrep", "-q", "to", "a"])).is_err());
+        assert!(Config::parse_from_env(&args(&["minigrep", "-E", "(", "a"])).is_err());
+        let config = Config::parse_from_env(&args(&["minigrep", "--", "-n", "a"])).unwrap();
+        assert!(config.matcher.is_match("grep -n"));
+        assert!(!config.line_number);
+    }
+
+    #[test]
+    fn parse_patterns() {
+        let config =
+            Config::parse_from_env(&args(&["minigrep", "-nwe", "you", "--regexp=Who", "a"]))
+                .unwrap();
+        assert!(config.line_number);
+        assert!(config.matcher.is_match("you?"));
+        assert!(config.matcher.is_match("Who"));
+        assert!(!config.matcher.is_match("your"));
+        assert_eq!(config.paths, vec!["a"]);
+        assert!(Config::parse_from_env(&args(&["minigrep", "a", "-e"])).is_err());
+        assert!(
+            Config::parse_from_env(&args(&["minigrep", "--invert-match=x", "a", "b"])).is_err()
+        );
+    }
+
+    #[test]
+    fn run_with_patterns() {
+        let (_, out, _) = run_args(&["minigrep", "-E", "-e", "^Th", "-e", "!$", "words.txt"]);
+        assert_eq!(
+            out,
+            "Then there's a pair of us - don't tell!\n\
+             They'd banish us, you know.\n\
+             How dreary to be somebody!\n\
+             To an admiring bog!\n"
+        );
+        let (_, out, _) = run_args(&["minigrep", "-vx", "-f", "words.txt", "words.txt"]);
+        assert_eq!(out, "");
+        let (_, out, _) = run_args(&["minigrep", "-Fv", "o", "words.txt"]);
+        assert_eq!(out, "\n");
+    }
+
+    #[test]
+    fn run_prints_matches() {
+        let (status, out, err) = run_args(&["minigrep", "-n", "nobody", "words.txt"]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(out, "1:I'm nobody! Who are you?\n2:Are you nobody, too?\n");
+        assert_eq!(err, "");
+    }
+
+    #[test]
+    fn run_prefixes_filenames() {
+        let (status, out, _) = run_args(&["minigrep", "Who", "words.txt", "words.txt"]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(
+            out,
+            "words.txt:I'm nobody! Who are you?\nwords.txt:I'm nobody! Who are you?\n"
+        );
+        let (_, out, _) = run_args(&["minigrep", "-h", "Who", "words.txt", "words.txt"]);
+        assert_eq!(out, "I'm nobody! Who are you?\nI'm nobody! Who are you?\n");
+    }
+
+    #[test]
+    fn run_walks_directories() {
+        let root = std::env::temp_dir().join(format!("minigrep-walk-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "nobody\n").unwrap();
+        fs::write(root.join("binary"), "nobody\0\n").unwrap();
+        let dir = root.display().to_string();
+        let (status, out, _) = run_args(&["minigrep", "nobody", &dir]);
+        assert_eq!(status, Status::Matched);
         assert_eq!(
-            vec!["safe, fast, productive."],
-            search(query, contents)
+            out,
+            format!("{}:nobody\n", root.join("sub/a.txt").display())
         );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn run_in_parallel() {
+        let root = std::env::temp_dir().join(format!("minigrep-parallel-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for i in 0..50 {
+            let lines: Vec<String> = (0..i).map(|j| format!("{} nobody {}", i, j)).collect();
+            fs::write(root.join(format!("{:02}.txt", i)), lines.join("\n")).unwrap();
+        }
+        let dir = root.display().to_string();
+        let (_, sequential, _) = run_args(&["minigrep", "-j", "1", "nobody", &dir]);
+        let (status, parallel, _) = run_args(&["minigrep", "-j8", "nobody", &dir]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(parallel, sequential);
+        assert!(sequential.starts_with(&format!("{}:1 nobody 0\n", root.join("01.txt").display())));
+
+        // unsorted, each file's lines still come together
+        let (_, unsorted, _) = run_args(&["minigrep", "-j8", "--sort=none", "nobody", &dir]);
+        let mut files: Vec<&str> = unsorted
+            .lines()
+            .map(|line| line.split(':').next().unwrap())
+            .collect();
+        files.dedup();
+        assert_eq!(files.len(), 49);
+
+        assert!(Config::parse_from_env(&args(&["minigrep", "-j0", "x", "a"])).is_err());
+        assert!(Config::parse_from_env(&args(&["minigrep", "--sort=size", "x", "a"])).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn run_with_context() {
+        let (status, out, _) =
+            run_args(&["minigrep", "-n", "-A1", "-B", "1", "nobody", "words.txt"]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(
+            out,
+            "1:I'm nobody! Who are you?\n\
+             2:Are you nobody, too?\n\
+             3-Then there's a pair of us - don't tell!\n"
+        );
+        let (_, out, _) = run_args(&["minigrep", "-C1", "-B0", "-b", "How", "words.txt"]);
+        assert_eq!(
+            out,
+            "115:How dreary to be somebody!\n\
+             142:How public, like a frog\n\
+             166-To tell your name the livelong day\n"
+        );
+        let (_, out, _) = run_args(&["minigrep", "-m1", "-A1", "nobody", "words.txt"]);
+        assert_eq!(out, "I'm nobody! Who are you?\nAre you nobody, too?\n");
+        assert!(Config::parse_from_env(&args(&["minigrep", "-A", "x", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn run_counts_and_lists() {
+        let (status, out, _) = run_args(&["minigrep", "-c", "o", "words.txt", "words.txt"]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(out, "words.txt:8\nwords.txt:8\n");
+        let (_, out, _) = run_args(&["minigrep", "-cv", "-m", "1", "o", "words.txt"]);
+        assert_eq!(out, "1\n");
+
+        let (status, out, _) = run_args(&["minigrep", "-l", "nobody", "words.txt", "Cargo.toml"]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(out, "words.txt\n");
+        let (status, out, _) = run_args(&["minigrep", "-L", "nobody", "words.txt", "Cargo.toml"]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(out, "Cargo.toml\n");
+        let (status, out, _) = run_args(&["minigrep", "-L", "nobody", "words.txt"]);
+        assert_eq!(status, Status::NoMatch);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn run_colored() {
+        let (_, out, _) = run_args(&[
+            "minigrep",
+            "--color=always",
+            "-nH",
+            "-A1",
+            "body",
+            "words.txt",
+        ]);
+        assert_eq!(
+            out,
+            "\x1b[35mwords.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m1\x1b[0m\x1b[36m:\x1b[0m\
+             I'm no\x1b[1;31mbody\x1b[0m! Who are you?\n\
+             \x1b[35mwords.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m2\x1b[0m\x1b[36m:\x1b[0m\
+             Are you no\x1b[1;31mbody\x1b[0m, too?\n\
+             \x1b[35mwords.txt\x1b[0m\x1b[36m-\x1b[0m\x1b[32m3\x1b[0m\x1b[36m-\x1b[0m\
+             Then there's a pair of us - don't tell!\n\
+             \x1b[36m--\x1b[0m\n\
+             \x1b[35mwords.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m6\x1b[0m\x1b[36m:\x1b[0m\
+             How dreary to be some\x1b[1;31mbody\x1b[0m!\n\
+             \x1b[35mwords.txt\x1b[0m\x1b[36m-\x1b[0m\x1b[32m7\x1b[0m\x1b[36m-\x1b[0m\
+             How public, like a frog\n"
+        );
+        // auto doesn't color output that isn't a terminal
+        let (_, out, _) = run_args(&["minigrep", "--color=auto", "body", "words.txt"]);
+        assert!(!out.contains('\x1b'));
+        assert!(Config::parse_from_env(&args(&["minigrep", "--color=red", "x", "a"])).is_err());
+    }
+
+    #[test]
+    fn run_json() {
+        let (status, out, _) = run_args(&["minigrep", "--json", "-E", "-A1", "[Nn]o", "words.txt"]);
+        assert_eq!(status, Status::Matched);
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            serde_json::json!({
+                "path": "words.txt",
+                "line_number": 2,
+                "byte_offset": 25,
+                "line": "Are you nobody, too?",
+                "submatches": [{"match": "no", "start": 8, "end": 10}],
+            })
+        );
+        assert_eq!(lines[2]["line_number"], 4);
+        assert_eq!(lines[2]["submatches"][0]["start"], 23);
+    }
+
+    #[test]
+    fn run_replace() {
+        let (status, out, _) = run_args(&[
+            "minigrep",
+            "-n",
+            "-A1",
+            "--color=always",
+            "-E",
+            "--replace=<$1>",
+            r"(\w+)body",
+            "words.txt",
+        ]);
+        assert_eq!(status, Status::Matched);
+        assert!(out.contains("I'm <no>! Who are you?\n"));
+        assert!(out.contains("Are you <no>, too?\n"));
+        assert!(out.contains("Then there's a pair of us - don't tell!\n"));
+        assert!(out.ends_with(
+            "How dreary to be <some>!\n\x1b[32m7\x1b[0m\x1b[36m-\x1b[0mHow public, like a frog\n"
+        ));
+
+        let parse = |args_: &[&str]| Config::parse_from_env(&args(args_));
+        assert!(parse(&["minigrep", "--in-place", "x", "a"]).is_err());
+        assert!(parse(&["minigrep", "-r", "y", "--backup=.bak", "x", "a"]).is_err());
+    }
+
+    #[test]
+    fn run_in_place() {
+        let root = std::env::temp_dir().join(format!("minigrep-in-place-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "color\ncolour\nred color\n").unwrap();
+        fs::write(root.join("b.txt"), "grey\n").unwrap();
+        let dir = root.display().to_string();
+        let (status, out, _) = run_args(&[
+            "minigrep",
+            "--in-place",
+            "--backup",
+            "~",
+            "-r",
+            "colour",
+            "color",
+            &dir,
+        ]);
+        assert_eq!(status, Status::Matched);
+        assert_eq!(out, "");
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt")).unwrap(),
+            "colour\ncolour\nred colour\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt~")).unwrap(),
+            "color\ncolour\nred color\n"
+        );
+        assert!(!root.join("b.txt~").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_in_place_through_link() {
+        let root =
+            std::env::temp_dir().join(format!("minigrep-in-place-link-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("t.txt"), "a\n").unwrap();
+        std::os::unix::fs::symlink("t.txt", root.join("l.txt")).unwrap();
+        let dir = root.display().to_string();
+        let link = root.join("l.txt").display().to_string();
+        for threads in &["-j1", "-j4"] {
+            // the file is found in the directory and named through the link
+            let (status, _, err) = run_args(&[
+                "minigrep",
+                threads,
+                "--in-place",
+                "-r",
+                "aa",
+                "a",
+                &dir,
+                &link,
+            ]);
+            assert_eq!((status, err.as_str()), (Status::Matched, ""));
+            assert_eq!(fs::read_to_string(root.join("t.txt")).unwrap(), "aa\n");
+            fs::write(root.join("t.txt"), "a\n").unwrap();
+        }
+        assert!(fs::symlink_metadata(root.join("l.txt"))
+            .unwrap()
+            .is_symlink());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn run_large_and_compressed_files() {
+        let root = std::env::temp_dir().join(format!("minigrep-large-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut contents = "filler line\n".repeat(200_000).into_bytes();
+        contents.extend_from_slice(b"caf\xe9 nobody\n");
+        fs::write(root.join("large.txt"), &contents).unwrap();
+        let large = root.join("large.txt").display().to_string();
+        for option in &["--mmap", "--sort=path"] {
+            let (status, out, err) = run_args(&["minigrep", "-n", option, "nobody", &large]);
+            assert_eq!((status, err.as_str()), (Status::Matched, ""));
+            assert_eq!(out, "200001:caf\u{fffd} nobody\n");
+        }
+        // only a NUL byte near the start makes a file binary, also when mapped
+        let binary = root.join("binary.txt");
+        fs::write(&binary, [&contents[..], b"\0"].concat()).unwrap();
+        let binary = binary.display().to_string();
+        for option in &["--mmap", "--sort=path"] {
+            let (status, _, _) = run_args(&["minigrep", option, "nobody", &binary]);
+            assert_eq!(status, Status::Matched);
+        }
+        // past the first block, a NUL byte before the match doesn't hide it
+        let mut nul_first = "filler line\n".repeat(5000).into_bytes();
+        nul_first.extend_from_slice(b"bin\0ary\nlate nobody\n");
+        fs::write(root.join("binary.txt"), &nul_first).unwrap();
+        let (status, out, _) = run_args(&["minigrep", "-c", "nobody", &binary]);
+        assert_eq!((status, out.as_str()), (Status::Matched, "1\n"));
+        fs::write(root.join("binary.txt"), [b"\0", &contents[..]].concat()).unwrap();
+        for option in &["--mmap", "--sort=path"] {
+            let (status, _, _) = run_args(&["minigrep", option, "nobody", &binary]);
+            assert_eq!(status, Status::NoMatch);
+        }
+
+        let gzip = Command::new("gzip").arg("-k").arg(&large).status();
+        if gzip.is_ok_and(|status| status.success()) {
+            let compressed = format!("{}.gz", large);
+            let (status, out, _) = run_args(&["minigrep", "-z", "-c", "nobody", &compressed]);
+            assert_eq!(status, Status::Matched);
+            assert_eq!(out, "1\n");
+            // without -z the file is binary
+            let (status, _, _) = run_args(&["minigrep", "nobody", &compressed]);
+            assert_eq!(status, Status::NoMatch);
+            let corrupt = root.join("corrupt.gz");
+            fs::write(&corrupt, "not gzip").unwrap();
+            let corrupt = corrupt.display().to_string();
+            let (status, _, err) = run_args(&["minigrep", "-z", "nobody", &corrupt]);
+            assert_eq!(status, Status::Error);
+            assert!(err.contains("gzip: "));
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    // A writer whose reader has gone, like a pipe to `head` once it exits.
+    struct ClosedPipe {
+        writes: usize,
+    }
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn run_closed_output() {
+        for threads in &["-j1", "-j4"] {
+            let config =
+                Config::parse_from_env(&args(&["minigrep", threads, "o", "words.txt", "src"]))
+                    .unwrap();
+            let mut out = ClosedPipe { writes: 0 };
+            let mut err = Vec::new();
+            let status = run_with(&config, &mut out, &mut err);
+            assert_eq!(status, Status::Matched);
+            assert_eq!(String::from_utf8(err).unwrap(), "");
+            // the search stops at the first line it fails to print
+            assert_eq!(out.writes, 1);
+        }
+    }
+
+    #[test]
+    fn run_status() {
+        let (status, out, _) = run_args(&["minigrep", "zebra", "words.txt"]);
+        assert_eq!(status, Status::NoMatch);
+        assert_eq!(out, "");
+        let (status, out, err) = run_args(&["minigrep", "Who", "missing.txt", "words.txt"]);
+        assert_eq!(status, Status::Error);
+        assert_eq!(out, "words.txt:I'm nobody! Who are you?\n");
+        assert!(err.starts_with("minigrep: missing.txt: "));
     }
 }
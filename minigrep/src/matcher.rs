@@ -0,0 +1,264 @@
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::ops::Range;
+
+// How patterns are interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    // Patterns are literal strings (`-F`).
+    Fixed,
+    // Patterns are regular expressions (`-E`).
+    Regex,
+}
+
+// How letter case is compared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Case {
+    Sensitive,
+    // Letters match in any case, with Unicode case folding (`-i`).
+    Insensitive,
+    // Insensitive unless a pattern contains an uppercase letter (`-S`).
+    Smart,
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchOptions {
+    pub syntax: Syntax,
+    pub case: Case,
+    // Only match whole words (`-w`).
+    pub word: bool,
+    // Only match whole lines (`-x`).
+    pub line: bool,
+    // Select the lines that don't match (`-v`).
+    pub invert: bool,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            syntax: Syntax::Fixed,
+            case: Case::Sensitive,
+            word: false,
+            line: false,
+            invert: false,
+        }
+    }
+}
+
+// A set of patterns compiled into a single regular expression, so each line is
+// scanned once however many patterns there are.
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    // `None` if there are no patterns, which matches no line.
+    regex: Option<Regex>,
+    invert: bool,
+}
+
+impl Matcher {
+    pub fn new<S: AsRef<str>>(
+        patterns: &[S],
+        options: &MatchOptions,
+    ) -> Result<Matcher, regex::Error> {
+        if patterns.is_empty() {
+            return Ok(Matcher {
+                regex: None,
+                invert: options.invert,
+            });
+        }
+        let alternatives: Vec<String> = patterns
+            .iter()
+            .map(|pattern| match options.syntax {
+                Syntax::Fixed => regex::escape(pattern.as_ref()),
+                Syntax::Regex => format!("(?:{})", pattern.as_ref()),
+            })
+            .collect();
+        let alternation = alternatives.join("|");
+        let pattern = if options.line {
+            format!("^(?:{})$", alternation)
+        } else if options.word {
+            // a word match must not be next to another word character, even
+            // if the pattern itself starts or ends with a non-word character
+            format!(r"\b{{start-half}}(?:{})\b{{end-half}}", alternation)
+        } else {
+            alternation
+        };
+        let case_insensitive = match options.case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !patterns
+                .iter()
+                .any(|pattern| has_uppercase(pattern.as_ref(), options.syntax)),
+        };
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        Ok(Matcher {
+            regex: Some(regex),
+            invert: options.invert,
+        })
+    }
+
+    // Whether `line` is selected, taking `-v` into account.
+    pub fn is_match(&self, line: &str) -> bool {
+        let found = match &self.regex {
+            Some(regex) => regex.is_match(line),
+            None => false,
+        };
+        found != self.invert
+    }
+
+    // The byte ranges of the non-empty matches in `line`, for highlighting.
+    // Lines selected by `-v` have no matches.
+    pub fn spans(&self, line: &str) -> Vec<Range<usize>> {
+        match &self.regex {
+            Some(regex) if !self.invert => regex
+                .find_iter(line)
+                .map(|m| m.range())
+                .filter(|range| !range.is_empty())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Replace the matches in `line` by `template`, in which `$0` stands for
+    // the match and `$1` or `${name}` for a group of the patterns. The groups
+    // of several patterns are numbered on from one pattern to the next.
+    // Lines selected by `-v` have no matches to replace.
+    pub fn replace<'a>(&self, line: &'a str, template: &str) -> Cow<'a, str> {
+        match &self.regex {
+            Some(regex) if !self.invert => regex.replace_all(line, template),
+            _ => Cow::Borrowed(line),
+        }
+    }
+}
+
+// Whether `pattern` has an uppercase letter, ignoring the escapes of a regular
+// expression such as `\W` or `\S`.
+fn has_uppercase(pattern: &str, syntax: Syntax) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' && syntax == Syntax::Regex {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[&str], options: MatchOptions) -> Matcher {
+        Matcher::new(patterns, &options).unwrap()
+    }
+
+    #[test]
+    fn fixed_strings() {
+        let m = matcher(&["a.c", "x"], MatchOptions::default());
+        assert!(m.is_match("1 a.c 2"));
+        assert!(!m.is_match("abc"));
+        assert_eq!(m.spans("a.c x a.c"), vec![0..3, 4..5, 6..9]);
+    }
+
+    #[test]
+    fn regex_patterns() {
+        let options = MatchOptions {
+            syntax: Syntax::Regex,
+            ..MatchOptions::default()
+        };
+        let m = matcher(&["a.c", r"\d+"], options);
+        assert!(m.is_match("abc"));
+        assert_eq!(m.spans("abc 42"), vec![0..3, 4..6]);
+        assert!(Matcher::new(
+            &["("],
+            &MatchOptions {
+                syntax: Syntax::Regex,
+                ..MatchOptions::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn whole_words_and_lines() {
+        let words = MatchOptions {
+            word: true,
+            ..MatchOptions::default()
+        };
+        let m = matcher(&["you"], words.clone());
+        assert!(m.is_match("Who are you?"));
+        assert!(!m.is_match("your name"));
+        let m = matcher(&["-n"], words);
+        assert!(m.is_match("grep -n x"));
+        assert!(!m.is_match("grep a-n x"));
+
+        let lines = MatchOptions {
+            line: true,
+            ..MatchOptions::default()
+        };
+        let m = matcher(&["Pick three.", "Rust:"], lines);
+        assert!(m.is_match("Rust:"));
+        assert!(!m.is_match("Rust: safe"));
+    }
+
+    #[test]
+    fn case_folding() {
+        let options = |case| MatchOptions {
+            case,
+            ..MatchOptions::default()
+        };
+        assert!(!matcher(&["rust"], options(Case::Sensitive)).is_match("Rust:"));
+        let m = matcher(&["rust", "straße"], options(Case::Insensitive));
+        assert!(m.is_match("Rust:"));
+        assert!(m.is_match("STRAẞE"));
+        assert!(matcher(&["ΣΊΣΥΦΟΣ"], options(Case::Insensitive)).is_match("σίσυφος"));
+
+        assert!(matcher(&["rust"], options(Case::Smart)).is_match("Rust:"));
+        assert!(!matcher(&["Rust"], options(Case::Smart)).is_match("trust"));
+        let regex = MatchOptions {
+            syntax: Syntax::Regex,
+            ..options(Case::Smart)
+        };
+        assert!(matcher(&[r"r\Sst"], regex).is_match("RUST"));
+    }
+
+    #[test]
+    fn replace() {
+        let options = MatchOptions {
+            syntax: Syntax::Regex,
+            ..MatchOptions::default()
+        };
+        let m = matcher(&[r"(\w+)@(?P<host>\w+)"], options.clone());
+        assert_eq!(
+            m.replace("joe@home, ann@work", "$1 at ${host}"),
+            "joe at home, ann at work"
+        );
+        assert_eq!(m.replace("nobody", "$1"), "nobody");
+        let m = matcher(&["a.c"], MatchOptions::default());
+        assert_eq!(m.replace("abc a.c", "[$0]"), "abc [a.c]");
+        let inverted = MatchOptions {
+            invert: true,
+            ..options
+        };
+        assert_eq!(matcher(&["b"], inverted).replace("abc", "x"), "abc");
+    }
+
+    #[test]
+    fn inverted() {
+        let options = MatchOptions {
+            invert: true,
+            ..MatchOptions::default()
+        };
+        let m = matcher(&["duct"], options.clone());
+        assert!(!m.is_match("productive"));
+        assert!(m.is_match("Pick three."));
+        assert!(m.spans("Pick three.").is_empty());
+
+        // without patterns nothing matches, so `-v` selects every line
+        let none: &[&str] = &[];
+        assert!(!matcher(none, MatchOptions::default()).is_match("x"));
+        assert!(matcher(none, options).is_match("x"));
+    }
+}
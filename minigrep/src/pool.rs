@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+// Call `job` on every item on `threads` worker threads and pass the results to
+// `done` on the calling thread. With `ordered` the results come in the order
+// of the items, otherwise as soon as they are ready.
+//
+// Like `restaurant::ThreadPool`, the workers take the items from a channel
+// they share, so a slow item doesn't hold up the others. The threads are
+// scoped, so `job` can borrow from the caller.
+pub fn for_each<I, T, R, F, D>(items: I, threads: usize, ordered: bool, job: F, mut done: D)
+where
+    I: Iterator<Item = T>,
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+    D: FnMut(R),
+{
+    assert!(threads > 0);
+    if threads == 1 {
+        items.map(job).for_each(done);
+        return;
+    }
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (result_sender, results) = mpsc::channel();
+        for _ in 0..threads {
+            let receiver = Arc::clone(&receiver);
+            let result_sender = result_sender.clone();
+            let job = &job;
+            scope.spawn(move || loop {
+                // the lock is released before the job runs
+                let item = receiver.lock().unwrap().recv();
+                match item {
+                    Ok((index, item)) => {
+                        if result_sender.send((index, job(item))).is_err() {
+                            break;
+                        }
+                    }
+                    // all the items are sent
+                    Err(_) => break,
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut results_in_order = InOrder::new(ordered);
+        for item in items.enumerate() {
+            sender.send(item).unwrap();
+            // pass on what is ready while the items are still coming
+            while let Ok((index, result)) = results.try_recv() {
+                results_in_order.push(index, result, &mut done);
+            }
+        }
+        drop(sender);
+        for (index, result) in results {
+            results_in_order.push(index, result, &mut done);
+        }
+    });
+}
+
+// Holds back the results that are ahead of the next one in order.
+struct InOrder<R> {
+    ordered: bool,
+    next: usize,
+    pending: BTreeMap<usize, R>,
+}
+
+impl<R> InOrder<R> {
+    fn new(ordered: bool) -> InOrder<R> {
+        InOrder {
+            ordered,
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    fn push<D: FnMut(R)>(&mut self, index: usize, result: R, done: &mut D) {
+        if !self.ordered {
+            done(result);
+            return;
+        }
+        self.pending.insert(index, result);
+        while let Some(result) = self.pending.remove(&self.next) {
+            done(result);
+            self.next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn run(threads: usize, ordered: bool) -> Vec<u64> {
+        let mut results = Vec::new();
+        // the early items take longest, so they finish last
+        let job = |n: u64| {
+            thread::sleep(Duration::from_millis(20 - n * 2));
+            n
+        };
+        for_each(0..10, threads, ordered, job, |n| results.push(n));
+        results
+    }
+
+    #[test]
+    fn ordered() {
+        let expected: Vec<u64> = (0..10).collect();
+        assert_eq!(run(1, true), expected);
+        assert_eq!(run(4, true), expected);
+    }
+
+    #[test]
+    fn unordered() {
+        let mut results = run(10, false);
+        assert_ne!(results, (0..10).collect::<Vec<u64>>());
+        results.sort_unstable();
+        assert_eq!(results, (0..10).collect::<Vec<u64>>());
+    }
+}
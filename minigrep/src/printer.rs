@@ -0,0 +1,183 @@
+use crate::searcher::{Event, Line, Searcher};
+use serde_json::json;
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+// What is printed for each file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // The matching lines with their context.
+    Lines,
+    // The number of matching lines (`-c`).
+    Count,
+    // The name of the file if it has a match (`-l`).
+    FilesWithMatches,
+    // The name of the file if it has no match (`-L`).
+    FilesWithoutMatch,
+    // A JSON object on its own line for each matching line (`--json`).
+    Json,
+}
+
+// The SGR codes of the colors GNU grep uses by default.
+const FILENAME_COLOR: &str = "35";
+const NUMBER_COLOR: &str = "32";
+const SEPARATOR_COLOR: &str = "36";
+const MATCH_COLOR: &str = "1;31";
+
+// Prints the events of a search like grep, for example `file:3:text` for a
+// match and `file-4-text` for a context line.
+#[derive(Clone, Debug)]
+pub struct Printer {
+    pub mode: Mode,
+    pub with_filename: bool,
+    // Prefix lines with their line number (`-n`).
+    pub line_number: bool,
+    // Prefix lines with their byte offset in the file (`-b`).
+    pub byte_offset: bool,
+    // Color the matches, filenames and prefixes with ANSI escapes.
+    pub color: bool,
+    // Print matching lines with their matches replaced by this template
+    // (`--replace`).
+    pub replace: Option<String>,
+}
+
+impl Printer {
+    // Search `reader`, the contents of the file at `path`, and print the
+    // results. Return whether the file counts as a match for the exit
+    // status: with `-L` that is when its name is printed.
+    pub fn print<R: BufRead, W: Write>(
+        &self,
+        searcher: &Searcher,
+        path: &Path,
+        reader: R,
+        out: &mut W,
+    ) -> io::Result<bool> {
+        match self.mode {
+            Mode::Lines => {
+                let count = searcher.search(reader, |event| {
+                    match event {
+                        Event::Match(line) => self.line(searcher, path, line, ':', out)?,
+                        Event::Context(line) => self.line(searcher, path, line, '-', out)?,
+                        Event::Separator => {
+                            self.paint(out, SEPARATOR_COLOR, "--")?;
+                            writeln!(out)?;
+                        }
+                    }
+                    Ok(true)
+                })?;
+                Ok(count > 0)
+            }
+            Mode::Count => {
+                let count = searcher.search(reader, |_| Ok(true))?;
+                if self.with_filename {
+                    self.paint(out, FILENAME_COLOR, path.display())?;
+                    self.paint(out, SEPARATOR_COLOR, ':')?;
+                }
+                writeln!(out, "{}", count)?;
+                Ok(count > 0)
+            }
+            Mode::FilesWithMatches | Mode::FilesWithoutMatch => {
+                // the first match decides
+                let count =
+                    searcher.search(reader, |event| Ok(!matches!(event, Event::Match(_))))?;
+                let listed = (count > 0) == (self.mode == Mode::FilesWithMatches);
+                if listed {
+                    self.paint(out, FILENAME_COLOR, path.display())?;
+                    writeln!(out)?;
+                }
+                Ok(listed)
+            }
+            Mode::Json => {
+                let count = searcher.search(reader, |event| {
+                    if let Event::Match(line) = event {
+                        self.json(searcher, path, line, out)?;
+                    }
+                    Ok(true)
+                })?;
+                Ok(count > 0)
+            }
+        }
+    }
+
+    fn line<W: Write>(
+        &self,
+        searcher: &Searcher,
+        path: &Path,
+        line: &Line,
+        separator: char,
+        out: &mut W,
+    ) -> io::Result<()> {
+        if self.with_filename {
+            self.paint(out, FILENAME_COLOR, path.display())?;
+            self.paint(out, SEPARATOR_COLOR, separator)?;
+        }
+        if self.line_number {
+            self.paint(out, NUMBER_COLOR, line.number)?;
+            self.paint(out, SEPARATOR_COLOR, separator)?;
+        }
+        if self.byte_offset {
+            self.paint(out, NUMBER_COLOR, line.offset)?;
+            self.paint(out, SEPARATOR_COLOR, separator)?;
+        }
+        if separator != ':' {
+            return writeln!(out, "{}", line.text);
+        }
+        if let Some(template) = &self.replace {
+            return writeln!(out, "{}", searcher.matcher().replace(&line.text, template));
+        }
+        if !self.color {
+            return writeln!(out, "{}", line.text);
+        }
+        let mut end = 0;
+        for span in searcher.matcher().spans(&line.text) {
+            write!(out, "{}", &line.text[end..span.start])?;
+            self.paint(out, MATCH_COLOR, &line.text[span.clone()])?;
+            end = span.end;
+        }
+        writeln!(out, "{}", &line.text[end..])
+    }
+
+    // Print a match as JSON, for example
+    //
+    //     {"byte_offset":42,"line":"a match","line_number":3,"path":"a.txt",
+    //      "submatches":[{"end":7,"match":"match","start":2}]}
+    //
+    // on a single line. The submatch ranges are byte offsets in the line.
+    fn json<W: Write>(
+        &self,
+        searcher: &Searcher,
+        path: &Path,
+        line: &Line,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let submatches: Vec<_> = searcher
+            .matcher()
+            .spans(&line.text)
+            .into_iter()
+            .map(|span| {
+                json!({
+                    "match": &line.text[span.clone()],
+                    "start": span.start,
+                    "end": span.end,
+                })
+            })
+            .collect();
+        let object = json!({
+            "path": path.to_string_lossy(),
+            "line_number": line.number,
+            "byte_offset": line.offset,
+            "line": line.text,
+            "submatches": submatches,
+        });
+        writeln!(out, "{}", object)
+    }
+
+    fn paint<W: Write, T: Display>(&self, out: &mut W, color: &str, text: T) -> io::Result<()> {
+        if self.color {
+            write!(out, "\x1b[{}m{}\x1b[0m", color, text)
+        } else {
+            write!(out, "{}", text)
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::matcher::Matcher;
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+
+// How to rewrite files with `--in-place`.
+#[derive(Clone, Debug)]
+pub struct Rewrite {
+    // What the matches are replaced by (`--replace`).
+    pub template: String,
+    // Only replace in this many matching lines (`-m`).
+    pub max_count: Option<u64>,
+    // Keep the original file under its name with this suffix (`--backup`).
+    pub backup: Option<String>,
+    // The files rewritten so far, so a file named more than once, such as
+    // through a link, has the template applied once.
+    rewritten: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl Rewrite {
+    pub fn new(template: String, max_count: Option<u64>, backup: Option<String>) -> Rewrite {
+        Rewrite {
+            template,
+            max_count,
+            backup,
+            rewritten: Arc::default(),
+        }
+    }
+
+    // Replace the matches in the file at `path` and return the number of
+    // matching lines. The new contents are written to a temporary file next
+    // to it, which is renamed over the file, so the file is replaced whole or
+    // not at all. Files without matches or with NUL bytes are left alone.
+    //
+    // A symbolic link is followed: the file it points to is rewritten, and
+    // its backup is made next to it, while the link stays a link. A file
+    // already rewritten is left alone and has no matching lines.
+    pub fn rewrite(&self, matcher: &Matcher, path: &Path) -> io::Result<u64> {
+        let path = &fs::canonicalize(path)?;
+        if !self.rewritten.lock().unwrap().insert(path.clone()) {
+            return Ok(0);
+        }
+        let contents = fs::read_to_string(path)?;
+        if contents.contains('\0') {
+            return Ok(0);
+        }
+        let max_count = self.max_count.unwrap_or(u64::MAX);
+        let mut count = 0;
+        let mut rewritten = String::with_capacity(contents.len());
+        for line in contents.split_inclusive('\n') {
+            let text = line.strip_suffix('\n').unwrap_or(line);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            if count < max_count && matcher.is_match(text) {
+                count += 1;
+                rewritten.push_str(&matcher.replace(text, &self.template));
+                rewritten.push_str(&line[text.len()..]);
+            } else {
+                rewritten.push_str(line);
+            }
+        }
+        if rewritten == contents {
+            return Ok(count);
+        }
+
+        let temp = sibling(path, |name| {
+            let mut temp = OsString::from(".");
+            temp.push(name);
+            temp.push(format!(".minigrep-{}.tmp", process::id()));
+            temp
+        });
+        if let Err(e) = write_file(&temp, rewritten.as_bytes(), path) {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+        if let Some(suffix) = &self.backup {
+            let backup = sibling(path, |name| {
+                let mut backup = name.to_os_string();
+                backup.push(suffix);
+                backup
+            });
+            if let Err(e) = fs::copy(path, backup) {
+                let _ = fs::remove_file(&temp);
+                return Err(e);
+            }
+        }
+        fs::rename(&temp, path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })?;
+        Ok(count)
+    }
+}
+
+// The path of a file next to `path`, named after its file name.
+fn sibling<F: FnOnce(&OsStr) -> OsString>(path: &Path, name: F) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default();
+    path.with_file_name(name(file_name))
+}
+
+// Write `contents` to the new file `path`, with the owner, group and
+// permissions of `like`.
+fn write_file(path: &Path, contents: &[u8], like: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(like)?;
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    // before the permissions, as changing the owner can clear setuid bits
+    keep_owner(path, &metadata);
+    fs::set_permissions(path, metadata.permissions())
+}
+
+// Give the file at `path` the owner and group in `metadata`, as far as we are
+// allowed to. Like with `sed -i`, a file of another user we can write to
+// becomes ours.
+#[cfg(unix)]
+fn keep_owner(path: &Path, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{chown, MetadataExt};
+    if chown(path, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = chown(path, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_: &Path, _: &fs::Metadata) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::{MatchOptions, Syntax};
+
+    fn temp_file(test: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("minigrep-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn rewrite(template: &str, max_count: Option<u64>, backup: Option<&str>) -> Rewrite {
+        Rewrite::new(template.to_string(), max_count, backup.map(String::from))
+    }
+
+    #[test]
+    fn rewrites_matching_lines() {
+        let path = temp_file("rewrite", "let a = 1;\r\nlet bb = 2;\nconst c = 3;");
+        let options = MatchOptions {
+            syntax: Syntax::Regex,
+            ..MatchOptions::default()
+        };
+        let matcher = Matcher::new(&[r"let (\w+)"], &options).unwrap();
+        let count = rewrite("var $1", None, Some(".bak"))
+            .rewrite(&matcher, &path)
+            .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "var a = 1;\r\nvar bb = 2;\nconst c = 3;"
+        );
+        assert_eq!(
+            fs::read_to_string(path.with_file_name("file.txt.bak")).unwrap(),
+            "let a = 1;\r\nlet bb = 2;\nconst c = 3;"
+        );
+        // only the file and its backup are left
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 2);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn max_count_and_no_matches() {
+        let path = temp_file("rewrite-max", "x\nx\n");
+        let matcher = Matcher::new(&["x"], &MatchOptions::default()).unwrap();
+        assert_eq!(
+            rewrite("y", Some(1), None)
+                .rewrite(&matcher, &path)
+                .unwrap(),
+            1
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "y\nx\n");
+        let matcher = Matcher::new(&["z"], &MatchOptions::default()).unwrap();
+        assert_eq!(
+            rewrite("y", None, Some(".bak"))
+                .rewrite(&matcher, &path)
+                .unwrap(),
+            0
+        );
+        assert!(!path.with_file_name("file.txt.bak").exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks() {
+        let path = temp_file(
+            "rewrite-symlink",
+            "x
+",
+        );
+        let dir = path.parent().unwrap();
+        fs::create_dir(dir.join("links")).unwrap();
+        let link = dir.join("links/link.txt");
+        std::os::unix::fs::symlink("../file.txt", &link).unwrap();
+        let matcher = Matcher::new(&["x"], &MatchOptions::default()).unwrap();
+        assert_eq!(
+            rewrite("y", None, Some(".bak"))
+                .rewrite(&matcher, &link)
+                .unwrap(),
+            1
+        );
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "y\n");
+        // the backup and the temporary file are next to the file
+        assert_eq!(fs::read_to_string(dir.join("file.txt.bak")).unwrap(), "x\n");
+        assert_eq!(fs::read_dir(dir.join("links")).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use crate::matcher::Matcher;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+// A line of the file being searched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    // Line numbers start at 1.
+    pub number: u64,
+    // The byte offset of the start of the line in the file.
+    pub offset: u64,
+    // The line without its line terminator. Invalid UTF-8 is replaced by
+    // U+FFFD, so the text can be longer than the line in the file.
+    pub text: String,
+}
+
+// What the searcher finds, in the order of the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    // A selected line.
+    Match(&'a Line),
+    // A line around a selected one (`-A`, `-B`, `-C`).
+    Context(&'a Line),
+    // Separates lines that don't follow each other in the file, when there
+    // are context lines.
+    Separator,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    // The number of context lines before (`-B`) and after (`-A`) each match.
+    pub before: usize,
+    pub after: usize,
+    // Stop after this many matches (`-m`).
+    pub max_count: Option<u64>,
+}
+
+pub struct Searcher<'m> {
+    matcher: &'m Matcher,
+    options: SearchOptions,
+}
+
+impl<'m> Searcher<'m> {
+    pub fn new(matcher: &'m Matcher, options: &SearchOptions) -> Searcher<'m> {
+        Searcher {
+            matcher,
+            options: options.clone(),
+        }
+    }
+
+    pub fn matcher(&self) -> &Matcher {
+        self.matcher
+    }
+
+    // Read `reader` line by line and pass what is found to `sink`, which
+    // returns whether to go on. Return the number of matches.
+    //
+    // Lines are read one at a time into a buffer that is reused, so large
+    // files are scanned in constant memory. Whether a file is binary is up to
+    // the caller, a NUL byte further on doesn't end the search.
+    pub fn search<R, F>(&self, mut reader: R, mut sink: F) -> io::Result<u64>
+    where
+        R: BufRead,
+        F: FnMut(Event) -> io::Result<bool>,
+    {
+        let max_count = self.options.max_count.unwrap_or(u64::MAX);
+        let context = self.options.before > 0 || self.options.after > 0;
+        let mut before: VecDeque<Line> = VecDeque::with_capacity(self.options.before);
+        let mut after_left = 0;
+        let mut last_emitted: Option<u64> = None;
+        let mut count = 0;
+        let mut offset = 0;
+        let mut number = 0;
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            let len = reader.read_until(b'\n', &mut buf)?;
+            if len == 0 || (count >= max_count && after_left == 0) {
+                break;
+            }
+            number += 1;
+            let line = Line {
+                number,
+                offset,
+                text: String::from_utf8_lossy(trim_line_terminator(&buf)).into_owned(),
+            };
+            offset += len as u64;
+
+            if count < max_count && self.matcher.is_match(&line.text) {
+                let first = number - before.len() as u64;
+                let gap = last_emitted.is_some_and(|last| first > last + 1);
+                if context && gap && !sink(Event::Separator)? {
+                    break;
+                }
+                for context_line in before.drain(..) {
+                    if !sink(Event::Context(&context_line))? {
+                        return Ok(count);
+                    }
+                }
+                count += 1;
+                last_emitted = Some(number);
+                after_left = self.options.after;
+                if !sink(Event::Match(&line))? {
+                    break;
+                }
+            } else if after_left > 0 {
+                after_left -= 1;
+                last_emitted = Some(number);
+                if !sink(Event::Context(&line))? {
+                    break;
+                }
+            } else if self.options.before > 0 {
+                if before.len() == self.options.before {
+                    before.pop_front();
+                }
+                before.push_back(line);
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn trim_line_terminator(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatchOptions;
+
+    // The events as text: `n:line` for matches, `n-line` for context and `--`
+    // for separators.
+    fn events(pattern: &str, options: SearchOptions, contents: &str) -> Vec<String> {
+        let matcher = Matcher::new(&[pattern], &MatchOptions::default()).unwrap();
+        let mut events = Vec::new();
+        Searcher::new(&matcher, &options)
+            .search(contents.as_bytes(), |event| {
+                events.push(match event {
+                    Event::Match(line) => format!("{}:{}", line.number, line.text),
+                    Event::Context(line) => format!("{}-{}", line.number, line.text),
+                    Event::Separator => "--".to_string(),
+                });
+                Ok(true)
+            })
+            .unwrap();
+        events
+    }
+
+    const CONTENTS: &str = "a\nb\nx1\nc\nd\ne\nf\nx2\nx3\ng\n";
+
+    #[test]
+    fn matches() {
+        assert_eq!(
+            events("x", SearchOptions::default(), CONTENTS),
+            vec!["3:x1", "8:x2", "9:x3"]
+        );
+        assert!(events("z", SearchOptions::default(), CONTENTS).is_empty());
+    }
+
+    #[test]
+    fn context() {
+        let options = SearchOptions {
+            before: 1,
+            after: 1,
+            max_count: None,
+        };
+        assert_eq!(
+            events("x", options, CONTENTS),
+            vec!["2-b", "3:x1", "4-c", "--", "7-f", "8:x2", "9:x3", "10-g"]
+        );
+        // groups that touch are not separated
+        let options = SearchOptions {
+            before: 0,
+            after: 4,
+            max_count: None,
+        };
+        assert_eq!(
+            events("x", options, CONTENTS),
+            vec!["3:x1", "4-c", "5-d", "6-e", "7-f", "8:x2", "9:x3", "10-g"]
+        );
+    }
+
+    #[test]
+    fn max_count() {
+        let options = SearchOptions {
+            before: 0,
+            after: 0,
+            max_count: Some(2),
+        };
+        assert_eq!(events("x", options, CONTENTS), vec!["3:x1", "8:x2"]);
+        // the context after the last match is still printed
+        let options = SearchOptions {
+            before: 0,
+            after: 2,
+            max_count: Some(1),
+        };
+        assert_eq!(events("x", options, CONTENTS), vec!["3:x1", "4-c", "5-d"]);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let matcher = Matcher::new(&["caf"], &MatchOptions::default()).unwrap();
+        let mut texts = Vec::new();
+        Searcher::new(&matcher, &SearchOptions::default())
+            .search(&b"caf\xe9\nok\ncaf\xc3\xa9\n"[..], |event| {
+                if let Event::Match(line) = event {
+                    texts.push(line.text.clone());
+                }
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(texts, vec!["caf\u{fffd}", "café"]);
+    }
+
+    #[test]
+    fn offsets_and_line_terminators() {
+        let matcher = Matcher::new(&["b"], &MatchOptions::default()).unwrap();
+        let mut lines = Vec::new();
+        Searcher::new(&matcher, &SearchOptions::default())
+            .search("a\r\nb\r\nab\0\nb\n".as_bytes(), |event| {
+                if let Event::Match(line) = event {
+                    lines.push(line.clone());
+                }
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                Line {
+                    number: 2,
+                    offset: 3,
+                    text: "b".to_string(),
+                },
+                Line {
+                    number: 3,
+                    offset: 6,
+                    text: "ab\0".to_string(),
+                },
+                Line {
+                    number: 4,
+                    offset: 10,
+                    text: "b".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::types::{Types, TypesBuilder};
+use ignore::WalkBuilder;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Default)]
+pub struct WalkOptions {
+    // Search hidden files and directories (`--hidden`).
+    pub hidden: bool,
+    // Search the files excluded by `.gitignore`, `.ignore` and the like
+    // (`--no-ignore`).
+    pub no_ignore: bool,
+    // Globs the files must match, or must not match if they start with `!`
+    // (`--glob`).
+    pub globs: Vec<String>,
+    // Only search files of these types (`--type`), such as `rust` or `py`.
+    pub types: Vec<String>,
+    // Don't search files of these types (`--type-not`).
+    pub types_not: Vec<String>,
+}
+
+// Finds the files to search below the paths given on the command line. Paths
+// that name a file are always searched, the filters only apply to the files
+// found in directories. Symbolic links are only followed if they are on the
+// command line.
+#[derive(Clone, Debug)]
+pub struct Walker {
+    hidden: bool,
+    no_ignore: bool,
+    overrides: Override,
+    types: Types,
+}
+
+impl Walker {
+    pub fn new(options: &WalkOptions) -> Result<Walker, ignore::Error> {
+        let mut overrides = OverrideBuilder::new(".");
+        for glob in &options.globs {
+            overrides.add(glob)?;
+        }
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        for name in &options.types {
+            types.select(name);
+        }
+        for name in &options.types_not {
+            types.negate(name);
+        }
+        Ok(Walker {
+            hidden: options.hidden,
+            no_ignore: options.no_ignore,
+            overrides: overrides.build()?,
+            types: types.build()?,
+        })
+    }
+
+    // The files below `paths`, sorted by name within each directory. Errors
+    // are described with the path they occurred on. `-` stands for stdin and
+    // is passed on as is.
+    pub fn files<'a>(
+        &'a self,
+        paths: &'a [String],
+    ) -> impl Iterator<Item = Result<PathBuf, String>> + 'a {
+        paths.iter().flat_map(move |path| {
+            let (stdin, walk) = if path == "-" {
+                (Some(Ok(PathBuf::from(path))), None)
+            } else {
+                (None, Some(self.walk(path)))
+            };
+            stdin.into_iter().chain(walk.into_iter().flatten())
+        })
+    }
+
+    fn walk(&self, path: &str) -> impl Iterator<Item = Result<PathBuf, String>> {
+        let mut builder = WalkBuilder::new(path);
+        builder
+            .standard_filters(!self.no_ignore)
+            .hidden(!self.hidden)
+            .overrides(self.overrides.clone())
+            .types(self.types.clone())
+            .sort_by_file_name(|a, b| a.cmp(b));
+        if !self.hidden {
+            // the walk lets files selected by a glob or a type through even if
+            // they are hidden
+            builder.filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with('.'));
+        }
+        builder.build().filter_map(|entry| match entry {
+            // a path on the command line, which may be a link to a directory
+            Ok(entry) if entry.depth() == 0 && entry.path().is_dir() => None,
+            // links found in directories aren't followed: a link to a file
+            // would be searched along with the file
+            Ok(entry) if entry.depth() > 0 && !entry.file_type().is_some_and(|t| t.is_file()) => {
+                None
+            }
+            Ok(entry) => Some(Ok(entry.into_path())),
+            Err(e) => Some(Err(describe(&e))),
+        })
+    }
+}
+
+// Describe a walk error like an error reading a file: the path, then the I/O
+// error if there is one.
+fn describe(err: &ignore::Error) -> String {
+    match err {
+        ignore::Error::WithPath { path, err } => match err.io_error() {
+            // errors found while walking a directory wrap the OS error in a
+            // message repeating the path
+            Some(io) => match io.get_ref().and_then(|e| e.source()) {
+                Some(source) => format!("{}: {}", path.display(), source),
+                None => format!("{}: {}", path.display(), io),
+            },
+            None => format!("{}: {}", path.display(), err),
+        },
+        ignore::Error::WithDepth { err, .. } => describe(err),
+        err => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    // A fresh directory with `files` in it, named after `test`.
+    fn tree(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("minigrep-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (name, contents) in files {
+            let path = root.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn files(root: &Path, options: WalkOptions) -> Vec<String> {
+        let walker = Walker::new(&options).unwrap();
+        walker
+            .files(&[root.display().to_string()])
+            .map(|path| {
+                let path = path.unwrap();
+                let path = path.strip_prefix(root).unwrap();
+                path.to_str().unwrap().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filters() {
+        let root = tree(
+            "filters",
+            &[
+                ("b.txt", ""),
+                ("a/main.rs", ""),
+                ("a/skipped.log", ""),
+                (".ignore", "*.log\n"),
+                (".hidden.rs", ""),
+            ],
+        );
+        assert_eq!(
+            files(&root, WalkOptions::default()),
+            vec!["a/main.rs", "b.txt"]
+        );
+        let all = WalkOptions {
+            hidden: true,
+            no_ignore: true,
+            ..WalkOptions::default()
+        };
+        assert_eq!(
+            files(&root, all),
+            vec![
+                ".hidden.rs",
+                ".ignore",
+                "a/main.rs",
+                "a/skipped.log",
+                "b.txt"
+            ]
+        );
+        let rust = WalkOptions {
+            types: vec!["rust".to_string()],
+            ..WalkOptions::default()
+        };
+        assert_eq!(files(&root, rust), vec!["a/main.rs"]);
+        let not_rust = WalkOptions {
+            types_not: vec!["rust".to_string()],
+            ..WalkOptions::default()
+        };
+        assert_eq!(files(&root, not_rust), vec!["b.txt"]);
+        let globs = WalkOptions {
+            globs: vec!["!*.txt".to_string()],
+            ..WalkOptions::default()
+        };
+        assert_eq!(files(&root, globs), vec!["a/main.rs"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+        let root = tree("symlinks", &[("a/file.txt", ""), ("b.txt", "")]);
+        symlink("a", root.join("linkdir")).unwrap();
+        symlink("b.txt", root.join("link.txt")).unwrap();
+        assert_eq!(
+            files(&root, WalkOptions::default()),
+            vec!["a/file.txt", "b.txt"]
+        );
+        // links named on the command line are followed
+        let walker = Walker::new(&WalkOptions::default()).unwrap();
+        let paths = [
+            root.join("link.txt").display().to_string(),
+            root.join("linkdir").display().to_string(),
+        ];
+        let found: Vec<_> = walker.files(&paths).map(Result::unwrap).collect();
+        assert_eq!(
+            found,
+            vec![root.join("link.txt"), root.join("linkdir").join("file.txt")]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let bad_type = WalkOptions {
+            types: vec!["nope".to_string()],
+            ..WalkOptions::default()
+        };
+        assert!(Walker::new(&bad_type).is_err());
+        let walker = Walker::new(&WalkOptions::default()).unwrap();
+        let missing = walker
+            .files(&["no-such-path".to_string()])
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(missing.starts_with("no-such-path: "));
+        assert!(!missing.contains("IO error"));
+        let paths = ["-".to_string(), "no-such-path".to_string(), "-".to_string()];
+        let files: Vec<_> = walker.files(&paths).collect();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0], Ok(PathBuf::from("-")));
+        assert!(files[1].is_err());
+    }
+}
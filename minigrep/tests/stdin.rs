@@ -0,0 +1,38 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// The matches in stdin are printed as they come, like with
+// `tail -f log | minigrep error`.
+#[test]
+fn stdin_is_searched_as_it_comes() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_minigrep"))
+        .arg("match")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in stdout.lines() {
+            if sender.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+
+    writeln!(stdin, "match1\nnothing").unwrap();
+    let line = lines.recv_timeout(Duration::from_secs(5));
+    assert_eq!(line.as_deref(), Ok("match1"));
+    writeln!(stdin, "match2").unwrap();
+    let line = lines.recv_timeout(Duration::from_secs(5));
+    assert_eq!(line.as_deref(), Ok("match2"));
+
+    drop(stdin);
+    assert_eq!(child.wait().unwrap().code(), Some(0));
+    assert!(lines.recv().is_err());
+}
@@ -0,0 +1,458 @@
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot, Mutex};
+
+use crate::client::{Cmd, ConnectOptions, Error, Result, SetOptions, ToArg};
+use crate::serde_resp::{self, Decoder, Protocol};
+
+// The number of requests that can wait for the connection task before senders
+// have to wait as well.
+const REQUEST_BUFFER: usize = 1024;
+// The size of a single read from the socket.
+const READ_CHUNK: usize = 4096;
+
+// A command waiting for its reply.
+struct Request {
+    // The encoded command.
+    frame: Vec<u8>,
+    // Where the encoded reply goes.
+    reply: oneshot::Sender<Result<Vec<u8>>>,
+}
+
+// An async connection to a Redis server.
+//
+// Clones of the client share the connection. Commands of concurrent tasks are
+// written to the socket as they come, and because Redis replies in the order
+// of the commands, each reply goes to the oldest command still waiting. The
+// connection is closed when the last clone is dropped.
+#[derive(Clone)]
+pub struct Client {
+    requests: mpsc::Sender<Request>,
+    protocol: Protocol,
+    timeout: Option<Duration>,
+}
+
+impl Client {
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Client> {
+        Client::connect_with(addr, &ConnectOptions::default()).await
+    }
+
+    pub async fn connect_with<A: ToSocketAddrs>(
+        addr: A,
+        options: &ConnectOptions,
+    ) -> Result<Client> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let (requests, receiver) = mpsc::channel(REQUEST_BUFFER);
+        tokio::spawn(run(stream, receiver));
+        let mut client = Client {
+            requests,
+            protocol: Protocol::Resp2,
+            timeout: options.timeout,
+        };
+        if let Some(hello) = options.hello_cmd() {
+            let reply = client.query(&hello).await?;
+            client.protocol = ConnectOptions::hello_protocol(reply)?;
+        }
+        for cmd in options.setup_cmds(client.protocol) {
+            client.query::<String>(&cmd).await?;
+        }
+        Ok(client)
+    }
+
+    // The protocol the server speaks on this connection.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    // Whether the connection was lost. Commands of a closed client fail.
+    pub fn is_closed(&self) -> bool {
+        self.requests.is_closed()
+    }
+
+    // Send `cmd` and deserialize its reply. An error reply fails with
+    // `Error::Server`, unless `T` is `RedisType` which holds errors as values.
+    // A reply that takes longer than the timeout of the connection fails with
+    // an I/O error of kind `TimedOut`; the connection stays usable.
+    pub async fn query<T: DeserializeOwned>(&self, cmd: &Cmd) -> Result<T> {
+        let mut frame = Vec::new();
+        cmd.write_to(&mut frame)?;
+        let (reply, receiver) = oneshot::channel();
+        self.requests
+            .send(Request { frame, reply })
+            .await
+            .map_err(|_| closed())?;
+        let reply = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, receiver)
+                .await
+                .map_err(|_| Error::Io(io::ErrorKind::TimedOut.into()))?,
+            None => receiver.await,
+        };
+        let frame = reply.map_err(|_| closed())??;
+        Ok(serde_resp::from_slice(&frame)?)
+    }
+
+    pub async fn ping(&self) -> Result<String> {
+        self.query(&Cmd::new("PING")).await
+    }
+
+    // Return the value of `key`, or `None` if it does not exist.
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        self.query(&Cmd::new("GET").arg(key)).await
+    }
+
+    // Set `key` to `value`. Returns `false` if the key was not set because of
+    // the `NX` option.
+    pub async fn set<V: ToArg + ?Sized>(
+        &self,
+        key: &str,
+        value: &V,
+        options: &SetOptions,
+    ) -> Result<bool> {
+        let reply: Option<String> = self.query(&Cmd::set(key, value, options)).await?;
+        Ok(reply.is_some())
+    }
+
+    // Delete `keys` and return how many of them existed.
+    pub async fn del(&self, keys: &[&str]) -> Result<i64> {
+        self.query(&Cmd::keys("DEL", keys)).await
+    }
+
+    // Increment the integer value of `key` and return the new value.
+    pub async fn incr(&self, key: &str) -> Result<i64> {
+        self.query(&Cmd::new("INCR").arg(key)).await
+    }
+
+    // Return the values of `keys`, with `None` for keys that don't exist.
+    pub async fn mget<T: DeserializeOwned>(&self, keys: &[&str]) -> Result<Vec<Option<T>>> {
+        self.query(&Cmd::keys("MGET", keys)).await
+    }
+
+    // Expire `key` after `seconds`. Returns `false` if the key does not exist.
+    pub async fn expire(&self, key: &str, seconds: u64) -> Result<bool> {
+        self.query(&Cmd::new("EXPIRE").arg(key).arg(&seconds)).await
+    }
+}
+
+fn closed() -> Error {
+    Error::Io(io::ErrorKind::BrokenPipe.into())
+}
+
+// Write the requests to the socket and hand out the replies in order until the
+// connection fails or all clients are dropped.
+async fn run(stream: TcpStream, mut requests: mpsc::Receiver<Request>) {
+    let (mut reader, mut writer) = stream.into_split();
+    let mut decoder = Decoder::new();
+    let mut buf = Vec::new();
+    let mut chunk = vec![0; READ_CHUNK];
+    let mut pending: VecDeque<oneshot::Sender<Result<Vec<u8>>>> = VecDeque::new();
+    let err = loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let request = match request {
+                    Some(request) => request,
+                    None => return,
+                };
+                // write the requests that queued up meanwhile in one go
+                let mut frames = request.frame;
+                pending.push_back(request.reply);
+                while let Ok(request) = requests.try_recv() {
+                    frames.extend_from_slice(&request.frame);
+                    pending.push_back(request.reply);
+                }
+                if let Err(err) = writer.write_all(&frames).await {
+                    break Error::Io(err);
+                }
+            }
+            n = reader.read(&mut chunk) => {
+                match n {
+                    Ok(0) => break Error::Io(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    Err(err) => break Error::Io(err),
+                }
+                if let Err(err) = dispatch(&mut decoder, &mut buf, &mut pending) {
+                    break err;
+                }
+            }
+        }
+    };
+    // the reply of a failed request is lost, so all of them fail
+    requests.close();
+    if let Some(reply) = pending.pop_front() {
+        let _ = reply.send(Err(err));
+    }
+    for reply in pending {
+        let _ = reply.send(Err(closed()));
+    }
+}
+
+// Send the complete replies in `buf` to the oldest pending requests.
+fn dispatch(
+    decoder: &mut Decoder,
+    buf: &mut Vec<u8>,
+    pending: &mut VecDeque<oneshot::Sender<Result<Vec<u8>>>>,
+) -> Result<()> {
+    while let Some(len) = decoder.frame_len(buf)? {
+        let frame: Vec<u8> = buf.drain(..len).collect();
+        // RESP3 push frames are not replies to a command
+        if frame[0] == b'>' {
+            continue;
+        }
+        match pending.pop_front() {
+            // the request may have timed out, which drops the receiver
+            Some(reply) => {
+                let _ = reply.send(Ok(frame));
+            }
+            None => {
+                return Err(Error::Protocol(serde_resp::Error::Message(
+                    "reply without a command".to_string(),
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+// A fixed number of connections to one server. Each connection is shared by
+// the tasks using it, and a lost connection is replaced the next time it is
+// handed out.
+pub struct Pool {
+    addrs: Vec<SocketAddr>,
+    options: ConnectOptions,
+    clients: Vec<Mutex<Option<Client>>>,
+    next: AtomicUsize,
+}
+
+impl Pool {
+    // Open `size` connections to `addr`.
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        size: usize,
+        options: &ConnectOptions,
+    ) -> Result<Pool> {
+        assert!(size > 0, "a pool needs at least one connection");
+        let addrs: Vec<SocketAddr> = lookup_host(addr).await?.collect();
+        let mut clients = Vec::with_capacity(size);
+        for _ in 0..size {
+            let client = Client::connect_with(&addrs[..], options).await?;
+            clients.push(Mutex::new(Some(client)));
+        }
+        Ok(Pool {
+            addrs,
+            options: options.clone(),
+            clients,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.clients.len()
+    }
+
+    // Return the next connection in turn, reconnecting if it was lost.
+    pub async fn get(&self) -> Result<Client> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+        let mut slot = self.clients[index].lock().await;
+        if let Some(client) = &*slot {
+            if !client.is_closed() {
+                return Ok(client.clone());
+            }
+        }
+        *slot = None;
+        let client = Client::connect_with(&self.addrs[..], &self.options).await?;
+        *slot = Some(client.clone());
+        Ok(client)
+    }
+
+    // Run `cmd` on the next connection.
+    pub async fn query<T: DeserializeOwned>(&self, cmd: &Cmd) -> Result<T> {
+        self.get().await?.query(cmd).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::redis::RedisType;
+    use crate::serde_resp::Decoded;
+    use crate::server::Server;
+
+    // Serve one connection, answering every command with `handler`.
+    async fn mock_server<F>(handler: F) -> SocketAddr
+    where
+        F: Fn(&[String]) -> RedisType + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut decoder = Decoder::new();
+            let mut buf = Vec::new();
+            let mut chunk = [0; 512];
+            loop {
+                match decoder.decode::<Vec<String>>(&buf).unwrap() {
+                    Decoded::Frame(cmd, len) => {
+                        buf.drain(..len);
+                        let reply = serde_resp::to_vec(&handler(&cmd)).unwrap();
+                        stream.write_all(&reply).await.unwrap();
+                    }
+                    Decoded::Incomplete => match stream.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    },
+                }
+            }
+        });
+        addr
+    }
+
+    // Accept `HELLO 3 AUTH user secret` only, and anything after it.
+    fn auth_server(cmd: &[String]) -> RedisType {
+        match cmd {
+            [hello, _, _, _, password] if hello == "HELLO" && password == "secret" => {
+                RedisType::Map(vec![])
+            }
+            [hello, ..] if hello == "HELLO" => {
+                RedisType::Error("NOAUTH Authentication required".to_string())
+            }
+            _ => RedisType::SimpleString("OK".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commands() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let client = Client::connect(server.addr()).await.unwrap();
+        assert_eq!(client.ping().await.unwrap(), "PONG");
+        assert!(client
+            .set("key", "value", &SetOptions::default())
+            .await
+            .unwrap());
+        assert_eq!(
+            client.get::<String>("key").await.unwrap(),
+            Some("value".to_string())
+        );
+        assert_eq!(client.incr("counter").await.unwrap(), 1);
+        assert_eq!(
+            client.mget::<String>(&["key", "missing"]).await.unwrap(),
+            vec![Some("value".to_string()), None]
+        );
+        assert!(client.expire("key", 10).await.unwrap());
+        match client.incr("key").await {
+            Err(Error::Server(msg)) => {
+                assert_eq!(msg, "ERR value is not an integer or out of range")
+            }
+            other => panic!("expected a server error, got {:?}", other),
+        }
+        assert_eq!(client.del(&["key", "counter"]).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_hello() {
+        let options = ConnectOptions::default()
+            .protocol(Protocol::Resp3)
+            .password("secret");
+        let client = Client::connect_with(mock_server(auth_server).await, &options)
+            .await
+            .unwrap();
+        assert_eq!(client.protocol(), Protocol::Resp3);
+        let options = ConnectOptions::default().protocol(Protocol::Resp3);
+        match Client::connect_with(mock_server(auth_server).await, &options).await {
+            Err(err) => assert_eq!(err.code(), Some("NOAUTH")),
+            Ok(_) => panic!("expected HELLO to fail"),
+        }
+        // the server does not know HELLO
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let client = Client::connect_with(server.addr(), &options).await.unwrap();
+        assert_eq!(client.protocol(), Protocol::Resp2);
+        assert_eq!(client.ping().await.unwrap(), "PONG");
+    }
+
+    #[tokio::test]
+    async fn test_multiplexing() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let client = Client::connect(server.addr()).await.unwrap();
+        let tasks: Vec<_> = (0..100)
+            .map(|i| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let key = format!("key:{}", i);
+                    client.set(&key, &i, &SetOptions::default()).await.unwrap();
+                    let value: Option<i64> = client.get(&key).await.unwrap();
+                    assert_eq!(value, Some(i));
+                    client.incr("counter").await.unwrap()
+                })
+            })
+            .collect();
+        let mut counts = Vec::new();
+        for task in tasks {
+            counts.push(task.await.unwrap());
+        }
+        counts.sort();
+        assert_eq!(counts, (1..=100).collect::<Vec<i64>>());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        // a server that accepts the connection and never replies
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+        let options = ConnectOptions::default().timeout(Duration::from_millis(50));
+        let client = Client::connect_with(addr, &options).await.unwrap();
+        match client.ping().await {
+            Err(Error::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        drop(accept.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_connection_lost() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let client = Client::connect(server.addr()).await.unwrap();
+        assert_eq!(client.ping().await.unwrap(), "PONG");
+        drop(server);
+        assert!(matches!(client.ping().await, Err(Error::Io(_))));
+        assert!(client.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_pool() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let addr = server.addr();
+        let options = ConnectOptions::default().db(3);
+        let pool = Arc::new(Pool::connect(addr, 4, &options).await.unwrap());
+        assert_eq!(pool.size(), 4);
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let pool = Arc::clone(&pool);
+                tokio::spawn(async move { pool.get().await.unwrap().incr("counter").await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+        let count: Option<i64> = pool.query(&Cmd::new("GET").arg("counter")).await.unwrap();
+        assert_eq!(count, Some(20));
+
+        // the connections of the pool are replaced after a restart
+        drop(server);
+        let _server = Server::start(addr).unwrap();
+        for _ in 0..pool.size() {
+            let _ = pool.get().await.unwrap().ping().await;
+        }
+        for _ in 0..pool.size() {
+            assert_eq!(pool.get().await.unwrap().ping().await.unwrap(), "PONG");
+        }
+    }
+}
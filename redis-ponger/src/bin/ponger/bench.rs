@@ -0,0 +1,357 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use redis_ponger::client::{Cmd, ConnectOptions, Pipeline, Result};
+use redis_ponger::{Client, RedisType};
+
+// A command the benchmark can send.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Ping,
+    Get,
+    Set,
+    Incr,
+}
+
+impl Op {
+    fn parse(name: &str) -> Option<Op> {
+        match name.to_ascii_lowercase().as_str() {
+            "ping" => Some(Op::Ping),
+            "get" => Some(Op::Get),
+            "set" => Some(Op::Set),
+            "incr" => Some(Op::Incr),
+            _ => None,
+        }
+    }
+
+    fn cmd(self, key: &str, value: &[u8]) -> Cmd {
+        match self {
+            Op::Ping => Cmd::new("PING"),
+            Op::Get => Cmd::new("GET").arg(key),
+            Op::Set => Cmd::new("SET").arg(key).arg(value),
+            Op::Incr => Cmd::new("INCR").arg(key),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Op::Ping => "PING",
+            Op::Get => "GET",
+            Op::Set => "SET",
+            Op::Incr => "INCR",
+        };
+        f.write_str(name)
+    }
+}
+
+// The options of `ponger bench`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    // The number of parallel connections.
+    pub clients: usize,
+    // The total number of commands.
+    pub requests: usize,
+    // The number of commands sent at once on a connection.
+    pub pipeline: usize,
+    // The commands to send and their weights.
+    pub mix: Vec<(Op, u32)>,
+    // The number of distinct keys.
+    pub keyspace: usize,
+    // The size of the values of SET in bytes.
+    pub data_size: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            clients: 50,
+            requests: 100_000,
+            pipeline: 1,
+            mix: vec![(Op::Get, 1), (Op::Set, 1)],
+            keyspace: 10_000,
+            data_size: 3,
+        }
+    }
+}
+
+impl Options {
+    // Parse the arguments after `bench`.
+    pub fn parse(args: &[String]) -> std::result::Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "-c" | "--clients" => options.clients = number(arg, value()?)?,
+                "-n" | "--requests" => options.requests = number(arg, value()?)?,
+                "-P" | "--pipeline" => options.pipeline = number(arg, value()?)?,
+                "-r" | "--keyspace" => options.keyspace = number(arg, value()?)?,
+                "-d" | "--data-size" => {
+                    options.data_size = value()?
+                        .parse()
+                        .map_err(|_| format!("invalid value for {}", arg))?
+                }
+                "--mix" => options.mix = parse_mix(value()?)?,
+                _ => return Err(format!("unknown bench option {}", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// Parse a positive number.
+fn number(arg: &str, value: &str) -> std::result::Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid value for {}: {}", arg, value)),
+    }
+}
+
+// Parse a command mix such as `get=80,set=20`. A command without a weight has
+// the weight 1.
+fn parse_mix(mix: &str) -> std::result::Result<Vec<(Op, u32)>, String> {
+    mix.split(',')
+        .map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let name = parts.next().unwrap_or_default();
+            let op = Op::parse(name).ok_or_else(|| format!("unknown command in mix: {}", name))?;
+            let weight = match parts.next() {
+                Some(weight) => weight
+                    .parse()
+                    .map_err(|_| format!("invalid weight in mix: {}", entry))?,
+                None => 1,
+            };
+            Ok((op, weight))
+        })
+        .filter(|entry| !matches!(entry, Ok((_, 0))))
+        .collect::<std::result::Result<Vec<_>, String>>()
+        .and_then(|mix| {
+            if mix.is_empty() {
+                Err("the command mix is empty".to_string())
+            } else {
+                Ok(mix)
+            }
+        })
+}
+
+// The result of a benchmark.
+pub struct Report {
+    pub elapsed: Duration,
+    // The latency of every command, sorted.
+    pub latencies: Vec<Duration>,
+    // The number of commands sent per kind, in the order of the mix.
+    pub counts: Vec<(Op, usize)>,
+    pub errors: usize,
+}
+
+impl Report {
+    pub fn throughput(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    // The latency below which a fraction `q` of the commands completed.
+    pub fn percentile(&self, q: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::default();
+        }
+        let index = ((self.latencies.len() - 1) as f64 * q).round() as usize;
+        self.latencies[index]
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        writeln!(
+            f,
+            "{} requests completed in {:.2} seconds",
+            self.latencies.len(),
+            self.elapsed.as_secs_f64()
+        )?;
+        for (op, count) in &self.counts {
+            writeln!(f, "  {}: {} requests", op, count)?;
+        }
+        if self.errors > 0 {
+            writeln!(f, "  {} error replies", self.errors)?;
+        }
+        writeln!(
+            f,
+            "throughput: {:.2} requests per second",
+            self.throughput()
+        )?;
+        write!(
+            f,
+            "latency (ms): p50 {:.3}, p90 {:.3}, p99 {:.3}, p99.9 {:.3}, max {:.3}",
+            ms(self.percentile(0.5)),
+            ms(self.percentile(0.9)),
+            ms(self.percentile(0.99)),
+            ms(self.percentile(0.999)),
+            ms(self.percentile(1.0)),
+        )
+    }
+}
+
+// A small xorshift generator, so runs are reproducible without a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+struct Worker {
+    latencies: Vec<Duration>,
+    counts: Vec<usize>,
+    errors: usize,
+}
+
+// Run the benchmark against `addr`. Each client runs on its own thread with
+// its own connection, sending batches of `pipeline` commands. The latency of a
+// command is the round trip of its batch.
+pub fn run(addr: SocketAddr, connect: &ConnectOptions, options: &Options) -> Result<Report> {
+    let start = Instant::now();
+    let workers: Vec<_> = (0..options.clients)
+        .map(|i| {
+            let requests = options.requests / options.clients
+                + (i < options.requests % options.clients) as usize;
+            let connect = connect.clone();
+            let options = options.clone();
+            thread::spawn(move || worker(addr, &connect, &options, i as u64, requests))
+        })
+        .collect();
+    let mut report = Report {
+        elapsed: Duration::default(),
+        latencies: Vec::with_capacity(options.requests),
+        counts: options.mix.iter().map(|&(op, _)| (op, 0)).collect(),
+        errors: 0,
+    };
+    for worker in workers {
+        let worker = worker.join().expect("benchmark thread panicked")?;
+        report.latencies.extend(worker.latencies);
+        for (total, count) in report.counts.iter_mut().zip(worker.counts) {
+            total.1 += count;
+        }
+        report.errors += worker.errors;
+    }
+    report.elapsed = start.elapsed();
+    report.latencies.sort();
+    Ok(report)
+}
+
+fn worker(
+    addr: SocketAddr,
+    connect: &ConnectOptions,
+    options: &Options,
+    seed: u64,
+    requests: usize,
+) -> Result<Worker> {
+    let mut client = Client::connect_with(addr, connect)?;
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15 ^ (seed + 1).wrapping_mul(0x2545_f491_4f6c_dd1d));
+    let total_weight: u64 = options.mix.iter().map(|&(_, w)| u64::from(w)).sum();
+    let value = vec![b'x'; options.data_size];
+    let mut worker = Worker {
+        latencies: Vec::with_capacity(requests),
+        counts: vec![0; options.mix.len()],
+        errors: 0,
+    };
+    let mut remaining = requests;
+    while remaining > 0 {
+        let batch = remaining.min(options.pipeline);
+        let mut pipeline = Pipeline::new();
+        for _ in 0..batch {
+            let mut pick = rng.next() % total_weight;
+            let index = options
+                .mix
+                .iter()
+                .position(|&(_, weight)| match pick.checked_sub(u64::from(weight)) {
+                    Some(rest) => {
+                        pick = rest;
+                        false
+                    }
+                    None => true,
+                })
+                .unwrap_or(0);
+            worker.counts[index] += 1;
+            let key = format!("key:{:012}", rng.next() % options.keyspace as u64);
+            pipeline.cmd(options.mix[index].0.cmd(&key, &value));
+        }
+        let start = Instant::now();
+        let replies = client.pipeline(&pipeline)?;
+        let latency = start.elapsed();
+        worker.errors += replies
+            .iter()
+            .filter(|reply| matches!(reply, RedisType::Error(_)))
+            .count();
+        worker.latencies.extend((0..batch).map(|_| latency));
+        remaining -= batch;
+    }
+    Ok(worker)
+}
+
+#[cfg(test)]
+mod tests {
+    use redis_ponger::Server;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let options = Options::parse(&args(&[
+            "-c",
+            "4",
+            "-n",
+            "1000",
+            "-P",
+            "16",
+            "--mix",
+            "get=80,set=20,ping",
+        ]))
+        .unwrap();
+        assert_eq!(options.clients, 4);
+        assert_eq!(options.requests, 1000);
+        assert_eq!(options.pipeline, 16);
+        assert_eq!(
+            options.mix,
+            vec![(Op::Get, 80), (Op::Set, 20), (Op::Ping, 1)]
+        );
+        assert!(Options::parse(&args(&["-c", "0"])).is_err());
+        assert!(Options::parse(&args(&["--mix", "flushall"])).is_err());
+        assert!(Options::parse(&args(&["--mix", "get=0"])).is_err());
+        assert!(Options::parse(&args(&["--pipeline"])).is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let options = Options {
+            clients: 3,
+            requests: 100,
+            pipeline: 8,
+            mix: vec![(Op::Set, 1), (Op::Incr, 1), (Op::Get, 2)],
+            keyspace: 10,
+            data_size: 3,
+        };
+        let report = run(server.addr(), &ConnectOptions::default(), &options).unwrap();
+        assert_eq!(report.latencies.len(), 100);
+        assert_eq!(report.counts.iter().map(|(_, n)| n).sum::<usize>(), 100);
+        // INCR fails on keys holding "xxx"
+        assert!(report.errors <= report.counts[1].1);
+        assert!(report.percentile(0.5) <= report.percentile(1.0));
+        assert!(report.to_string().contains("100 requests completed"));
+    }
+}
@@ -0,0 +1,304 @@
+mod bench;
+mod pretty;
+
+use redis_ponger::client::{Cmd, ConnectOptions, Error};
+use redis_ponger::serde_resp::Protocol;
+use redis_ponger::{Client, RedisType};
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::process;
+
+const USAGE: &str = "\
+Usage: ponger [OPTIONS] [COMMAND [ARG...]]
+       ponger [OPTIONS] bench [BENCH OPTIONS]
+
+Without a command ponger starts an interactive prompt.
+
+Options:
+  --host <host>        Server hostname (default: 127.0.0.1)
+  --port <port>        Server port (default: 6379)
+  --password <pass>    Authenticate with AUTH after connecting
+  --db <index>         Select a database after connecting
+  --resp3              Ask the server to speak RESP3
+  --help               Print this help
+
+Bench options:
+  -c, --clients <n>    Parallel connections (default: 50)
+  -n, --requests <n>   Total number of commands (default: 100000)
+  -P, --pipeline <n>   Commands sent at once per connection (default: 1)
+  -r, --keyspace <n>   Number of distinct keys (default: 10000)
+  -d, --data-size <n>  Size of SET values in bytes (default: 3)
+  --mix <mix>          Commands and weights (default: get=1,set=1),
+                       from ping, get, set and incr";
+
+struct Args {
+    host: String,
+    port: u16,
+    options: ConnectOptions,
+    mode: Mode,
+}
+
+enum Mode {
+    Repl,
+    OneShot(Vec<String>),
+    Bench(bench::Options),
+    Help,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        host: "127.0.0.1".to_string(),
+        port: 6379,
+        options: ConnectOptions::default(),
+        mode: Mode::Repl,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        let value = || {
+            args.get(i + 1)
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg {
+            "--host" => parsed.host = value()?.clone(),
+            "--port" => {
+                parsed.port = value()?
+                    .parse()
+                    .map_err(|_| format!("invalid port: {}", value().unwrap()))?
+            }
+            "--password" => parsed.options = parsed.options.password(value()?),
+            "--db" => {
+                let db = value()?
+                    .parse()
+                    .map_err(|_| format!("invalid database: {}", value().unwrap()))?;
+                parsed.options = parsed.options.db(db);
+            }
+            "--resp3" => {
+                parsed.options = parsed.options.protocol(Protocol::Resp3);
+                i += 1;
+                continue;
+            }
+            "--help" => {
+                parsed.mode = Mode::Help;
+                return Ok(parsed);
+            }
+            "bench" => {
+                parsed.mode = Mode::Bench(bench::Options::parse(&args[i + 1..])?);
+                return Ok(parsed);
+            }
+            arg if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => {
+                parsed.mode = Mode::OneShot(args[i..].to_vec());
+                return Ok(parsed);
+            }
+        }
+        i += 2;
+    }
+    Ok(parsed)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("ponger: {}\n\n{}", msg, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(args) {
+        eprintln!("ponger: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let addr = resolve(&args.host, args.port)?;
+    match args.mode {
+        Mode::Help => println!("{}", USAGE),
+        Mode::OneShot(cmd) => {
+            let mut client = Client::connect_with(addr, &args.options)?;
+            println!("{}", pretty::format(&client.query(&command(&cmd))?));
+        }
+        Mode::Repl => {
+            let client = Client::connect_with(addr, &args.options)?;
+            repl(client, &format!("{}:{}", args.host, args.port))?;
+        }
+        Mode::Bench(options) => {
+            let report = bench::run(addr, &args.options, &options)?;
+            println!("{}", report);
+        }
+    }
+    Ok(())
+}
+
+fn resolve(host: &str, port: u16) -> io::Result<SocketAddr> {
+    (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("unknown host {}", host)))
+}
+
+// The arguments are sent as they are, so they may be any bytes. The command
+// name is text.
+fn command<A: AsRef<[u8]>>(args: &[A]) -> Cmd {
+    let name = String::from_utf8_lossy(args[0].as_ref());
+    args[1..]
+        .iter()
+        .fold(Cmd::new(&name), |cmd, arg| cmd.arg(arg.as_ref()))
+}
+
+// Read commands from stdin and print their replies until `quit` or the end of
+// the input.
+fn repl(mut client: Client, prompt: &str) -> Result<(), Error> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{}> ", prompt);
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        let args = match split_args(&line) {
+            Ok(args) => args,
+            Err(msg) => {
+                println!("(error) {}", msg);
+                continue;
+            }
+        };
+        match args.first().map(|name| name.to_ascii_lowercase()) {
+            None => continue,
+            Some(name) if name == b"quit" || name == b"exit" => return Ok(()),
+            Some(_) => {}
+        }
+        match client.query::<RedisType>(&command(&args)) {
+            Ok(reply) => println!("{}", pretty::format(&reply)),
+            Err(Error::Io(err)) => return Err(Error::Io(err)),
+            Err(err) => println!("(error) {}", err),
+        }
+    }
+}
+
+// Split a line into arguments like redis-cli. Arguments are separated by
+// whitespace, `"..."` may contain escapes such as `\n` or `\x41`, and `'...'`
+// is taken literally. `\xff` stands for the byte, so arguments are bytes.
+fn split_args(line: &str) -> Result<Vec<Vec<u8>>, &'static str> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let first = match chars.peek() {
+            Some(&c) => c,
+            None => return Ok(args),
+        };
+        let mut arg = Vec::new();
+        let push = |arg: &mut Vec<u8>, c: char| {
+            arg.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+        };
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => return Err("unbalanced quotes"),
+                    Some(c) if c == first => break,
+                    Some('\\') if first == '"' => match chars.next() {
+                        Some('n') => arg.push(b'\n'),
+                        Some('r') => arg.push(b'\r'),
+                        Some('t') => arg.push(b'\t'),
+                        Some('x') => {
+                            let hex: String = chars.by_ref().take(2).collect();
+                            match u8::from_str_radix(&hex, 16) {
+                                Ok(b) if hex.len() == 2 => arg.push(b),
+                                _ => return Err("invalid \\x escape"),
+                            }
+                        }
+                        Some(c) => push(&mut arg, c),
+                        None => return Err("unbalanced quotes"),
+                    },
+                    Some(c) => push(&mut arg, c),
+                }
+            }
+            // a closing quote must end the argument
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err("closing quote must be followed by a space");
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                push(&mut arg, c);
+                chars.next();
+            }
+        }
+        args.push(arg);
+    }
+}
+
+// For Simple Strings the first byte of the reply is "+"
+// For Errors the first byte of the reply is "-"
+// For Integers the first byte of the reply is ":"
+// For Bulk Strings the first byte of the reply is "$"
+// For Arrays the first byte of the reply is "*"
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = parse_args(&args(&[
+            "--host", "redis", "--port", "7000", "SET", "k", "v",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.host, "redis");
+        assert_eq!(parsed.port, 7000);
+        match parsed.mode {
+            Mode::OneShot(cmd) => assert_eq!(cmd, vec!["SET", "k", "v"]),
+            _ => panic!("expected a one-shot command"),
+        }
+        assert!(matches!(parse_args(&[]).unwrap().mode, Mode::Repl));
+        match parse_args(&args(&["--resp3", "bench", "-c", "2"]))
+            .unwrap()
+            .mode
+        {
+            Mode::Bench(options) => assert_eq!(options.clients, 2),
+            _ => panic!("expected bench"),
+        }
+        assert!(parse_args(&args(&["--port", "x"])).is_err());
+        assert!(parse_args(&args(&["--host"])).is_err());
+        assert!(parse_args(&args(&["--verbose"])).is_err());
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args("  set key  value ").unwrap(),
+            vec![&b"set"[..], b"key", b"value"]
+        );
+        assert_eq!(
+            split_args(r#"set "a b\n\x41" 'c\n' é"#).unwrap(),
+            vec![&b"set"[..], b"a b\nA", b"c\\n", "é".as_bytes()]
+        );
+        assert_eq!(split_args("").unwrap(), Vec::<Vec<u8>>::new());
+        assert!(split_args("set \"key").is_err());
+        assert!(split_args("set \"key\"x").is_err());
+    }
+
+    #[test]
+    fn test_binary_args() {
+        let args = split_args(r#"set key "\xff\x00""#).unwrap();
+        assert_eq!(
+            command(&args),
+            Cmd::new("set").arg("key").arg(&[0xff, 0x00][..])
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use redis_ponger::RedisType;
+
+// Format a reply the way redis-cli does on a terminal, for example
+//
+//     1) "key"
+//     2) (integer) 42
+//     3) 1) "nested"
+//        2) (nil)
+pub fn format(reply: &RedisType) -> String {
+    match reply {
+        RedisType::SimpleString(text) => text.clone(),
+        RedisType::Error(msg) => format!("(error) {}", msg),
+        RedisType::Integer(n) => format!("(integer) {}", n),
+        RedisType::BulkString(bytes) => quote(bytes),
+        RedisType::Null => "(nil)".to_string(),
+        RedisType::Double(n) => format!("(double) {}", n),
+        RedisType::Boolean(b) => format!("({})", b),
+        RedisType::BigNumber(n) => format!("(big number) {}", n),
+        RedisType::VerbatimString { text, .. } => String::from_utf8_lossy(text).into_owned(),
+        RedisType::Attribute { value, .. } => format(value),
+        RedisType::Array(items) | RedisType::Push(items) => list(items, ')'),
+        RedisType::Set(items) => list(items, '~'),
+        RedisType::Map(pairs) => {
+            let entries: Vec<String> = pairs
+                .iter()
+                .map(|(key, value)| format!("{} => {}", format(key), format(value)))
+                .collect();
+            numbered(&entries, '#', "(empty hash)")
+        }
+    }
+}
+
+fn list(items: &[RedisType], marker: char) -> String {
+    let items: Vec<String> = items.iter().map(format).collect();
+    let empty = if marker == '~' {
+        "(empty set)"
+    } else {
+        "(empty array)"
+    };
+    numbered(&items, marker, empty)
+}
+
+// Number the formatted `items` and indent their following lines below the
+// first one.
+fn numbered(items: &[String], marker: char, empty: &str) -> String {
+    if items.is_empty() {
+        return empty.to_string();
+    }
+    let width = items.len().to_string().len();
+    let mut out = String::new();
+    for (i, item) in items.iter().enumerate() {
+        let label = format!("{:>width$}{} ", i + 1, marker, width = width);
+        let indent = " ".repeat(label.len());
+        for (j, line) in item.lines().enumerate() {
+            if i > 0 || j > 0 {
+                out.push('\n');
+            }
+            out.push_str(if j == 0 { &label } else { &indent });
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+// Quote a bulk string, escaping quotes, control characters and bytes that are
+// not printable ASCII.
+pub fn quote(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b' '..=b'~' => out.push(b as char),
+            b => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &str) -> RedisType {
+        RedisType::BulkString(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_format_scalars() {
+        assert_eq!(format(&RedisType::SimpleString("OK".to_string())), "OK");
+        assert_eq!(
+            format(&RedisType::Error("ERR oops".to_string())),
+            "(error) ERR oops"
+        );
+        assert_eq!(format(&RedisType::Integer(-3)), "(integer) -3");
+        assert_eq!(format(&RedisType::Null), "(nil)");
+        assert_eq!(format(&bulk("a \"b\"\n")), r#""a \"b\"\n""#);
+        assert_eq!(
+            format(&RedisType::BulkString(vec![0xff, b'x'])),
+            r#""\xffx""#
+        );
+        assert_eq!(format(&RedisType::Boolean(true)), "(true)");
+        assert_eq!(format(&RedisType::Double(1.5)), "(double) 1.5");
+    }
+
+    #[test]
+    fn test_format_nested() {
+        let reply = RedisType::Array(vec![
+            bulk("key"),
+            RedisType::Integer(42),
+            RedisType::Array(vec![bulk("nested"), RedisType::Null]),
+            RedisType::Array(vec![]),
+        ]);
+        let expected = "\
+1) \"key\"
+2) (integer) 42
+3) 1) \"nested\"
+   2) (nil)
+4) (empty array)";
+        assert_eq!(format(&reply), expected);
+    }
+
+    #[test]
+    fn test_format_wide_numbers() {
+        let reply = RedisType::Array((0..10).map(RedisType::Integer).collect());
+        let formatted = format(&reply);
+        assert!(formatted.starts_with(" 1) (integer) 0\n"));
+        assert!(formatted.ends_with("\n10) (integer) 9"));
+    }
+
+    #[test]
+    fn test_format_map_and_set() {
+        let reply = RedisType::Map(vec![
+            (bulk("a"), RedisType::Integer(1)),
+            (bulk("b"), RedisType::Set(vec![bulk("x"), bulk("y")])),
+        ]);
+        let expected = "\
+1# \"a\" => (integer) 1
+2# \"b\" => 1~ \"x\"
+   2~ \"y\"";
+        assert_eq!(format(&reply), expected);
+    }
+}
@@ -0,0 +1,738 @@
+use serde::de::DeserializeOwned;
+use std::fmt::{self, Display};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::redis::RedisType;
+use crate::serde_resp::{self, Decoder, Protocol};
+
+// The size of a single read from the socket.
+const READ_CHUNK: usize = 4096;
+
+// An error of a client command.
+#[derive(Debug)]
+pub enum Error {
+    /// The connection failed.
+    Io(io::Error),
+    /// The server sent a reply that is not valid RESP or does not have the
+    /// type the command expects.
+    Protocol(serde_resp::Error),
+    /// The server replied with an error, for example `ERR unknown command` or
+    /// `WRONGTYPE Operation against a key holding the wrong kind of value`.
+    Server(String),
+}
+
+impl Error {
+    // The error code of a server error, which is the first word of the message
+    // such as `ERR` or `WRONGTYPE`.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::Server(msg) => msg.split(' ').next(),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(formatter, "connection error: {}", err),
+            Error::Protocol(err) => write!(formatter, "protocol error: {}", err),
+            Error::Server(msg) => write!(formatter, "server error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Protocol(err) => Some(err),
+            Error::Server(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_resp::Error> for Error {
+    fn from(err: serde_resp::Error) -> Self {
+        match err {
+            serde_resp::Error::Redis(msg) => Error::Server(msg),
+            err => Error::Protocol(err),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// A value that can be passed as an argument of a command. Redis receives every
+// argument as a bulk string.
+pub trait ToArg {
+    fn to_arg(&self) -> Vec<u8>;
+}
+
+impl ToArg for str {
+    fn to_arg(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToArg for String {
+    fn to_arg(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToArg for [u8] {
+    fn to_arg(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl ToArg for Vec<u8> {
+    fn to_arg(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl<T: ToArg + ?Sized> ToArg for &T {
+    fn to_arg(&self) -> Vec<u8> {
+        (**self).to_arg()
+    }
+}
+
+macro_rules! impl_to_arg_display {
+    ($($ty:ty)*) => {
+        $(
+            impl ToArg for $ty {
+                fn to_arg(&self) -> Vec<u8> {
+                    self.to_string().into_bytes()
+                }
+            }
+        )*
+    };
+}
+
+impl_to_arg_display!(i8 i16 i32 i64 isize u8 u16 u32 u64 usize f32 f64);
+
+// A command and its arguments, for example `Cmd::new("GET").arg("key")`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cmd {
+    args: Vec<Vec<u8>>,
+}
+
+impl Cmd {
+    pub fn new(name: &str) -> Self {
+        Cmd {
+            args: vec![name.to_arg()],
+        }
+    }
+
+    pub fn arg<A: ToArg + ?Sized>(mut self, arg: &A) -> Self {
+        self.args.push(arg.to_arg());
+        self
+    }
+
+    // `SET key value [EX seconds] [NX]`
+    pub(crate) fn set<V: ToArg + ?Sized>(key: &str, value: &V, options: &SetOptions) -> Self {
+        let mut cmd = Cmd::new("SET").arg(key).arg(value);
+        if let Some(seconds) = options.ex {
+            cmd = cmd.arg("EX").arg(&seconds);
+        }
+        if options.nx {
+            cmd = cmd.arg("NX");
+        }
+        cmd
+    }
+
+    // A command that takes a list of keys, such as `DEL` or `MGET`.
+    pub(crate) fn keys(name: &str, keys: &[&str]) -> Self {
+        keys.iter().fold(Cmd::new(name), |cmd, key| cmd.arg(key))
+    }
+
+    // Append the command to `buf` as an array of bulk strings.
+    pub(crate) fn write_to(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let cmd = RedisType::Array(
+            self.args
+                .iter()
+                .map(|arg| RedisType::BulkString(arg.clone()))
+                .collect(),
+        );
+        serde_resp::to_writer(buf, &cmd)?;
+        Ok(())
+    }
+}
+
+// Commands that are sent together and whose replies are read afterwards, which
+// saves a round trip per command.
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline {
+    cmds: Vec<Cmd>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    pub fn cmd(&mut self, cmd: Cmd) -> &mut Self {
+        self.cmds.push(cmd);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.cmds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+}
+
+// The options of `Client::set`.
+#[derive(Clone, Debug, Default)]
+pub struct SetOptions {
+    ex: Option<u64>,
+    nx: bool,
+}
+
+impl SetOptions {
+    // Expire the key after `seconds` (`EX`).
+    pub fn ex(mut self, seconds: u64) -> Self {
+        self.ex = Some(seconds);
+        self
+    }
+
+    // Only set the key if it does not exist yet (`NX`).
+    pub fn nx(mut self) -> Self {
+        self.nx = true;
+        self
+    }
+}
+
+// The options of `Client::connect_with`.
+#[derive(Clone, Debug)]
+pub struct ConnectOptions {
+    username: Option<String>,
+    password: Option<String>,
+    db: Option<i64>,
+    pub(crate) protocol: Protocol,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            username: None,
+            password: None,
+            db: None,
+            protocol: Protocol::Resp2,
+            timeout: None,
+        }
+    }
+}
+
+impl ConnectOptions {
+    // Authenticate with `AUTH password` after connecting.
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    // Authenticate as an ACL user with `AUTH username password`. Only used
+    // together with a password.
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    // Switch to the database `db` with `SELECT` after connecting.
+    pub fn db(mut self, db: i64) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    // Ask the server to speak `protocol` with `HELLO`. Servers older than
+    // Redis 6 don't know the command and keep speaking RESP2.
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    // Fail commands whose reply takes longer than `timeout` with an I/O error.
+    // A blocking client should be dropped after a timeout, since the late reply
+    // would be read as the reply of the next command.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // The `HELLO` to send first if RESP3 is asked for. It carries the
+    // credentials, since a server that requires them refuses `HELLO` before
+    // `AUTH`.
+    pub(crate) fn hello_cmd(&self) -> Option<Cmd> {
+        if self.protocol != Protocol::Resp3 {
+            return None;
+        }
+        let mut cmd = Cmd::new("HELLO").arg("3");
+        if let Some(password) = &self.password {
+            let username = self.username.as_deref().unwrap_or("default");
+            cmd = cmd.arg("AUTH").arg(username).arg(password);
+        }
+        Some(cmd)
+    }
+
+    // The protocol the server speaks after replying `reply` to `HELLO`. Only
+    // a server that does not know the command, older than Redis 6, keeps
+    // speaking RESP2. Other errors, such as a wrong password, fail.
+    pub(crate) fn hello_protocol(reply: RedisType) -> Result<Protocol> {
+        match reply {
+            RedisType::Error(msg) if msg.starts_with("ERR unknown command") => Ok(Protocol::Resp2),
+            RedisType::Error(msg) => Err(Error::Server(msg)),
+            _ => Ok(Protocol::Resp3),
+        }
+    }
+
+    // The commands to send after connecting and after `HELLO`, which has
+    // authenticated already if the server speaks RESP3.
+    pub(crate) fn setup_cmds(&self, protocol: Protocol) -> Vec<Cmd> {
+        let mut cmds = Vec::new();
+        let authenticated = protocol == Protocol::Resp3;
+        if let Some(password) = self.password.as_ref().filter(|_| !authenticated) {
+            let mut cmd = Cmd::new("AUTH");
+            if let Some(username) = &self.username {
+                cmd = cmd.arg(username);
+            }
+            cmds.push(cmd.arg(password));
+        }
+        if let Some(db) = self.db {
+            cmds.push(Cmd::new("SELECT").arg(&db));
+        }
+        cmds
+    }
+}
+
+// A blocking connection to a Redis server.
+pub struct Client {
+    stream: TcpStream,
+    decoder: Decoder,
+    // Bytes read from the stream that don't belong to a returned reply yet.
+    buf: Vec<u8>,
+    protocol: Protocol,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Client> {
+        Client::connect_with(addr, &ConnectOptions::default())
+    }
+
+    pub fn connect_with<A: ToSocketAddrs>(addr: A, options: &ConnectOptions) -> Result<Client> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(options.timeout)?;
+        stream.set_write_timeout(options.timeout)?;
+        let mut client = Client {
+            stream,
+            decoder: Decoder::new(),
+            buf: Vec::new(),
+            protocol: Protocol::Resp2,
+        };
+        if let Some(hello) = options.hello_cmd() {
+            let reply = client.query(&hello)?;
+            client.protocol = ConnectOptions::hello_protocol(reply)?;
+        }
+        for cmd in options.setup_cmds(client.protocol) {
+            client.query::<String>(&cmd)?;
+        }
+        Ok(client)
+    }
+
+    // The protocol the server speaks on this connection.
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    // Send `cmd` and deserialize its reply. An error reply fails with
+    // `Error::Server`, unless `T` is `RedisType` which holds errors as values.
+    pub fn query<T: DeserializeOwned>(&mut self, cmd: &Cmd) -> Result<T> {
+        self.send(cmd)?;
+        self.read_reply()
+    }
+
+    // Send `cmd` without waiting for its reply.
+    pub(crate) fn send(&mut self, cmd: &Cmd) -> Result<()> {
+        let mut buf = Vec::new();
+        cmd.write_to(&mut buf)?;
+        self.stream.write_all(&buf)?;
+        Ok(())
+    }
+
+    // Send all commands of `pipeline` at once and return their replies in order.
+    // Error replies are returned as `RedisType::Error` so the replies of the
+    // other commands are not lost.
+    pub fn pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RedisType>> {
+        let mut buf = Vec::new();
+        for cmd in &pipeline.cmds {
+            cmd.write_to(&mut buf)?;
+        }
+        self.stream.write_all(&buf)?;
+        pipeline.cmds.iter().map(|_| self.read_reply()).collect()
+    }
+
+    pub fn ping(&mut self) -> Result<String> {
+        self.query(&Cmd::new("PING"))
+    }
+
+    // Return the value of `key`, or `None` if it does not exist.
+    pub fn get<T: DeserializeOwned>(&mut self, key: &str) -> Result<Option<T>> {
+        self.query(&Cmd::new("GET").arg(key))
+    }
+
+    // Set `key` to `value`. Returns `false` if the key was not set because of
+    // the `NX` option.
+    pub fn set<V: ToArg + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &V,
+        options: &SetOptions,
+    ) -> Result<bool> {
+        let reply: Option<String> = self.query(&Cmd::set(key, value, options))?;
+        Ok(reply.is_some())
+    }
+
+    // Delete `keys` and return how many of them existed.
+    pub fn del(&mut self, keys: &[&str]) -> Result<i64> {
+        self.query(&Cmd::keys("DEL", keys))
+    }
+
+    // Increment the integer value of `key` and return the new value.
+    pub fn incr(&mut self, key: &str) -> Result<i64> {
+        self.query(&Cmd::new("INCR").arg(key))
+    }
+
+    // Return the values of `keys`, with `None` for keys that don't exist.
+    pub fn mget<T: DeserializeOwned>(&mut self, keys: &[&str]) -> Result<Vec<Option<T>>> {
+        self.query(&Cmd::keys("MGET", keys))
+    }
+
+    // Expire `key` after `seconds`. Returns `false` if the key does not exist.
+    pub fn expire(&mut self, key: &str, seconds: u64) -> Result<bool> {
+        self.query(&Cmd::new("EXPIRE").arg(key).arg(&seconds))
+    }
+
+    // Read the reply to the oldest command sent. RESP3 push frames coming
+    // before it, such as client tracking invalidations, are dropped.
+    pub(crate) fn read_reply<T: DeserializeOwned>(&mut self) -> Result<T> {
+        loop {
+            let len = self.read_frame()?;
+            if self.buf[0] == b'>' {
+                self.buf.drain(..len);
+                continue;
+            }
+            let reply = serde_resp::from_slice(&self.buf[..len]);
+            self.buf.drain(..len);
+            return Ok(reply?);
+        }
+    }
+
+    // Read the next frame, a reply or a push.
+    pub(crate) fn read_message<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let len = self.read_frame()?;
+        let message = serde_resp::from_slice(&self.buf[..len]);
+        self.buf.drain(..len);
+        Ok(message?)
+    }
+
+    // Read from the stream until the buffer starts with one complete frame
+    // and return its length.
+    fn read_frame(&mut self) -> Result<usize> {
+        loop {
+            if let Some(len) = self.decoder.frame_len(&self.buf)? {
+                return Ok(len);
+            }
+            let mut chunk = [0; READ_CHUNK];
+            let n = self.stream.read(&mut chunk)?;
+            if n == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::serde_resp::Decoded;
+
+    // Serve one connection, answering every command with `handler`. The
+    // commands are sent to the returned receiver as they arrive.
+    fn mock_server<F>(mut handler: F) -> (SocketAddr, mpsc::Receiver<Vec<String>>)
+    where
+        F: FnMut(&[String]) -> RedisType + Send + 'static,
+    {
+        raw_server(move |cmd| serde_resp::to_vec(&handler(cmd)).unwrap())
+    }
+
+    // Like `mock_server`, with `handler` returning the bytes to answer with.
+    fn raw_server<F>(mut handler: F) -> (SocketAddr, mpsc::Receiver<Vec<String>>)
+    where
+        F: FnMut(&[String]) -> Vec<u8> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut decoder = Decoder::new();
+            let mut buf = Vec::new();
+            let mut chunk = [0; 512];
+            loop {
+                match decoder.decode::<Vec<String>>(&buf).unwrap() {
+                    Decoded::Frame(cmd, len) => {
+                        buf.drain(..len);
+                        stream.write_all(&handler(&cmd)).unwrap();
+                        let _ = sender.send(cmd);
+                    }
+                    Decoded::Incomplete => match stream.read(&mut chunk) {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    },
+                }
+            }
+        });
+        (addr, receiver)
+    }
+
+    fn ok() -> RedisType {
+        RedisType::SimpleString("OK".to_string())
+    }
+
+    // A tiny key-value store that understands the commands of the client.
+    fn store() -> impl FnMut(&[String]) -> RedisType {
+        let mut data: HashMap<String, String> = HashMap::new();
+        move |cmd| {
+            let bulk = |value: Option<&String>| match value {
+                Some(value) => RedisType::BulkString(value.as_bytes().to_vec()),
+                None => RedisType::Null,
+            };
+            match cmd[0].as_str() {
+                "PING" => RedisType::SimpleString("PONG".to_string()),
+                "GET" => bulk(data.get(&cmd[1])),
+                "SET" => {
+                    if cmd.iter().any(|arg| arg == "NX") && data.contains_key(&cmd[1]) {
+                        return RedisType::Null;
+                    }
+                    data.insert(cmd[1].clone(), cmd[2].clone());
+                    ok()
+                }
+                "DEL" => {
+                    let n = cmd[1..].iter().filter(|k| data.remove(*k).is_some());
+                    RedisType::Integer(n.count() as i64)
+                }
+                "INCR" => match data.get(&cmd[1]).map_or(Ok(0), |v| v.parse::<i64>()) {
+                    Ok(n) => {
+                        data.insert(cmd[1].clone(), (n + 1).to_string());
+                        RedisType::Integer(n + 1)
+                    }
+                    Err(_) => {
+                        RedisType::Error("ERR value is not an integer or out of range".to_string())
+                    }
+                },
+                "MGET" => RedisType::Array(cmd[1..].iter().map(|k| bulk(data.get(k))).collect()),
+                "EXPIRE" => RedisType::Integer(data.contains_key(&cmd[1]) as i64),
+                _ => RedisType::Error(format!("ERR unknown command '{}'", cmd[0])),
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_before_reply() {
+        let (addr, _) = raw_server(|cmd| match cmd[0].as_str() {
+            "HELLO" => b"%0\r\n".to_vec(),
+            // an invalidation of client tracking arrives before the reply
+            _ => b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n+PONG\r\n".to_vec(),
+        });
+        let options = ConnectOptions::default().protocol(Protocol::Resp3);
+        let mut client = Client::connect_with(addr, &options).unwrap();
+        assert_eq!(client.protocol(), Protocol::Resp3);
+        assert_eq!(client.ping().unwrap(), "PONG");
+        assert_eq!(client.ping().unwrap(), "PONG");
+    }
+
+    #[test]
+    fn test_commands() {
+        let (addr, _) = mock_server(store());
+        let mut client = Client::connect(addr).unwrap();
+        assert_eq!(client.ping().unwrap(), "PONG");
+        assert_eq!(client.get::<String>("key").unwrap(), None);
+        assert!(client.set("key", "value", &SetOptions::default()).unwrap());
+        assert_eq!(
+            client.get::<String>("key").unwrap(),
+            Some("value".to_string())
+        );
+        assert_eq!(client.incr("counter").unwrap(), 1);
+        assert_eq!(client.incr("counter").unwrap(), 2);
+        assert_eq!(client.get::<i64>("counter").unwrap(), Some(2));
+        assert_eq!(
+            client.mget::<String>(&["key", "missing"]).unwrap(),
+            vec![Some("value".to_string()), None]
+        );
+        assert!(client.expire("key", 10).unwrap());
+        assert!(!client.expire("missing", 10).unwrap());
+        assert_eq!(client.del(&["key", "counter", "missing"]).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_set_options() {
+        let (addr, commands) = mock_server(store());
+        let mut client = Client::connect(addr).unwrap();
+        let options = SetOptions::default().ex(60).nx();
+        assert!(client.set("key", &42, &options).unwrap());
+        assert!(!client.set("key", &43, &options).unwrap());
+        assert_eq!(
+            commands.recv().unwrap(),
+            vec!["SET", "key", "42", "EX", "60", "NX"]
+        );
+        assert_eq!(client.get::<i64>("key").unwrap(), Some(42));
+    }
+
+    #[test]
+    fn test_server_error() {
+        let (addr, _) = mock_server(store());
+        let mut client = Client::connect(addr).unwrap();
+        client.set("key", "value", &SetOptions::default()).unwrap();
+        let err = client.incr("key").unwrap_err();
+        assert_eq!(err.code(), Some("ERR"));
+        assert_eq!(
+            err.to_string(),
+            "server error: ERR value is not an integer or out of range"
+        );
+        // the connection is still usable after an error reply
+        assert_eq!(client.ping().unwrap(), "PONG");
+    }
+
+    #[test]
+    fn test_unexpected_reply() {
+        let (addr, _) = mock_server(|_| RedisType::SimpleString("PONG".to_string()));
+        let mut client = Client::connect(addr).unwrap();
+        match client.incr("key") {
+            Err(Error::Protocol(_)) => {}
+            other => panic!("expected a protocol error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let (addr, _) = mock_server(store());
+        let mut client = Client::connect(addr).unwrap();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .cmd(Cmd::new("SET").arg("key").arg("value"))
+            .cmd(Cmd::new("INCR").arg("key"))
+            .cmd(Cmd::new("GET").arg("key"));
+        let replies = client.pipeline(&pipeline).unwrap();
+        assert_eq!(
+            replies,
+            vec![
+                ok(),
+                RedisType::Error("ERR value is not an integer or out of range".to_string()),
+                RedisType::BulkString(b"value".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_connect_options() {
+        let (addr, commands) = mock_server(|cmd| match cmd[0].as_str() {
+            "HELLO" => RedisType::Error("ERR unknown command 'HELLO'".to_string()),
+            _ => ok(),
+        });
+        let options = ConnectOptions::default()
+            .protocol(Protocol::Resp3)
+            .username("user")
+            .password("secret")
+            .db(2);
+        let client = Client::connect_with(addr, &options).unwrap();
+        assert_eq!(client.protocol(), Protocol::Resp2);
+        assert_eq!(
+            commands.recv().unwrap(),
+            vec!["HELLO", "3", "AUTH", "user", "secret"]
+        );
+        assert_eq!(commands.recv().unwrap(), vec!["AUTH", "user", "secret"]);
+        assert_eq!(commands.recv().unwrap(), vec!["SELECT", "2"]);
+    }
+
+    // A server that requires a password and refuses everything else before it.
+    fn auth_server(cmd: &[String]) -> RedisType {
+        match cmd {
+            [hello, _, auth, _, password] if hello == "HELLO" && auth == "AUTH" => {
+                if password == "secret" {
+                    RedisType::Map(vec![(
+                        RedisType::BulkString(b"proto".to_vec()),
+                        RedisType::Integer(3),
+                    )])
+                } else {
+                    RedisType::Error("WRONGPASS invalid username-password pair".to_string())
+                }
+            }
+            [hello, ..] if hello == "HELLO" => RedisType::Error(
+                "NOAUTH HELLO must be called with the client already authenticated".to_string(),
+            ),
+            _ => ok(),
+        }
+    }
+
+    #[test]
+    fn test_hello_with_auth() {
+        let (addr, commands) = mock_server(auth_server);
+        let options = ConnectOptions::default()
+            .protocol(Protocol::Resp3)
+            .password("secret")
+            .db(2);
+        let client = Client::connect_with(addr, &options).unwrap();
+        assert_eq!(client.protocol(), Protocol::Resp3);
+        assert_eq!(
+            commands.recv().unwrap(),
+            vec!["HELLO", "3", "AUTH", "default", "secret"]
+        );
+        assert_eq!(commands.recv().unwrap(), vec!["SELECT", "2"]);
+
+        let (addr, _) = mock_server(auth_server);
+        let options = ConnectOptions::default()
+            .protocol(Protocol::Resp3)
+            .password("wrong");
+        match Client::connect_with(addr, &options) {
+            Err(err) => assert_eq!(err.code(), Some("WRONGPASS")),
+            Ok(_) => panic!("expected HELLO to fail"),
+        }
+        let (addr, _) = mock_server(auth_server);
+        let options = ConnectOptions::default().protocol(Protocol::Resp3);
+        match Client::connect_with(addr, &options) {
+            Err(err) => assert_eq!(err.code(), Some("NOAUTH")),
+            Ok(_) => panic!("expected HELLO to fail"),
+        }
+    }
+
+    #[test]
+    fn test_auth_failure() {
+        let (addr, _) = mock_server(|_| {
+            RedisType::Error("WRONGPASS invalid username-password pair".to_string())
+        });
+        let options = ConnectOptions::default().password("wrong");
+        match Client::connect_with(addr, &options) {
+            Err(err) => assert_eq!(err.code(), Some("WRONGPASS")),
+            Ok(_) => panic!("expected AUTH to fail"),
+        }
+    }
+}
@@ -1,2 +1,12 @@
+#[cfg(feature = "aio")]
+pub mod aio;
+pub mod client;
+pub mod pubsub;
 pub mod redis;
 pub mod serde_resp;
+pub mod server;
+
+pub use client::Client;
+pub use pubsub::Subscriber;
+pub use redis::RedisType;
+pub use server::Server;
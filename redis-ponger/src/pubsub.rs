@@ -0,0 +1,388 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use crate::client::{Client, Cmd, ConnectOptions, Error, Result};
+use crate::redis::RedisType;
+use crate::serde_resp;
+
+// How often a lost connection is dialed again before `next_event` gives up,
+// and the delay before the first attempt. Each attempt waits a bit longer.
+const RECONNECT_ATTEMPTS: u32 = 10;
+const RECONNECT_DELAY: Duration = Duration::from_millis(50);
+
+// A message received by a subscriber.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A message published to a channel subscribed with `subscribe`.
+    Message { channel: String, payload: Vec<u8> },
+    /// A message published to a channel that matches a pattern subscribed with
+    /// `psubscribe`.
+    PMessage {
+        pattern: String,
+        channel: String,
+        payload: Vec<u8>,
+    },
+}
+
+// A frame the server pushes to a connection in subscriber mode.
+enum Push {
+    Event(Event),
+    // The confirmation of a (un)subscribe command.
+    Confirmation,
+}
+
+impl Push {
+    fn parse(frame: RedisType) -> Result<Push> {
+        let items = match frame {
+            RedisType::Array(items) | RedisType::Push(items) => items,
+            RedisType::Error(msg) => return Err(Error::Server(msg)),
+            frame => return Err(unexpected(&frame)),
+        };
+        let text = |item: &RedisType| match item {
+            RedisType::BulkString(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            RedisType::SimpleString(text) => Some(text.clone()),
+            _ => None,
+        };
+        let bytes = |item: &RedisType| match item {
+            RedisType::BulkString(bytes) => Some(bytes.clone()),
+            _ => None,
+        };
+        let push = match (items.first().and_then(text).as_deref(), &items[..]) {
+            (Some("message"), [_, channel, payload]) => text(channel)
+                .zip(bytes(payload))
+                .map(|(channel, payload)| Push::Event(Event::Message { channel, payload })),
+            (Some("pmessage"), [_, pattern, channel, payload]) => text(pattern)
+                .zip(text(channel))
+                .zip(bytes(payload))
+                .map(|((pattern, channel), payload)| {
+                    Push::Event(Event::PMessage {
+                        pattern,
+                        channel,
+                        payload,
+                    })
+                }),
+            (Some(kind), [_, _, RedisType::Integer(_)]) if kind.ends_with("subscribe") => {
+                Some(Push::Confirmation)
+            }
+            _ => None,
+        };
+        push.ok_or_else(|| unexpected(&RedisType::Array(items)))
+    }
+}
+
+fn unexpected(frame: &RedisType) -> Error {
+    Error::Protocol(serde_resp::Error::Message(format!(
+        "unexpected frame in subscriber mode: {:?}",
+        frame
+    )))
+}
+
+// A connection in subscriber mode, which receives the messages published to
+// its channels and patterns.
+//
+// The subscriber remembers its subscriptions. When the connection is lost,
+// `next_event` connects again and subscribes to the same channels and patterns
+// before it waits for the next message.
+pub struct Subscriber {
+    client: Client,
+    addrs: Vec<SocketAddr>,
+    options: ConnectOptions,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+    // Messages that arrived while waiting for a confirmation.
+    pending: VecDeque<Event>,
+}
+
+impl Subscriber {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Subscriber> {
+        Subscriber::connect_with(addr, &ConnectOptions::default())
+    }
+
+    pub fn connect_with<A: ToSocketAddrs>(addr: A, options: &ConnectOptions) -> Result<Subscriber> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        Ok(Subscriber {
+            client: Client::connect_with(&addrs[..], options)?,
+            addrs,
+            options: options.clone(),
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    // The channels the subscriber listens to.
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.channels.iter().map(String::as_str)
+    }
+
+    // The patterns the subscriber listens to.
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.patterns.iter().map(String::as_str)
+    }
+
+    // Subscribe to `channels` and wait until the server confirms it.
+    pub fn subscribe(&mut self, channels: &[&str]) -> Result<()> {
+        self.channels.extend(channels.iter().map(|c| c.to_string()));
+        self.request("SUBSCRIBE", channels, channels.len())
+    }
+
+    // Subscribe to the channels matching the glob-style `patterns`, such as
+    // `news.*`.
+    pub fn psubscribe(&mut self, patterns: &[&str]) -> Result<()> {
+        self.patterns.extend(patterns.iter().map(|p| p.to_string()));
+        self.request("PSUBSCRIBE", patterns, patterns.len())
+    }
+
+    // Unsubscribe from `channels`, or from all channels if it is empty.
+    pub fn unsubscribe(&mut self, channels: &[&str]) -> Result<()> {
+        let confirmations = unsubscribe(&mut self.channels, channels);
+        self.request("UNSUBSCRIBE", channels, confirmations)
+    }
+
+    // Unsubscribe from `patterns`, or from all patterns if it is empty.
+    pub fn punsubscribe(&mut self, patterns: &[&str]) -> Result<()> {
+        let confirmations = unsubscribe(&mut self.patterns, patterns);
+        self.request("PUNSUBSCRIBE", patterns, confirmations)
+    }
+
+    // Wait for the next message, reconnecting if the connection is lost.
+    pub fn next_event(&mut self) -> Result<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            match self.client.read_message().and_then(Push::parse) {
+                Ok(Push::Event(event)) => return Ok(event),
+                Ok(Push::Confirmation) => continue,
+                Err(Error::Io(_)) => self.reconnect()?,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // An endless iterator over the messages of `next_event`.
+    pub fn events(&mut self) -> Events<'_> {
+        Events { subscriber: self }
+    }
+
+    // Send `cmd` with `args` and read frames until `confirmations` replies of
+    // the command arrived.
+    fn request(&mut self, cmd: &str, args: &[&str], confirmations: usize) -> Result<()> {
+        let cmd = args.iter().fold(Cmd::new(cmd), |cmd, arg| cmd.arg(arg));
+        match self.send(&cmd, confirmations) {
+            // the subscriptions are remembered, so they are sent again
+            Err(Error::Io(_)) => self.reconnect(),
+            result => result,
+        }
+    }
+
+    fn send(&mut self, cmd: &Cmd, mut confirmations: usize) -> Result<()> {
+        self.client.send(cmd)?;
+        while confirmations > 0 {
+            match Push::parse(self.client.read_message()?)? {
+                Push::Event(event) => self.pending.push_back(event),
+                Push::Confirmation => confirmations -= 1,
+            }
+        }
+        Ok(())
+    }
+
+    // Connect again and restore the subscriptions.
+    fn reconnect(&mut self) -> Result<()> {
+        let mut last_err = Error::Io(io::ErrorKind::NotConnected.into());
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            thread::sleep(RECONNECT_DELAY * attempt);
+            match self.resubscribe() {
+                Ok(()) => return Ok(()),
+                Err(err @ Error::Io(_)) => last_err = err,
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_err)
+    }
+
+    fn resubscribe(&mut self) -> Result<()> {
+        self.client = Client::connect_with(&self.addrs[..], &self.options)?;
+        let channels = self
+            .channels
+            .iter()
+            .fold(Cmd::new("SUBSCRIBE"), |cmd, c| cmd.arg(c));
+        let patterns = self
+            .patterns
+            .iter()
+            .fold(Cmd::new("PSUBSCRIBE"), |cmd, p| cmd.arg(p));
+        if !self.channels.is_empty() {
+            self.send(&channels, self.channels.len())?;
+        }
+        if !self.patterns.is_empty() {
+            self.send(&patterns, self.patterns.len())?;
+        }
+        Ok(())
+    }
+}
+
+// Remove `names` from `subscriptions`, or all subscriptions if `names` is
+// empty, and return how many confirmations the server sends for it.
+fn unsubscribe(subscriptions: &mut BTreeSet<String>, names: &[&str]) -> usize {
+    if names.is_empty() {
+        // the server confirms with a single reply if nothing was subscribed
+        let confirmations = subscriptions.len().max(1);
+        subscriptions.clear();
+        return confirmations;
+    }
+    for name in names {
+        subscriptions.remove(*name);
+    }
+    names.len()
+}
+
+// The iterator returned by `Subscriber::events`.
+pub struct Events<'a> {
+    subscriber: &'a mut Subscriber,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        Some(self.subscriber.next_event())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::server::Server;
+
+    // Publish `payload` until a subscriber receives it. Subscribers of a new
+    // server may not have resubscribed yet.
+    fn publish(client: &mut Client, channel: &str, payload: &str) -> i64 {
+        for _ in 0..100 {
+            let cmd = Cmd::new("PUBLISH").arg(channel).arg(payload);
+            let receivers: i64 = client.query(&cmd).unwrap();
+            if receivers > 0 {
+                return receivers;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("nobody subscribed to {}", channel);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut subscriber = Subscriber::connect(server.addr()).unwrap();
+        subscriber.subscribe(&["news", "sports"]).unwrap();
+        subscriber.psubscribe(&["news.*"]).unwrap();
+        let mut publisher = Client::connect(server.addr()).unwrap();
+        assert_eq!(publish(&mut publisher, "news", "hello"), 1);
+        assert_eq!(publish(&mut publisher, "news.tech", "rust"), 1);
+        let mut events = subscriber.events();
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            Event::Message {
+                channel: "news".to_string(),
+                payload: b"hello".to_vec(),
+            }
+        );
+        assert_eq!(
+            events.next().unwrap().unwrap(),
+            Event::PMessage {
+                pattern: "news.*".to_string(),
+                channel: "news.tech".to_string(),
+                payload: b"rust".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut subscriber = Subscriber::connect(server.addr()).unwrap();
+        subscriber.subscribe(&["a", "b", "c"]).unwrap();
+        subscriber.unsubscribe(&["a"]).unwrap();
+        assert_eq!(subscriber.channels().collect::<Vec<_>>(), vec!["b", "c"]);
+        let mut publisher = Client::connect(server.addr()).unwrap();
+        let receivers: i64 = publisher
+            .query(&Cmd::new("PUBLISH").arg("a").arg("lost"))
+            .unwrap();
+        assert_eq!(receivers, 0);
+        subscriber.unsubscribe(&[]).unwrap();
+        assert_eq!(subscriber.channels().count(), 0);
+        subscriber.punsubscribe(&[]).unwrap();
+        let receivers: i64 = publisher
+            .query(&Cmd::new("PUBLISH").arg("b").arg("lost"))
+            .unwrap();
+        assert_eq!(receivers, 0);
+    }
+
+    #[test]
+    fn test_resubscribe_on_reconnect() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let addr = server.addr();
+        let mut subscriber = Subscriber::connect(addr).unwrap();
+        subscriber.subscribe(&["news"]).unwrap();
+        subscriber.psubscribe(&["sports.*"]).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            for _ in 0..2 {
+                sender.send(subscriber.next_event().unwrap()).unwrap();
+            }
+        });
+
+        // restart the server, which drops the connection of the subscriber
+        drop(server);
+        let server = Server::start(addr).unwrap();
+        let mut publisher = Client::connect(server.addr()).unwrap();
+        publish(&mut publisher, "news", "back");
+        assert_eq!(
+            receiver.recv().unwrap(),
+            Event::Message {
+                channel: "news".to_string(),
+                payload: b"back".to_vec(),
+            }
+        );
+        publish(&mut publisher, "sports.tennis", "again");
+        assert_eq!(
+            receiver.recv().unwrap(),
+            Event::PMessage {
+                pattern: "sports.*".to_string(),
+                channel: "sports.tennis".to_string(),
+                payload: b"again".to_vec(),
+            }
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_push() {
+        let push = RedisType::Push(vec![
+            RedisType::BulkString(b"message".to_vec()),
+            RedisType::BulkString(b"news".to_vec()),
+            RedisType::BulkString(b"hi".to_vec()),
+        ]);
+        match Push::parse(push) {
+            Ok(Push::Event(event)) => assert_eq!(
+                event,
+                Event::Message {
+                    channel: "news".to_string(),
+                    payload: b"hi".to_vec(),
+                }
+            ),
+            _ => panic!("expected a message"),
+        }
+        assert!(matches!(
+            Push::parse(RedisType::Integer(1)),
+            Err(Error::Protocol(_))
+        ));
+        assert!(matches!(
+            Push::parse(RedisType::Error("ERR oops".to_string())),
+            Err(Error::Server(_))
+        ));
+    }
+}
@@ -1,34 +1,318 @@
-use serde::ser::SerializeSeq;
-use serde::Serializer;
+use std::fmt;
 
-#[derive(Debug)]
+use serde::de::{self, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::ser::{SerializeSeq, SerializeTupleStruct};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::serde_resp::{
+    ATTRIBUTE_TOKEN, BIG_NUMBER_TOKEN, ERROR_TOKEN, PUSH_TOKEN, SET_TOKEN, SIMPLE_STRING_TOKEN,
+    VERBATIM_STRING_TOKEN,
+};
+
+// The most elements preallocated for an array or a map. The length comes from
+// the peer, which may declare far more elements than it sends.
+const MAX_PREALLOCATED: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum RedisType {
     SimpleString(String),
     Error(String),
     Integer(i64),
-    BulkString(String),
+    BulkString(Vec<u8>),
     Array(Vec<RedisType>),
     Null,
+    // The types added by RESP3. Serialized as RESP2 they degrade to the types
+    // Redis uses for the same replies in RESP2, e.g. maps become flat arrays
+    // and booleans the integers 0 and 1.
+    Map(Vec<(RedisType, RedisType)>),
+    Set(Vec<RedisType>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    // A string with its format, `txt` or `mkd`, such as the reply of `INFO`.
+    VerbatimString {
+        format: String,
+        text: Vec<u8>,
+    },
+    // Metadata about the reply `value`, such as key popularity.
+    Attribute {
+        attributes: Vec<(RedisType, RedisType)>,
+        value: Box<RedisType>,
+    },
+    // An out of band message, such as a pub/sub message or an invalidation.
+    Push(Vec<RedisType>),
 }
 
-impl serde::Serialize for RedisType {
+impl Serialize for RedisType {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         match self {
-            RedisType::SimpleString(s) => serializer.serialize_str(s),
-            RedisType::Error(e) => serializer.serialize_str(e),
+            RedisType::SimpleString(s) => {
+                serializer.serialize_newtype_struct(SIMPLE_STRING_TOKEN, s)
+            }
+            RedisType::Error(e) => serializer.serialize_newtype_struct(ERROR_TOKEN, e),
             RedisType::Integer(n) => serializer.serialize_i64(*n),
-            RedisType::BulkString(s) => serializer.serialize_str(s),
+            RedisType::BulkString(b) => serializer.serialize_bytes(b),
             RedisType::Array(v) => {
                 let mut seq = serializer.serialize_seq(Some(v.len()))?;
-                for redisType in v {
-                    seq.serialize_element(redisType);
+                for redis_type in v {
+                    seq.serialize_element(redis_type)?;
                 }
                 seq.end()
             }
             RedisType::Null => serializer.serialize_unit(),
+            RedisType::Map(pairs) => Pairs(pairs).serialize(serializer),
+            RedisType::Set(v) => serialize_tagged_seq(serializer, SET_TOKEN, v),
+            RedisType::Double(f) => serializer.serialize_f64(*f),
+            RedisType::Boolean(b) => serializer.serialize_bool(*b),
+            RedisType::BigNumber(n) => serializer.serialize_newtype_struct(BIG_NUMBER_TOKEN, n),
+            RedisType::VerbatimString { format, text } => {
+                let mut bytes = Vec::with_capacity(format.len() + 1 + text.len());
+                bytes.extend_from_slice(format.as_bytes());
+                bytes.push(b':');
+                bytes.extend_from_slice(text);
+                serializer.serialize_newtype_struct(VERBATIM_STRING_TOKEN, &Bytes(&bytes))
+            }
+            RedisType::Attribute { attributes, value } => {
+                let mut seq = serializer.serialize_tuple_struct(ATTRIBUTE_TOKEN, 2)?;
+                seq.serialize_field(&Pairs(attributes))?;
+                seq.serialize_field(value)?;
+                seq.end()
+            }
+            RedisType::Push(v) => serialize_tagged_seq(serializer, PUSH_TOKEN, v),
+        }
+    }
+}
+
+fn serialize_tagged_seq<S>(
+    serializer: S,
+    name: &'static str,
+    v: &[RedisType],
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let mut seq = serializer.serialize_tuple_struct(name, v.len())?;
+    for redis_type in v {
+        seq.serialize_field(redis_type)?;
+    }
+    seq.end()
+}
+
+// Key value pairs serialized as a map.
+struct Pairs<'a>(&'a [(RedisType, RedisType)]);
+
+impl<'a> Serialize for Pairs<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+// Bytes deserialized from a bulk string.
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ByteBufVisitor;
+
+        impl<'de> Visitor<'de> for ByteBufVisitor {
+            type Value = ByteBuf;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("bytes")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<ByteBuf, E>
+            where
+                E: de::Error,
+            {
+                Ok(ByteBuf(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<ByteBuf, E>
+            where
+                E: de::Error,
+            {
+                Ok(ByteBuf(v))
+            }
+        }
+
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for RedisType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(RedisTypeVisitor)
+    }
+}
+
+struct RedisTypeVisitor;
+
+impl<'de> Visitor<'de> for RedisTypeVisitor {
+    type Value = RedisType;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a RESP value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::Boolean(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::SimpleString(v.to_owned()))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::Integer(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        if v > i64::MAX as u64 {
+            return Ok(RedisType::BigNumber(v.to_string()));
+        }
+        Ok(RedisType::Integer(v as i64))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::Double(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::BulkString(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::BulkString(v))
+    }
+
+    fn visit_none<E>(self) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::Null)
+    }
+
+    fn visit_unit<E>(self) -> Result<RedisType, E>
+    where
+        E: de::Error,
+    {
+        Ok(RedisType::Null)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<RedisType, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut v = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(MAX_PREALLOCATED));
+        while let Some(redis_type) = seq.next_element()? {
+            v.push(redis_type);
+        }
+        Ok(RedisType::Array(v))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<RedisType, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut pairs = Vec::with_capacity(map.size_hint().unwrap_or(0).min(MAX_PREALLOCATED));
+        while let Some(pair) = map.next_entry()? {
+            pairs.push(pair);
+        }
+        Ok(RedisType::Map(pairs))
+    }
+
+    // Types without a counterpart in the serde data model are passed by the
+    // RESP deserializer as newtype variants named like the variants of
+    // `RedisType`.
+    fn visit_enum<A>(self, data: A) -> Result<RedisType, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        const VARIANTS: &[&str] = &[
+            "Error",
+            "BigNumber",
+            "VerbatimString",
+            "Set",
+            "Push",
+            "Attribute",
+        ];
+        let (variant, value): (String, _) = data.variant()?;
+        match variant.as_str() {
+            "Error" => value.newtype_variant().map(RedisType::Error),
+            "BigNumber" => value.newtype_variant().map(RedisType::BigNumber),
+            "VerbatimString" => {
+                let ByteBuf(mut bytes) = value.newtype_variant()?;
+                if bytes.len() < 4 || bytes[3] != b':' {
+                    return Err(de::Error::invalid_value(
+                        de::Unexpected::Bytes(&bytes),
+                        &"a verbatim string starting with its format",
+                    ));
+                }
+                let text = bytes.split_off(4);
+                bytes.truncate(3);
+                let format = String::from_utf8(bytes).map_err(|e| {
+                    de::Error::invalid_value(de::Unexpected::Bytes(e.as_bytes()), &"a format")
+                })?;
+                Ok(RedisType::VerbatimString { format, text })
+            }
+            "Set" => value.newtype_variant().map(RedisType::Set),
+            "Push" => value.newtype_variant().map(RedisType::Push),
+            "Attribute" => {
+                let (attributes, value): (RedisType, RedisType) = value.newtype_variant()?;
+                match attributes {
+                    RedisType::Map(attributes) => Ok(RedisType::Attribute {
+                        attributes,
+                        value: Box::new(value),
+                    }),
+                    _ => Err(de::Error::custom("expected the map of an attribute")),
+                }
+            }
+            _ => Err(de::Error::unknown_variant(&variant, VARIANTS)),
         }
     }
 }
@@ -36,11 +320,101 @@ impl serde::Serialize for RedisType {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::serde_resp;
+    use crate::serde_resp::{self, Protocol};
+
+    fn to_resp3(value: &RedisType) -> Vec<u8> {
+        let mut serializer = serde_resp::Serializer::new(Vec::new(), Protocol::Resp3);
+        value.serialize(&mut serializer).unwrap();
+        serializer.into_inner()
+    }
+
+    fn resp3_types() -> Vec<(RedisType, &'static [u8], &'static [u8])> {
+        vec![
+            (RedisType::Null, b"_\r\n", b"$-1\r\n"),
+            (RedisType::Boolean(true), b"#t\r\n", b":1\r\n"),
+            (RedisType::Double(-1.5), b",-1.5\r\n", b"$4\r\n-1.5\r\n"),
+            (
+                RedisType::BigNumber("123456789012345678901234567890".to_string()),
+                b"(123456789012345678901234567890\r\n",
+                b"$30\r\n123456789012345678901234567890\r\n",
+            ),
+            (
+                RedisType::VerbatimString {
+                    format: "txt".to_string(),
+                    text: b"Some string".to_vec(),
+                },
+                b"=15\r\ntxt:Some string\r\n",
+                b"$11\r\nSome string\r\n",
+            ),
+            (
+                RedisType::Map(vec![(
+                    RedisType::SimpleString("first".to_string()),
+                    RedisType::Integer(1),
+                )]),
+                b"%1\r\n+first\r\n:1\r\n",
+                b"*2\r\n+first\r\n:1\r\n",
+            ),
+            (
+                RedisType::Set(vec![RedisType::Integer(1), RedisType::Integer(2)]),
+                b"~2\r\n:1\r\n:2\r\n",
+                b"*2\r\n:1\r\n:2\r\n",
+            ),
+            (
+                RedisType::Push(vec![RedisType::BulkString(b"message".to_vec())]),
+                b">1\r\n$7\r\nmessage\r\n",
+                b"*1\r\n$7\r\nmessage\r\n",
+            ),
+            (
+                RedisType::Attribute {
+                    attributes: vec![(
+                        RedisType::SimpleString("ttl".to_string()),
+                        RedisType::Integer(3600),
+                    )],
+                    value: Box::new(RedisType::Integer(7)),
+                },
+                b"|1\r\n+ttl\r\n:3600\r\n:7\r\n",
+                b":7\r\n",
+            ),
+        ]
+    }
 
     #[test]
     fn test_serialize_redis_type() {
         let actual = serde_resp::to_string(&RedisType::SimpleString("foo".to_string())).unwrap();
         assert_eq!(actual, "+foo\r\n");
     }
+
+    #[test]
+    fn test_resp3_round_trip() {
+        for (value, resp3, _) in resp3_types() {
+            assert_eq!(to_resp3(&value), resp3, "{:?}", value);
+            assert_eq!(serde_resp::from_slice::<RedisType>(resp3), Ok(value));
+        }
+    }
+
+    #[test]
+    fn test_resp3_as_resp2() {
+        for (value, _, resp2) in resp3_types() {
+            assert_eq!(serde_resp::to_vec(&value).unwrap(), resp2, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_resp3_blob_error() {
+        let actual = serde_resp::from_slice::<RedisType>(b"!21\r\nSYNTAX invalid syntax\r\n");
+        assert_eq!(
+            actual,
+            Ok(RedisType::Error("SYNTAX invalid syntax".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_declared_length_is_not_preallocated() {
+        let actual = serde_resp::from_slice::<RedisType>(b"*9223372036854775807\r\n");
+        assert_eq!(actual, Err(serde_resp::Error::Eof));
+        let actual = serde_resp::from_slice::<RedisType>(b"*100000000000\r\n:1\r\n");
+        assert_eq!(actual, Err(serde_resp::Error::Eof));
+        let actual = serde_resp::from_slice::<RedisType>(b"%100000000000\r\n:1\r\n:2\r\n");
+        assert_eq!(actual, Err(serde_resp::Error::Eof));
+    }
 }
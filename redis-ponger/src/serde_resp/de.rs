@@ -0,0 +1,1075 @@
+use std::str::{self, FromStr};
+
+use serde::de::value::{
+    BorrowedBytesDeserializer, BorrowedStrDeserializer, MapAccessDeserializer,
+    SeqAccessDeserializer,
+};
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IgnoredAny, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::Deserialize;
+
+use super::error::{Error, Result};
+
+pub struct Deserializer<'de> {
+    // The unread input. Values are consumed from the front as they are
+    // deserialized.
+    input: &'de [u8],
+    // How many more arrays, maps and other containers may be nested in the
+    // current one. Deeper values fail with `Error::TooDeep` instead of
+    // overflowing the stack.
+    remaining_depth: usize,
+}
+
+// As deep as serde_json lets values nest.
+const MAX_DEPTH: usize = 128;
+
+impl<'de> Deserializer<'de> {
+    pub fn from_slice(input: &'de [u8]) -> Self {
+        Deserializer {
+            input,
+            remaining_depth: MAX_DEPTH,
+        }
+    }
+
+    // Set how deep arrays, maps and other containers may nest, `MAX_DEPTH` by
+    // default.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.remaining_depth = max_depth;
+        self
+    }
+
+    // Deserialize the input, which must hold exactly one value.
+    pub(crate) fn deserialize_whole<T: Deserialize<'de>>(mut self) -> Result<T> {
+        let t = T::deserialize(&mut self)?;
+        if self.input.is_empty() {
+            Ok(t)
+        } else {
+            Err(Error::TrailingBytes)
+        }
+    }
+}
+
+// Deserialize exactly one RESP value from the input. Incomplete input fails with
+// `Error::Eof` so the caller can read more bytes and try again.
+pub fn from_slice<'a, T>(input: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    Deserializer::from_slice(input).deserialize_whole()
+}
+
+pub fn from_str<'a, T>(input: &'a str) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_slice(input.as_bytes())
+}
+
+// Parsing methods that consume the input. They are used by the `Deserializer`
+// trait implementation below.
+impl<'de> Deserializer<'de> {
+    fn peek_byte(&self) -> Result<u8> {
+        self.input.first().cloned().ok_or(Error::Eof)
+    }
+
+    fn next_byte(&mut self) -> Result<u8> {
+        let byte = self.peek_byte()?;
+        self.input = &self.input[1..];
+        Ok(byte)
+    }
+
+    // Consume a line and return it without the trailing `\r\n`.
+    fn parse_line(&mut self) -> Result<&'de [u8]> {
+        let end = self
+            .input
+            .iter()
+            .position(|&b| b == b'\r')
+            .ok_or(Error::Eof)?;
+        match self.input.get(end + 1) {
+            Some(b'\n') => {}
+            Some(_) => return Err(Error::ExpectedCrlf),
+            None => return Err(Error::Eof),
+        }
+        let line = &self.input[..end];
+        self.input = &self.input[end + 2..];
+        Ok(line)
+    }
+
+    // Deserialize the contents of a container with `f`, one level deeper.
+    fn nested<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        if self.remaining_depth == 0 {
+            return Err(Error::TooDeep);
+        }
+        self.remaining_depth -= 1;
+        let value = f(self);
+        self.remaining_depth += 1;
+        value
+    }
+
+    fn parse_int_line(&mut self) -> Result<i64> {
+        parse_int(self.parse_line()?)
+    }
+
+    // Consume the length line of a bulk string or an array. The null length -1
+    // is returned as `None`.
+    fn parse_len(&mut self) -> Result<Option<usize>> {
+        match self.parse_int_line()? {
+            -1 => Ok(None),
+            len if len >= 0 => Ok(Some(len as usize)),
+            _ => Err(Error::InvalidLength),
+        }
+    }
+
+    // Consume the length line of a map or an attribute and return the number
+    // of keys and values.
+    fn parse_pairs_len(&mut self) -> Result<usize> {
+        let len = self.parse_len()?.ok_or(Error::ExpectedMapPairs)?;
+        len.checked_mul(2).ok_or(Error::InvalidLength)
+    }
+
+    // Consume the data of a bulk string of `len` bytes and the `\r\n` after it.
+    fn parse_bulk(&mut self, len: usize) -> Result<&'de [u8]> {
+        if self.input.len() < len + 2 {
+            return Err(Error::Eof);
+        }
+        if &self.input[len..len + 2] != b"\r\n" {
+            return Err(Error::ExpectedCrlf);
+        }
+        let bytes = &self.input[..len];
+        self.input = &self.input[len + 2..];
+        Ok(bytes)
+    }
+
+    // Consume an error line and return it as `Error::Redis`.
+    fn parse_error_reply(&mut self) -> Error {
+        match self.parse_line().and_then(to_str) {
+            Ok(msg) => Error::Redis(msg.to_owned()),
+            Err(e) => e,
+        }
+    }
+
+    // Consume the length line and data of a bulk string, blob error or
+    // verbatim string that is not null.
+    fn parse_blob(&mut self) -> Result<&'de [u8]> {
+        match self.parse_len()? {
+            Some(len) => self.parse_bulk(len),
+            None => Err(Error::ExpectedString),
+        }
+    }
+
+    // Consume the RESP3 attributes in front of a value. They are metadata
+    // about the reply, which typed deserialization ignores.
+    fn skip_attributes(&mut self) -> Result<()> {
+        while self.peek_byte()? == b'|' {
+            self.next_byte()?;
+            let len = self.parse_pairs_len()?;
+            for _ in 0..len {
+                IgnoredAny::deserialize(&mut *self)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Consume a simple string, a bulk string that is not null or a verbatim
+    // string. The format of a verbatim string, such as `txt:`, is dropped.
+    fn parse_bytes(&mut self) -> Result<&'de [u8]> {
+        self.skip_attributes()?;
+        match self.next_byte()? {
+            b'+' => self.parse_line(),
+            b'$' => self.parse_blob(),
+            b'=' => {
+                let bytes = self.parse_blob()?;
+                Ok(bytes.get(4..).unwrap_or_default())
+            }
+            b'-' => Err(self.parse_error_reply()),
+            b'!' => Err(Error::Redis(to_str(self.parse_blob()?)?.to_owned())),
+            _ => Err(Error::ExpectedString),
+        }
+    }
+
+    fn parse_str(&mut self) -> Result<&'de str> {
+        to_str(self.parse_bytes()?)
+    }
+
+    // Consume an integer. Redis also replies numbers as strings, for example
+    // `GET` of a counter, so those are accepted as well.
+    fn parse_integer<T: FromStr>(&mut self) -> Result<T> {
+        self.skip_attributes()?;
+        let line = match self.peek_byte()? {
+            b':' | b'(' => {
+                self.next_byte()?;
+                self.parse_line()?
+            }
+            b'+' | b'$' | b'=' | b'-' | b'!' => self.parse_bytes()?,
+            _ => return Err(Error::ExpectedInteger),
+        };
+        let line = to_str(line)?;
+        line.parse().map_err(|_| {
+            let digits = line.strip_prefix('-').unwrap_or(line);
+            if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+                Error::IntegerOutOfRange
+            } else {
+                Error::InvalidInteger
+            }
+        })
+    }
+
+    fn parse_float(&mut self) -> Result<f64> {
+        self.skip_attributes()?;
+        let line = match self.peek_byte()? {
+            b':' | b',' | b'(' => {
+                self.next_byte()?;
+                self.parse_line()?
+            }
+            b'+' | b'$' | b'=' | b'-' | b'!' => self.parse_bytes()?,
+            _ => return Err(Error::ExpectedInteger),
+        };
+        to_str(line)?.parse().map_err(|_| Error::InvalidInteger)
+    }
+
+    // Consume the header of an array, set or push message that is not null and
+    // return its length.
+    fn parse_array_len(&mut self) -> Result<usize> {
+        self.skip_attributes()?;
+        match self.next_byte()? {
+            b'*' | b'~' | b'>' => self.parse_len()?.ok_or(Error::ExpectedArray),
+            b'-' => Err(self.parse_error_reply()),
+            b'!' => Err(Error::Redis(to_str(self.parse_blob()?)?.to_owned())),
+            _ => Err(Error::ExpectedArray),
+        }
+    }
+
+    // Consume the header of a map and return the number of keys and values. In
+    // RESP2 maps are flat arrays of alternating keys and values.
+    fn parse_map_len(&mut self) -> Result<usize> {
+        self.skip_attributes()?;
+        if self.peek_byte()? == b'%' {
+            self.next_byte()?;
+            return self.parse_pairs_len();
+        }
+        let len = self.parse_array_len()?;
+        if len % 2 != 0 {
+            return Err(Error::ExpectedMapPairs);
+        }
+        Ok(len)
+    }
+
+    // Consume a null, if the input starts with one. RESP2 has the null bulk
+    // string and the null array, RESP3 has a type of its own.
+    fn parse_null(&mut self) -> Result<bool> {
+        self.skip_attributes()?;
+        for null in [&b"$-1\r\n"[..], b"*-1\r\n", b"_\r\n"].iter() {
+            if self.input.starts_with(null) {
+                self.input = &self.input[null.len()..];
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+fn to_str(bytes: &[u8]) -> Result<&str> {
+    str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)
+}
+
+fn parse_int(line: &[u8]) -> Result<i64> {
+    to_str(line)?.parse().map_err(|_| Error::InvalidInteger)
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    // RESP is self-describing, so the type prefix decides how the value is
+    // passed to the visitor:
+    //
+    //   +simple string  -> str
+    //   -error          -> enum variant `Error` holding the message
+    //   :integer        -> i64
+    //   $bulk string    -> bytes, or none for `$-1`
+    //   *array          -> seq, or none for `*-1`
+    //
+    // and the RESP3 types:
+    //
+    //   _null           -> none
+    //   #boolean        -> bool
+    //   ,double         -> f64
+    //   %map            -> map
+    //   !blob error     -> enum variant `Error` holding the message
+    //   (big number     -> enum variant `BigNumber` holding the digits
+    //   =verbatim       -> enum variant `VerbatimString` holding `fmt:text`
+    //   ~set            -> enum variant `Set` holding a seq
+    //   >push           -> enum variant `Push` holding a seq
+    //   |attribute      -> enum variant `Attribute` holding a seq of the
+    //                      attribute map and the value after it
+    //
+    // The variant names are those of `RedisType`.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let prefix = self.peek_byte()?;
+        match prefix {
+            b'+' => visitor.visit_borrowed_str(self.parse_str()?),
+            b'-' => {
+                self.next_byte()?;
+                let msg = to_str(self.parse_line()?)?;
+                visitor.visit_enum(Tagged {
+                    variant: "Error",
+                    value: TaggedValue::Str(msg),
+                    de: self,
+                })
+            }
+            b'!' => {
+                self.next_byte()?;
+                let msg = to_str(self.parse_blob()?)?;
+                visitor.visit_enum(Tagged {
+                    variant: "Error",
+                    value: TaggedValue::Str(msg),
+                    de: self,
+                })
+            }
+            b'(' => {
+                self.next_byte()?;
+                let digits = to_str(self.parse_line()?)?;
+                visitor.visit_enum(Tagged {
+                    variant: "BigNumber",
+                    value: TaggedValue::Str(digits),
+                    de: self,
+                })
+            }
+            b'=' => {
+                self.next_byte()?;
+                let bytes = self.parse_blob()?;
+                visitor.visit_enum(Tagged {
+                    variant: "VerbatimString",
+                    value: TaggedValue::Bytes(bytes),
+                    de: self,
+                })
+            }
+            b'~' | b'>' | b'|' => {
+                self.next_byte()?;
+                let len = self.parse_len()?.ok_or(Error::InvalidLength)?;
+                let (variant, value) = match prefix {
+                    b'~' => ("Set", TaggedValue::Array(len)),
+                    b'>' => ("Push", TaggedValue::Array(len)),
+                    _ => ("Attribute", TaggedValue::Attribute(len)),
+                };
+                self.nested(|de| visitor.visit_enum(Tagged { variant, value, de }))
+            }
+            b'_' => {
+                self.next_byte()?;
+                if !self.parse_line()?.is_empty() {
+                    return Err(Error::ExpectedNull);
+                }
+                visitor.visit_none()
+            }
+            b'#' => {
+                self.next_byte()?;
+                match self.parse_line()? {
+                    b"t" => visitor.visit_bool(true),
+                    b"f" => visitor.visit_bool(false),
+                    _ => Err(Error::ExpectedBoolean),
+                }
+            }
+            b',' => visitor.visit_f64(self.parse_float()?),
+            b'%' => {
+                let len = self.parse_map_len()?;
+                self.nested(|de| visit_elements(de, len, |elements| visitor.visit_map(elements)))
+            }
+            b':' => {
+                self.next_byte()?;
+                visitor.visit_i64(self.parse_int_line()?)
+            }
+            b'$' => {
+                self.next_byte()?;
+                match self.parse_len()? {
+                    Some(len) => visitor.visit_borrowed_bytes(self.parse_bulk(len)?),
+                    None => visitor.visit_none(),
+                }
+            }
+            b'*' => {
+                self.next_byte()?;
+                match self.parse_len()? {
+                    Some(len) => self.nested(|de| {
+                        visit_elements(de, len, |elements| visitor.visit_seq(elements))
+                    }),
+                    None => visitor.visit_none(),
+                }
+            }
+            byte => Err(Error::InvalidPrefix(byte)),
+        }
+    }
+
+    // Booleans are RESP3 booleans or the integers 0 and 1.
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.skip_attributes()?;
+        if self.peek_byte()? == b'#' {
+            self.next_byte()?;
+            return match self.parse_line()? {
+                b"t" => visitor.visit_bool(true),
+                b"f" => visitor.visit_bool(false),
+                _ => Err(Error::ExpectedBoolean),
+            };
+        }
+        match self.parse_integer::<i64>()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(Error::ExpectedBoolean),
+        }
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i8(self.parse_integer()?)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i16(self.parse_integer()?)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i32(self.parse_integer()?)
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i64(self.parse_integer()?)
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i128(self.parse_integer()?)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.parse_integer()?)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u16(self.parse_integer()?)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.parse_integer()?)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u64(self.parse_integer()?)
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u128(self.parse_integer()?)
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f32(self.parse_float()? as f32)
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f64(self.parse_float()?)
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let s = self.parse_str()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(de::Error::invalid_value(de::Unexpected::Str(s), &visitor)),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.parse_str()?)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.parse_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    // A null is `None`, anything else is `Some`.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.parse_null()? {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.parse_null()? {
+            visitor.visit_unit()
+        } else {
+            Err(Error::ExpectedNull)
+        }
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let len = self.parse_array_len()?;
+        self.nested(|de| visit_elements(de, len, |elements| visitor.visit_seq(elements)))
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    // Maps are RESP3 maps or flat arrays of alternating keys and values, like
+    // the RESP2 replies of `HGETALL` and `CONFIG GET`.
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let len = self.parse_map_len()?;
+        self.nested(|de| visit_elements(de, len, |elements| visitor.visit_map(elements)))
+    }
+
+    // Structs are maps keyed by field name.
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    // A unit variant is its name as a string. Other variants are an array of the
+    // name and the value.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.skip_attributes()?;
+        match self.peek_byte()? {
+            b'+' | b'$' | b'=' => {
+                let variant: &str = self.parse_str()?;
+                visitor.visit_enum(variant.into_deserializer())
+            }
+            b'*' => {
+                if self.parse_array_len()? != 2 {
+                    return Err(Error::ExpectedEnum);
+                }
+                self.nested(|de| visitor.visit_enum(Enum { de }))
+            }
+            // an error reply is returned as the error
+            b'-' | b'!' => match self.parse_bytes() {
+                Err(err) => Err(err),
+                Ok(_) => Err(Error::ExpectedEnum),
+            },
+            _ => Err(Error::ExpectedEnum),
+        }
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+}
+
+// Pass the next `len` values to `visit` as the elements of an array and check
+// that all of them were consumed.
+fn visit_elements<'a, 'de, T, F>(de: &'a mut Deserializer<'de>, len: usize, visit: F) -> Result<T>
+where
+    F: FnOnce(&mut Elements<'a, 'de>) -> Result<T>,
+{
+    let mut elements = Elements { de, remaining: len };
+    let value = visit(&mut elements)?;
+    if elements.remaining == 0 {
+        Ok(value)
+    } else {
+        Err(Error::TrailingElements)
+    }
+}
+
+// The elements of an array, visited as a sequence or as a map of alternating
+// keys and values.
+struct Elements<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Elements<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for Elements<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        self.next_element_seed(seed)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining / 2)
+    }
+}
+
+// An enum variant other than a unit variant: an array of the variant name and
+// the value.
+struct Enum<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Deserialize::deserialize(self.de)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_map(self.de, visitor)
+    }
+}
+
+// A RESP3 type without a counterpart in the serde data model, visited by
+// `deserialize_any` as a newtype variant.
+struct Tagged<'a, 'de: 'a> {
+    variant: &'static str,
+    value: TaggedValue<'de>,
+    de: &'a mut Deserializer<'de>,
+}
+
+enum TaggedValue<'de> {
+    Str(&'de str),
+    Bytes(&'de [u8]),
+    // The length of an array whose elements follow.
+    Array(usize),
+    // The number of attributes whose keys and values follow, and then the
+    // value they describe.
+    Attribute(usize),
+}
+
+impl<'de, 'a> EnumAccess<'de> for Tagged<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for Tagged<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Err(de::Error::invalid_type(
+            de::Unexpected::NewtypeVariant,
+            &"unit variant",
+        ))
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.value {
+            TaggedValue::Str(s) => seed.deserialize(BorrowedStrDeserializer::<Error>::new(s)),
+            TaggedValue::Bytes(b) => seed.deserialize(BorrowedBytesDeserializer::<Error>::new(b)),
+            TaggedValue::Array(len) => visit_elements(self.de, len, |elements| {
+                seed.deserialize(SeqAccessDeserializer::new(elements))
+            }),
+            TaggedValue::Attribute(len) => {
+                seed.deserialize(SeqAccessDeserializer::new(Attribute {
+                    de: self.de,
+                    len,
+                    index: 0,
+                }))
+            }
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(
+            de::Unexpected::NewtypeVariant,
+            &visitor,
+        ))
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(
+            de::Unexpected::NewtypeVariant,
+            &visitor,
+        ))
+    }
+}
+
+// An attribute visited as a sequence of the attribute map and the value after
+// it.
+struct Attribute<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    len: usize,
+    index: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for Attribute<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.index += 1;
+        match self.index {
+            1 => visit_elements(self.de, self.len * 2, |elements| {
+                seed.deserialize(MapAccessDeserializer::new(elements))
+            })
+            .map(Some),
+            2 => seed.deserialize(&mut *self.de).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(2usize.saturating_sub(self.index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use crate::redis::RedisType;
+
+    use super::*;
+
+    #[test]
+    fn test_deserialize_redis_type_simple_string() {
+        let actual: RedisType = from_str("+OK\r\n").unwrap();
+        assert_eq!(actual, RedisType::SimpleString("OK".to_string()));
+    }
+
+    #[test]
+    fn test_deserialize_redis_type_error() {
+        let actual: RedisType = from_str("-ERR unknown command\r\n").unwrap();
+        assert_eq!(actual, RedisType::Error("ERR unknown command".to_string()));
+    }
+
+    #[test]
+    fn test_deserialize_redis_type_integer() {
+        let actual: RedisType = from_str(":-42\r\n").unwrap();
+        assert_eq!(actual, RedisType::Integer(-42));
+    }
+
+    #[test]
+    fn test_deserialize_redis_type_bulk_string() {
+        let actual: RedisType = from_str("$7\r\nfoo\r\nba\r\n").unwrap();
+        assert_eq!(actual, RedisType::BulkString(b"foo\r\nba".to_vec()));
+        let actual: RedisType = from_str("$0\r\n\r\n").unwrap();
+        assert_eq!(actual, RedisType::BulkString(b"".to_vec()));
+    }
+
+    #[test]
+    fn test_deserialize_redis_type_null() {
+        let actual: RedisType = from_str("$-1\r\n").unwrap();
+        assert_eq!(actual, RedisType::Null);
+        let actual: RedisType = from_str("*-1\r\n").unwrap();
+        assert_eq!(actual, RedisType::Null);
+    }
+
+    #[test]
+    fn test_deserialize_redis_type_nested_array() {
+        let actual: RedisType = from_str("*3\r\n:1\r\n*2\r\n+a\r\n$-1\r\n*0\r\n").unwrap();
+        assert_eq!(
+            actual,
+            RedisType::Array(vec![
+                RedisType::Integer(1),
+                RedisType::Array(vec![
+                    RedisType::SimpleString("a".to_string()),
+                    RedisType::Null,
+                ]),
+                RedisType::Array(vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_deserialize_typed() {
+        assert_eq!(from_str::<i64>(":64\r\n"), Ok(64));
+        assert_eq!(from_str::<u8>("$2\r\n12\r\n"), Ok(12));
+        assert_eq!(from_str::<String>("$3\r\nfoo\r\n"), Ok("foo".to_string()));
+        assert_eq!(from_str::<Option<String>>("$-1\r\n"), Ok(None));
+        assert_eq!(
+            from_str::<Vec<Option<i64>>>("*2\r\n:1\r\n$-1\r\n"),
+            Ok(vec![Some(1), None])
+        );
+        let map: BTreeMap<String, i64> = from_str("*4\r\n+a\r\n:1\r\n+b\r\n:2\r\n").unwrap();
+        assert_eq!(map.get("b"), Some(&2));
+    }
+
+    #[test]
+    fn test_deserialize_struct_and_enum() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Point {
+            x: i64,
+            y: i64,
+        }
+        #[derive(Deserialize, PartialEq, Debug)]
+        enum Shape {
+            Empty,
+            Dot(Point),
+        }
+        assert_eq!(from_str::<Shape>("+Empty\r\n"), Ok(Shape::Empty));
+        assert_eq!(
+            from_str::<Shape>("*2\r\n+Dot\r\n*4\r\n+y\r\n:2\r\n+x\r\n:1\r\n"),
+            Ok(Shape::Dot(Point { x: 1, y: 2 }))
+        );
+    }
+
+    #[test]
+    fn test_deserialize_typed_resp3() {
+        assert_eq!(from_str::<bool>("#t\r\n"), Ok(true));
+        assert_eq!(from_str::<bool>(":0\r\n"), Ok(false));
+        assert_eq!(from_str::<f64>(",inf\r\n"), Ok(f64::INFINITY));
+        assert_eq!(
+            from_str::<u128>("(340282366920938463463374607431768211455\r\n"),
+            Ok(u128::MAX)
+        );
+        assert_eq!(from_str::<Option<i64>>("_\r\n"), Ok(None));
+        assert_eq!(
+            from_str::<String>("=7\r\ntxt:foo\r\n"),
+            Ok("foo".to_string())
+        );
+        assert_eq!(from_str::<Vec<i64>>("~2\r\n:1\r\n:2\r\n"), Ok(vec![1, 2]));
+        assert_eq!(from_str::<i64>("|1\r\n+ttl\r\n:10\r\n:7\r\n"), Ok(7));
+        let map: BTreeMap<String, i64> = from_str("%2\r\n+a\r\n:1\r\n+b\r\n:2\r\n").unwrap();
+        assert_eq!(map.get("b"), Some(&2));
+        assert_eq!(
+            from_str::<String>("!3\r\nERR\r\n"),
+            Err(Error::Redis("ERR".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        assert_eq!(
+            from_str::<String>("-ERR wrong type\r\n"),
+            Err(Error::Redis("ERR wrong type".to_string()))
+        );
+        assert_eq!(from_str::<RedisType>("$5\r\nfoo"), Err(Error::Eof));
+        assert_eq!(from_str::<RedisType>("*2\r\n:1\r\n"), Err(Error::Eof));
+        assert_eq!(from_str::<RedisType>("+OK\n"), Err(Error::Eof));
+        assert_eq!(from_str::<RedisType>("+OK\rx"), Err(Error::ExpectedCrlf));
+        assert_eq!(
+            from_str::<RedisType>("?\r\n"),
+            Err(Error::InvalidPrefix(b'?'))
+        );
+        assert_eq!(
+            from_str::<RedisType>(":1\r\n:2\r\n"),
+            Err(Error::TrailingBytes)
+        );
+        assert_eq!(from_str::<u8>(":256\r\n"), Err(Error::IntegerOutOfRange));
+        assert_eq!(
+            from_str::<(i64,)>("*2\r\n:1\r\n:2\r\n"),
+            Err(Error::TrailingElements)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_max_depth() {
+        let nested = |depth: usize| "*1\r\n".repeat(depth) + ":1\r\n";
+        assert!(from_str::<RedisType>(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            from_str::<RedisType>(&nested(MAX_DEPTH + 1)),
+            Err(Error::TooDeep)
+        );
+        assert_eq!(
+            from_str::<RedisType>(&nested(1_000_000)),
+            Err(Error::TooDeep)
+        );
+        assert_eq!(
+            from_str::<IgnoredAny>(&"|1\r\n".repeat(1_000_000)),
+            Err(Error::TooDeep)
+        );
+        assert_eq!(
+            from_str::<i64>(&("|1\r\n:1\r\n".to_string() + &nested(1_000_000))),
+            Err(Error::TooDeep)
+        );
+    }
+}
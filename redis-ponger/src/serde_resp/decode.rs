@@ -0,0 +1,398 @@
+use serde::Deserialize;
+
+use super::de::Deserializer;
+use super::error::{Error, Result};
+
+// The default limits match the defaults of the Redis server: `proto-max-bulk-len`
+// is 512 MB and nested replies are never deeper than a few levels.
+const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+const DEFAULT_MAX_DEPTH: usize = 32;
+
+// The result of decoding a buffer.
+#[derive(Debug, PartialEq)]
+pub enum Decoded<T> {
+    // A complete value and the number of bytes of the buffer it was decoded from.
+    // The caller should drop these bytes before decoding the next value.
+    Frame(T, usize),
+    // The buffer does not hold a complete value yet. The caller should append
+    // more bytes and decode the same buffer again.
+    Incomplete,
+}
+
+// An incremental decoder of RESP values read from a stream.
+//
+// Bytes are collected by the caller in a buffer and passed to `decode` after
+// every read. The decoder first finds the end of the first value in the buffer
+// without allocating, so a large bulk string arriving in many chunks is only
+// deserialized once it is complete. The scan goes on where the last call left
+// off, so a large array arriving in many chunks is scanned once.
+pub struct Decoder {
+    max_bulk_len: usize,
+    max_depth: usize,
+    // The buffer length the pending frame needs at least. Decoding a shorter
+    // buffer is known to be incomplete, so it is not scanned again.
+    needed: usize,
+    // The start of the first value of the pending frame not scanned yet.
+    pos: usize,
+    // How far the buffer is known to have no line break, when the line of the
+    // value at `pos` is incomplete.
+    searched: usize,
+    // The number of values still to come in each container the value at `pos`
+    // is nested in, from the outermost.
+    open: Vec<usize>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            max_bulk_len: DEFAULT_MAX_BULK_LEN,
+            max_depth: DEFAULT_MAX_DEPTH,
+            needed: 0,
+            pos: 0,
+            searched: 0,
+            open: Vec::new(),
+        }
+    }
+
+    // Set the maximum length of a bulk string or a simple line. Longer values
+    // fail with `Error::TooLong` before they are buffered completely.
+    pub fn max_bulk_len(mut self, max_bulk_len: usize) -> Self {
+        self.max_bulk_len = max_bulk_len;
+        self
+    }
+
+    // Set the maximum number of nested arrays, maps and attributes. Deeper
+    // values fail with `Error::TooDeep`. The same limit applies when `decode`
+    // deserializes the value.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // Decode the first value in `buf`.
+    pub fn decode<'a, T>(&mut self, buf: &'a [u8]) -> Result<Decoded<T>>
+    where
+        T: Deserialize<'a>,
+    {
+        match self.frame_len(buf)? {
+            Some(end) => {
+                let value = Deserializer::from_slice(&buf[..end])
+                    .max_depth(self.max_depth)
+                    .deserialize_whole()?;
+                Ok(Decoded::Frame(value, end))
+            }
+            None => Ok(Decoded::Incomplete),
+        }
+    }
+
+    // Return the length of the first value in `buf` without deserializing it,
+    // or `None` if the buffer does not hold a complete value yet. The frame can
+    // be dropped from the buffer even if deserializing it fails later.
+    //
+    // After `None` the buffer must only be appended to before the next call,
+    // which goes on scanning where this one stopped.
+    pub fn frame_len(&mut self, buf: &[u8]) -> Result<Option<usize>> {
+        if buf.len() < self.needed {
+            return Ok(None);
+        }
+        let end = self.scan(buf);
+        if !matches!(end, Ok(None)) {
+            self.needed = 0;
+            self.pos = 0;
+            self.searched = 0;
+            self.open.clear();
+        }
+        end
+    }
+
+    // Scan the values from `pos` on until the first value in `buf` is complete
+    // and return its end, or `None` if `buf` ends before it.
+    fn scan(&mut self, buf: &[u8]) -> Result<Option<usize>> {
+        loop {
+            let prefix = match buf.get(self.pos) {
+                Some(&prefix) => prefix,
+                None => return Ok(None),
+            };
+            let (line, next) = match self.line_at(buf, self.pos + 1)? {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+            let mut values = 0;
+            let end = match prefix {
+                b'+' | b'-' | b':' | b'_' | b'#' | b',' | b'(' => next,
+                b'$' | b'!' | b'=' => match parse_len(line)? {
+                    Some(len) if len > self.max_bulk_len => return Err(Error::TooLong),
+                    Some(len) => {
+                        let end = next + len + 2;
+                        if buf.len() < end {
+                            self.needed = end;
+                            return Ok(None);
+                        }
+                        if &buf[end - 2..end] != b"\r\n" {
+                            return Err(Error::ExpectedCrlf);
+                        }
+                        end
+                    }
+                    None => next,
+                },
+                b'*' | b'~' | b'>' | b'%' | b'|' => match parse_len(line)? {
+                    Some(_) if self.open.len() >= self.max_depth => return Err(Error::TooDeep),
+                    Some(len) => {
+                        // maps and attributes have a key and a value per entry,
+                        // and an attribute is followed by the value it
+                        // describes, which counts as nested in it so chained
+                        // attributes are limited
+                        values = match prefix {
+                            b'%' => len.checked_mul(2),
+                            b'|' => len.checked_mul(2).and_then(|len| len.checked_add(1)),
+                            _ => Some(len),
+                        }
+                        .ok_or(Error::InvalidLength)?;
+                        next
+                    }
+                    None => next,
+                },
+                prefix => return Err(Error::InvalidPrefix(prefix)),
+            };
+            self.pos = end;
+            if values > 0 {
+                self.open.push(values);
+                continue;
+            }
+            // the value is complete, and so are the containers it is the last
+            // value of
+            loop {
+                match self.open.last_mut() {
+                    None => return Ok(Some(end)),
+                    Some(left) if *left > 1 => {
+                        *left -= 1;
+                        break;
+                    }
+                    Some(_) => {
+                        self.open.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    // Return the line starting at `pos` without the trailing `\r\n` and the
+    // position after it.
+    fn line_at<'a>(&mut self, buf: &'a [u8], pos: usize) -> Result<Option<(&'a [u8], usize)>> {
+        let from = self.searched.max(pos).min(buf.len());
+        match buf[from..].iter().position(|&b| b == b'\n') {
+            Some(end) => {
+                let end = from + end;
+                self.searched = 0;
+                if end > pos && buf[end - 1] == b'\r' {
+                    Ok(Some((&buf[pos..end - 1], end + 1)))
+                } else {
+                    Err(Error::ExpectedCrlf)
+                }
+            }
+            None if buf.len().saturating_sub(pos) > self.max_bulk_len => Err(Error::TooLong),
+            None => {
+                self.searched = buf.len();
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn parse_len(line: &[u8]) -> Result<Option<usize>> {
+    let len: i64 = std::str::from_utf8(line)
+        .map_err(|_| Error::InvalidUtf8)?
+        .parse()
+        .map_err(|_| Error::InvalidInteger)?;
+    match len {
+        -1 => Ok(None),
+        len if len >= 0 => Ok(Some(len as usize)),
+        _ => Err(Error::InvalidLength),
+    }
+}
+
+// With the `codec` feature the decoder can frame a tokio stream, for example
+// `FramedRead::new(socket, Decoder::new())`.
+#[cfg(feature = "codec")]
+mod codec {
+    use bytes::{Buf, BytesMut};
+    use tokio_util::codec;
+
+    use super::{Decoded, Decoder};
+    use crate::redis::RedisType;
+    use crate::serde_resp::{Error, Result};
+
+    impl codec::Decoder for Decoder {
+        type Item = RedisType;
+        type Error = Error;
+
+        fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RedisType>> {
+            match Decoder::decode(self, &src[..])? {
+                Decoded::Frame(value, len) => {
+                    src.advance(len);
+                    Ok(Some(value))
+                }
+                Decoded::Incomplete => Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redis::RedisType;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_in_chunks() {
+        let input = b"*2\r\n$5\r\nhello\r\n:42\r\n+OK\r\n";
+        let mut decoder = Decoder::new();
+        for end in 0..20 {
+            let decoded: Decoded<RedisType> = decoder.decode(&input[..end]).unwrap();
+            assert_eq!(decoded, Decoded::Incomplete, "prefix of {} bytes", end);
+        }
+        let decoded = decoder.decode(&input[..]).unwrap();
+        let expected = RedisType::Array(vec![
+            RedisType::BulkString(b"hello".to_vec()),
+            RedisType::Integer(42),
+        ]);
+        assert_eq!(decoded, Decoded::Frame(expected, 20));
+        let decoded = decoder.decode(&input[20..]).unwrap();
+        assert_eq!(
+            decoded,
+            Decoded::Frame(RedisType::SimpleString("OK".to_string()), 5)
+        );
+    }
+
+    #[test]
+    fn test_decode_large_array_in_chunks() {
+        let mut input = b"*100000\r\n".to_vec();
+        for i in 0..100_000 {
+            input.extend_from_slice(format!(":{}\r\n", i).as_bytes());
+        }
+        input.extend_from_slice(b"+OK\r\n");
+        let len = input.len() - 5;
+        let mut decoder = Decoder::new();
+        for end in (0..len).step_by(1000) {
+            let decoded = decoder.frame_len(&input[..end]).unwrap();
+            assert_eq!(decoded, None, "prefix of {} bytes", end);
+            // the scan went on where the last one stopped, a value is at most
+            // 9 bytes long
+            assert!(decoder.pos + 9 >= end, "prefix of {} bytes", end);
+        }
+        let decoded: Decoded<Vec<i64>> = decoder.decode(&input).unwrap();
+        assert_eq!(decoded, Decoded::Frame((0..100_000).collect(), len));
+        assert_eq!(decoder.frame_len(&input[len..]), Ok(Some(5)));
+    }
+
+    #[test]
+    fn test_decode_resp3() {
+        let input = b"|1\r\n+ttl\r\n:3600\r\n%1\r\n=7\r\ntxt:foo\r\n~1\r\n#f\r\n";
+        let mut decoder = Decoder::new();
+        for end in 0..input.len() {
+            let decoded: Decoded<RedisType> = decoder.decode(&input[..end]).unwrap();
+            assert_eq!(decoded, Decoded::Incomplete, "prefix of {} bytes", end);
+        }
+        let expected = RedisType::Attribute {
+            attributes: vec![(
+                RedisType::SimpleString("ttl".to_string()),
+                RedisType::Integer(3600),
+            )],
+            value: Box::new(RedisType::Map(vec![(
+                RedisType::VerbatimString {
+                    format: "txt".to_string(),
+                    text: b"foo".to_vec(),
+                },
+                RedisType::Set(vec![RedisType::Boolean(false)]),
+            )])),
+        };
+        let decoded = decoder.decode(&input[..]).unwrap();
+        assert_eq!(decoded, Decoded::Frame(expected, input.len()));
+    }
+
+    #[test]
+    fn test_decode_null() {
+        let mut decoder = Decoder::new();
+        let decoded = decoder.decode(b"$-1\r\n*-1\r\n").unwrap();
+        assert_eq!(decoded, Decoded::Frame(RedisType::Null, 5));
+    }
+
+    #[test]
+    fn test_decode_max_bulk_len() {
+        let mut decoder = Decoder::new().max_bulk_len(4);
+        let decoded = decoder.decode::<RedisType>(b"$4\r\nfour\r\n").unwrap();
+        assert_eq!(
+            decoded,
+            Decoded::Frame(RedisType::BulkString(b"four".to_vec()), 10)
+        );
+        assert_eq!(decoder.decode::<RedisType>(b"$5\r\n"), Err(Error::TooLong));
+        assert_eq!(decoder.decode::<RedisType>(b"+hello"), Err(Error::TooLong));
+    }
+
+    #[test]
+    fn test_decode_max_depth() {
+        let mut decoder = Decoder::new().max_depth(2);
+        let decoded = decoder.decode::<RedisType>(b"*1\r\n*0\r\n").unwrap();
+        let expected = RedisType::Array(vec![RedisType::Array(vec![])]);
+        assert_eq!(decoded, Decoded::Frame(expected, 8));
+        assert_eq!(
+            decoder.decode::<RedisType>(b"*1\r\n*1\r\n*0\r\n"),
+            Err(Error::TooDeep)
+        );
+    }
+
+    #[test]
+    fn test_decode_chained_attributes() {
+        let mut decoder = Decoder::new().max_depth(2);
+        let decoded = decoder.decode::<RedisType>(b"|0\r\n:1\r\n").unwrap();
+        assert!(matches!(decoded, Decoded::Frame(_, 8)));
+        assert_eq!(
+            decoder.decode::<RedisType>(b"|0\r\n|0\r\n|0\r\n:1\r\n"),
+            Err(Error::TooDeep)
+        );
+        let chained = b"|0\r\n".repeat(20_000);
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.decode::<RedisType>(&chained), Err(Error::TooDeep));
+    }
+
+    #[test]
+    fn test_decode_max_depth_above_default() {
+        let nested = |depth: usize| [b"*1\r\n".repeat(depth), b":1\r\n".to_vec()].concat();
+        let deep = nested(150);
+        let mut decoder = Decoder::new().max_depth(200);
+        let decoded = decoder.decode::<RedisType>(&deep).unwrap();
+        assert!(matches!(decoded, Decoded::Frame(_, len) if len == deep.len()));
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.decode::<RedisType>(&deep), Err(Error::TooDeep));
+        let mut decoder = Decoder::new().max_depth(150);
+        assert!(decoder.decode::<RedisType>(&deep).is_ok());
+        assert_eq!(
+            decoder.decode::<RedisType>(&nested(151)),
+            Err(Error::TooDeep)
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.decode::<RedisType>(b"?\r\n"),
+            Err(Error::InvalidPrefix(b'?'))
+        );
+        assert_eq!(
+            decoder.decode::<RedisType>(b"$3\r\nfoobar\r\n"),
+            Err(Error::ExpectedCrlf)
+        );
+        assert_eq!(
+            decoder.decode::<RedisType>(b"+OK\n"),
+            Err(Error::ExpectedCrlf)
+        );
+    }
+}
@@ -1,14 +1,11 @@
 use std;
 use std::fmt::{self, Display};
+use std::io;
 
 use serde::{de, ser};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-// This is a bare-bones implementation. A real library would provide additional
-// information in its error type, for example the line and column at which the
-// error occurred, the byte offset into the input, or the current key being
-// processed.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Error {
     // One or more variants that can be created by data structures through the
@@ -20,22 +17,50 @@ pub enum Error {
 
     // Zero or more variants that can be created directly by the Serializer and
     // Deserializer without going through `ser::Error` and `de::Error`. These
-    // are specific to the format, in this case JSON.
+    // are specific to RESP.
+    /// The input ended before a complete value was read.
     Eof,
-    Syntax,
+    /// The first byte of a value is not a known RESP type prefix.
+    InvalidPrefix(u8),
+    /// A line is not terminated by `\r\n`.
+    ExpectedCrlf,
+    /// The line of an integer, or a length, is not a valid integer.
+    InvalidInteger,
+    /// A bulk string or array length is negative but not the null length -1.
+    InvalidLength,
+    /// A string is not valid UTF-8.
+    InvalidUtf8,
+    /// An integer does not fit into the requested type.
+    IntegerOutOfRange,
+    /// The value is not the integer 0 or 1.
     ExpectedBoolean,
+    /// The value is not an integer.
     ExpectedInteger,
+    /// The value is neither a simple string nor a bulk string.
     ExpectedString,
-    ExpectedNull,
+    /// The value is not an array.
     ExpectedArray,
-    ExpectedArrayComma,
-    ExpectedArrayEnd,
-    ExpectedMap,
-    ExpectedMapColon,
-    ExpectedMapComma,
-    ExpectedMapEnd,
+    /// The value is not a null bulk string or array.
+    ExpectedNull,
+    /// An enum is neither a variant name nor an array of a name and a value.
     ExpectedEnum,
-    TrailingCharacters,
+    /// A map is an array with an odd number of elements.
+    ExpectedMapPairs,
+    /// An array has more elements than the type being deserialized.
+    TrailingElements,
+    /// The server replied with an error, e.g. `-ERR unknown command`.
+    Redis(String),
+    /// Bytes remain after the value.
+    TrailingBytes,
+    /// A bulk string or a line is longer than the limit of the decoder.
+    TooLong,
+    /// Arrays are nested deeper than the limit of the decoder or the
+    /// deserializer.
+    TooDeep,
+    /// A simple string or an error contains a line break.
+    InvalidSimpleString,
+    /// Reading or writing the stream failed.
+    Io(io::ErrorKind),
 }
 
 impl ser::Error for Error {
@@ -50,19 +75,39 @@ impl de::Error for Error {
     }
 }
 
-impl Display for Error {
-    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(std::error::Error::description(self))
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err.kind())
     }
 }
 
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::Message(ref msg) => msg,
-            Error::Eof => "unexpected end of input",
-            _ => "other error",
-            /* and so forth */
+impl Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Message(msg) => formatter.write_str(msg),
+            Error::Eof => formatter.write_str("unexpected end of input"),
+            Error::InvalidPrefix(b) => write!(formatter, "invalid type prefix {:?}", *b as char),
+            Error::ExpectedCrlf => formatter.write_str("expected \\r\\n"),
+            Error::InvalidInteger => formatter.write_str("invalid integer"),
+            Error::InvalidLength => formatter.write_str("invalid length"),
+            Error::InvalidUtf8 => formatter.write_str("invalid UTF-8"),
+            Error::IntegerOutOfRange => formatter.write_str("integer out of range"),
+            Error::ExpectedBoolean => formatter.write_str("expected boolean"),
+            Error::ExpectedInteger => formatter.write_str("expected integer"),
+            Error::ExpectedString => formatter.write_str("expected string"),
+            Error::ExpectedArray => formatter.write_str("expected array"),
+            Error::ExpectedNull => formatter.write_str("expected null"),
+            Error::ExpectedEnum => formatter.write_str("expected enum"),
+            Error::ExpectedMapPairs => formatter.write_str("expected key value pairs"),
+            Error::TrailingElements => formatter.write_str("array has more elements than expected"),
+            Error::Redis(msg) => write!(formatter, "server error: {}", msg),
+            Error::TrailingBytes => formatter.write_str("trailing bytes"),
+            Error::TooLong => formatter.write_str("value exceeds the maximum length"),
+            Error::TooDeep => formatter.write_str("arrays exceed the maximum nesting depth"),
+            Error::InvalidSimpleString => formatter.write_str("line break in a simple string"),
+            Error::Io(kind) => write!(formatter, "I/O error: {:?}", kind),
         }
     }
 }
+
+impl std::error::Error for Error {}
@@ -1,5 +1,31 @@
+mod de;
+mod decode;
 mod error;
 mod ser;
 
+pub use de::{from_slice, from_str, Deserializer};
+pub use decode::{Decoded, Decoder};
 pub use error::{Error, Result};
-pub use ser::to_string;
+pub use ser::{to_string, to_vec, to_writer, Serializer};
+
+// The version of the protocol. Redis 6 and newer speak RESP3 after `HELLO 3`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+// Names of the newtype structs `RedisType` serializes simple strings, errors,
+// big numbers and verbatim strings as. The RESP serializer writes them with
+// their own type prefix, other serializers see plain strings and bytes.
+pub(crate) const SIMPLE_STRING_TOKEN: &str = "$serde_resp::SimpleString";
+pub(crate) const ERROR_TOKEN: &str = "$serde_resp::Error";
+pub(crate) const BIG_NUMBER_TOKEN: &str = "$serde_resp::BigNumber";
+pub(crate) const VERBATIM_STRING_TOKEN: &str = "$serde_resp::VerbatimString";
+
+// Names of the tuple structs `RedisType` serializes RESP3 sets, push messages
+// and attributes as. Other serializers see sequences, and an attribute as the
+// pair of the attribute map and the value.
+pub(crate) const SET_TOKEN: &str = "$serde_resp::Set";
+pub(crate) const PUSH_TOKEN: &str = "$serde_resp::Push";
+pub(crate) const ATTRIBUTE_TOKEN: &str = "$serde_resp::Attribute";
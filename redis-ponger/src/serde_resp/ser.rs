@@ -1,24 +1,133 @@
+use std::io;
+
 use serde::{ser, Serialize};
 
 use super::error::{Error, Result};
+use super::{
+    Protocol, ATTRIBUTE_TOKEN, BIG_NUMBER_TOKEN, ERROR_TOKEN, PUSH_TOKEN, SET_TOKEN,
+    SIMPLE_STRING_TOKEN, VERBATIM_STRING_TOKEN,
+};
+
+pub struct Serializer<W> {
+    // RESP is written to this writer as values are serialized. Values are
+    // written in many small pieces, so a socket should be wrapped in a
+    // `BufWriter`.
+    writer: W,
+    protocol: Protocol,
+}
 
-pub struct Serializer {
-    // This string starts empty and RESP is appended as values are serialized.
-    output: String,
+impl<W: io::Write> Serializer<W> {
+    pub fn new(writer: W, protocol: Protocol) -> Self {
+        Serializer { writer, protocol }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
 }
 
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: Serialize,
+{
+    let mut serializer = Serializer::new(writer, Protocol::Resp2);
+    value.serialize(&mut serializer)
+}
+
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut output = Vec::new();
+    to_writer(&mut output, value)?;
+    Ok(output)
+}
+
+// Serialize to a `String`. It fails if a byte array that is not valid UTF-8 was
+// serialized.
 pub fn to_string<T>(value: &T) -> Result<String>
 where
     T: Serialize,
 {
-    let mut serializer = Serializer {
-        output: String::new(),
-    };
-    value.serialize(&mut serializer)?;
-    Ok(serializer.output)
+    String::from_utf8(to_vec(value)?).map_err(|_| Error::InvalidUtf8)
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+// Methods writing the parts of RESP values.
+impl<W: io::Write> Serializer<W> {
+    fn write_line(&mut self, prefix: u8, line: &[u8]) -> Result<()> {
+        self.writer.write_all(&[prefix])?;
+        self.writer.write_all(line)?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(())
+    }
+
+    // Write the header of an array or a map with `len` elements.
+    fn write_len(&mut self, prefix: u8, len: usize) -> Result<()> {
+        self.write_line(prefix, len.to_string().as_bytes())
+    }
+
+    fn write_seq_len(&mut self, len: usize) -> Result<()> {
+        self.write_len(b'*', len)
+    }
+
+    // Write the header of a map with `len` entries. RESP2 has no maps, so the
+    // keys and values are sent as a flat array like the reply of `HGETALL`.
+    fn write_map_len(&mut self, len: usize) -> Result<()> {
+        match self.protocol {
+            Protocol::Resp2 => self.write_len(b'*', len * 2),
+            Protocol::Resp3 => self.write_len(b'%', len),
+        }
+    }
+
+    fn write_bulk(&mut self, v: &[u8]) -> Result<()> {
+        self.write_len(b'$', v.len())?;
+        self.writer.write_all(v)?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(())
+    }
+
+    // Floats are bulk strings in RESP2, as in the arguments of `INCRBYFLOAT`
+    // and `ZADD`, and doubles in RESP3. Redis spells the special values `inf`,
+    // `-inf` and `nan`.
+    fn write_float(&mut self, v: String) -> Result<()> {
+        let v = if v == "NaN" { "nan".to_owned() } else { v };
+        match self.protocol {
+            Protocol::Resp2 => self.write_bulk(v.as_bytes()),
+            Protocol::Resp3 => self.write_line(b',', v.as_bytes()),
+        }
+    }
+
+    // Simple strings and errors can't contain a line break.
+    fn write_simple(&mut self, prefix: u8, v: &str) -> Result<()> {
+        if v.contains(['\r', '\n']) {
+            return Err(Error::InvalidSimpleString);
+        }
+        self.write_line(prefix, v.as_bytes())
+    }
+
+    // Start an array or a map. Without a known length the elements are
+    // collected in a buffer and counted before the header is written.
+    fn compound(
+        &mut self,
+        len: Option<usize>,
+        write_len: fn(&mut Self, usize) -> Result<()>,
+    ) -> Result<Compound<'_, W>> {
+        match len {
+            Some(len) => {
+                write_len(self, len)?;
+                Ok(Compound::Direct(self))
+            }
+            None => Ok(Compound::Buffered {
+                buffer: Serializer::new(Vec::new(), self.protocol),
+                ser: self,
+                len: 0,
+            }),
+        }
+    }
+}
+
+impl<'a, W: io::Write> ser::Serializer for &'a mut Serializer<W> {
     // The output type produced by this `Serializer` during successful
     // serialization. Most serializers that produce text or binary output should
     // set `Ok = ()` and serialize into an `io::Write` or buffer contained
@@ -31,23 +140,24 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type Error = Error;
 
     // Associated types for keeping track of additional state while serializing
-    // compound data structures like sequences and maps. In this case no
-    // additional state is required beyond what is already stored in the
-    // Serializer struct.
-    type SerializeSeq = Self;
-    type SerializeTuple = Self;
-    type SerializeTupleStruct = Self;
-    type SerializeTupleVariant = Self;
-    type SerializeMap = Self;
-    type SerializeStruct = Self;
-    type SerializeStructVariant = Self;
-
-    // Here we go with the simple methods. The following 12 methods receive one
-    // of the primitive types of the data model and map it to JSON by appending
-    // into the output string.
+    // compound data structures like sequences and maps. RESP writes the number
+    // of elements before them, so `Compound` counts the elements when the
+    // length isn't known up front.
+    type SerializeSeq = Compound<'a, W>;
+    type SerializeTuple = Compound<'a, W>;
+    type SerializeTupleStruct = Compound<'a, W>;
+    type SerializeTupleVariant = Compound<'a, W>;
+    type SerializeMap = Compound<'a, W>;
+    type SerializeStruct = Compound<'a, W>;
+    type SerializeStructVariant = Compound<'a, W>;
+
+    // Here we go with the simple methods. RESP2 has integers and strings, so
+    // booleans are the integers 0 and 1, like the replies of `EXISTS`.
     fn serialize_bool(self, v: bool) -> Result<()> {
-        self.output += if v { "1" } else { "0" };
-        Ok(())
+        match self.protocol {
+            Protocol::Resp2 => self.serialize_i64(i64::from(v)),
+            Protocol::Resp3 => self.write_line(b'#', if v { b"t" } else { b"f" }),
+        }
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
@@ -63,10 +173,17 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_i64(self, v: i64) -> Result<()> {
-        self.output += ":";
-        self.output += &v.to_string();
-        self.output += "\r\n";
-        Ok(())
+        self.write_line(b':', v.to_string().as_bytes())
+    }
+
+    // RESP integers are signed 64 bit. Larger numbers are sent as bulk strings
+    // of their digits, which the deserializer reads back as integers.
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        if v >= i128::from(i64::MIN) && v <= i128::from(i64::MAX) {
+            self.serialize_i64(v as i64)
+        } else {
+            self.write_bulk(v.to_string().as_bytes())
+        }
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
@@ -74,7 +191,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        self.serialize_u64(u64::from(v))
+        self.serialize_i64(i64::from(v))
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
@@ -82,56 +199,48 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
-        if v > (std::i64::MAX as u64) {
-            panic!("Outside range");
+        self.serialize_i128(i128::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        if v <= i64::MAX as u128 {
+            self.serialize_i64(v as i64)
+        } else {
+            self.write_bulk(v.to_string().as_bytes())
         }
-        self.serialize_i64(v as i64)
     }
 
-    fn serialize_f32(self, _v: f32) -> Result<()> {
-        unimplemented!()
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_float(v.to_string())
     }
 
-    fn serialize_f64(self, _v: f64) -> Result<()> {
-        unimplemented!()
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_float(v.to_string())
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
         self.serialize_str(&v.to_string())
     }
 
+    // Strings are bulk strings because they may contain any byte. Simple
+    // strings are only written for `RedisType::SimpleString`, see
+    // `serialize_newtype_struct`.
     fn serialize_str(self, v: &str) -> Result<()> {
-        self.output += "$";
-        let num_bytes = &v.bytes().len();
-        self.output += num_bytes.to_string().as_str();
-        self.output += "\r\n";
-        self.output += v;
-        self.output += "\r\n";
-        Ok(())
+        self.write_bulk(v.as_bytes())
     }
 
-    // Serialize a byte array as an array of bytes. Could also use a base64
-    // string here. Binary formats will typically represent byte arrays more
-    // compactly.
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        use serde::ser::SerializeSeq;
-        let mut seq = self.serialize_seq(Some(v.len()))?;
-        for byte in v {
-            seq.serialize_element(byte)?;
-        }
-        seq.end()
+        self.write_bulk(v)
     }
 
-    // An absent optional is represented as the JSON `null`.
+    // An absent optional is represented as the null bulk string.
     fn serialize_none(self) -> Result<()> {
         self.serialize_unit()
     }
 
     // A present optional is represented as just the contained value. Note that
     // this is a lossy representation. For example the values `Some(())` and
-    // `None` both serialize as just `null`. Unfortunately this is typically
-    // what people expect when working with JSON. Other formats are encouraged
-    // to behave more intelligently if possible.
+    // `None` both serialize as just null.
     fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
@@ -140,23 +249,23 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     // In Serde, unit means an anonymous value containing no data. Map this to
-    // RESP as null.
+    // RESP as null, which is the null bulk string in RESP2.
     fn serialize_unit(self) -> Result<()> {
-        self.output += "$-1\r\n";
+        match self.protocol {
+            Protocol::Resp2 => self.writer.write_all(b"$-1\r\n")?,
+            Protocol::Resp3 => self.writer.write_all(b"_\r\n")?,
+        }
         Ok(())
     }
 
     // Unit struct means a named value containing no data. Again, since there is
-    // no data, map this to JSON as `null`. There is no need to serialize the
-    // name in most formats.
+    // no data, map this to RESP as null. There is no need to serialize the
+    // name.
     fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
         self.serialize_unit()
     }
 
-    // When serializing a unit variant (or any other kind of variant), formats
-    // can choose whether to keep track of it by index or by name. Binary
-    // formats typically use the index of the variant and human-readable formats
-    // typically use the name.
+    // Variants are tagged by name. A unit variant is just its name.
     fn serialize_unit_variant(
         self,
         _name: &'static str,
@@ -167,19 +276,46 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     // As is done here, serializers are encouraged to treat newtype structs as
-    // insignificant wrappers around the data they contain.
-    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    // insignificant wrappers around the data they contain. The exceptions are
+    // the private names `RedisType` uses for the string types RESP tells apart,
+    // which other serializers see as plain strings.
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(self)
+        let prefix = match name {
+            SIMPLE_STRING_TOKEN => b'+',
+            ERROR_TOKEN => b'-',
+            BIG_NUMBER_TOKEN => b'(',
+            VERBATIM_STRING_TOKEN => b'=',
+            _ => return value.serialize(self),
+        };
+        let mut bulk = Serializer::new(Vec::new(), self.protocol);
+        value.serialize(&mut bulk)?;
+        let v = bulk_data(&bulk.writer)?;
+        match (prefix, self.protocol) {
+            // RESP2 has neither big numbers nor verbatim strings. The format
+            // of a verbatim string, like `txt:`, is dropped.
+            (b'(', Protocol::Resp2) => self.write_bulk(v),
+            (b'=', Protocol::Resp2) => self.write_bulk(v.get(4..).unwrap_or_default()),
+            (b'=', Protocol::Resp3) => {
+                self.write_len(b'=', v.len())?;
+                self.writer.write_all(v)?;
+                self.writer.write_all(b"\r\n")?;
+                Ok(())
+            }
+            _ => {
+                let v = std::str::from_utf8(v).map_err(|_| Error::InvalidUtf8)?;
+                self.write_simple(prefix, v)
+            }
+        }
     }
 
     // Note that newtype variant (and all of the other variant serialization
     // methods) refer exclusively to the "externally tagged" enum
     // representation.
     //
-    // Serialize this to JSON in externally tagged form as `{ NAME: VALUE }`.
+    // Serialize this to RESP as an array of the name and the value.
     fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
@@ -190,91 +326,163 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        self.output += "{";
+        self.write_seq_len(2)?;
         variant.serialize(&mut *self)?;
-        self.output += ":";
-        value.serialize(&mut *self)?;
-        self.output += "}";
-        Ok(())
+        value.serialize(&mut *self)
     }
 
     // Now we get to the serialization of compound types.
     //
     // The start of the sequence, each value, and the end are three separate
-    // method calls. This one is responsible only for serializing the start,
-    // which in JSON is `[`.
-    //
-    // The length of the sequence may or may not be known ahead of time. This
-    // doesn't make a difference in JSON because the length is not represented
-    // explicitly in the serialized form. Some serializers may only be able to
-    // support sequences for which the length is known up front.
+    // method calls. RESP writes the length in front of the elements, so a
+    // sequence of unknown length is buffered until its end.
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.output += "*";
-        self.output += len.unwrap_or(0).to_string().as_str();
-        self.output += "\r\n";
-        Ok(self)
+        self.compound(len, Serializer::write_seq_len)
     }
 
-    // Tuples look just like sequences in JSON. Some formats may be able to
-    // represent tuples more efficiently by omitting the length, since tuple
-    // means that the corresponding `Deserialize implementation will know the
-    // length without needing to look at the serialized data.
+    // Tuples look just like sequences in RESP.
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
         self.serialize_seq(Some(len))
     }
 
-    // Tuple structs look just like sequences in JSON.
+    // Tuple structs look just like sequences in RESP, except for the private
+    // names `RedisType` uses for RESP3 sets, push messages and attributes. In
+    // RESP2 sets and push messages are arrays, and attributes are dropped.
     fn serialize_tuple_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        self.serialize_seq(Some(len))
-    }
-
-    // Tuple variants are represented in JSON as `{ NAME: [DATA...] }`. Again
+        let prefix = match (name, self.protocol) {
+            (ATTRIBUTE_TOKEN, _) => {
+                return Ok(Compound::Attribute {
+                    ser: self,
+                    index: 0,
+                })
+            }
+            (SET_TOKEN, Protocol::Resp3) => b'~',
+            (PUSH_TOKEN, Protocol::Resp3) => b'>',
+            _ => b'*',
+        };
+        self.write_len(prefix, len)?;
+        Ok(Compound::Direct(self))
+    }
+
+    // Tuple variants are represented in RESP as `[NAME, [DATA...]]`. Again
     // this method is only responsible for the externally tagged representation.
     fn serialize_tuple_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.output += "{";
+        self.write_seq_len(2)?;
         variant.serialize(&mut *self)?;
-        self.output += ":[";
-        Ok(self)
+        self.serialize_seq(Some(len))
     }
 
-    // Maps are represented in JSON as `{ K: V, K: V, ... }`.
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        self.output += "{";
-        Ok(self)
+    // Maps are flat arrays of alternating keys and values in RESP2, and maps
+    // in RESP3.
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.compound(len, Serializer::write_map_len)
     }
 
-    // Structs look just like maps in JSON. In particular, JSON requires that we
-    // serialize the field names of the struct. Other formats may be able to
-    // omit the field names when serializing structs because the corresponding
-    // Deserialize implementation is required to know what the keys are without
-    // looking at the serialized data.
+    // Structs look just like maps keyed by the field names.
     fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
         self.serialize_map(Some(len))
     }
 
-    // Struct variants are represented in JSON as `{ NAME: { K: V, ... } }`.
+    // Struct variants are represented in RESP as `[NAME, { K: V, ... }]`.
     // This is the externally tagged representation.
     fn serialize_struct_variant(
         self,
         _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.output += "{";
+        self.write_seq_len(2)?;
         variant.serialize(&mut *self)?;
-        self.output += ":{";
-        Ok(self)
+        self.serialize_map(Some(len))
+    }
+}
+
+// The state of an array or a map being serialized.
+pub enum Compound<'a, W> {
+    // The header is written and elements are written after it.
+    Direct(&'a mut Serializer<W>),
+    // The length was unknown. Elements are appended to `buffer` and counted,
+    // and the whole array or map is written at the end.
+    Buffered {
+        ser: &'a mut Serializer<W>,
+        buffer: Serializer<Vec<u8>>,
+        len: usize,
+    },
+    // An attribute map and the value it describes. `index` is the number of
+    // fields serialized.
+    Attribute {
+        ser: &'a mut Serializer<W>,
+        index: usize,
+    },
+}
+
+impl<'a, W: io::Write> Compound<'a, W> {
+    fn element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        match self {
+            Compound::Direct(ser) => value.serialize(&mut **ser),
+            Compound::Buffered { buffer, len, .. } => {
+                *len += 1;
+                value.serialize(buffer)
+            }
+            Compound::Attribute { ser, index } => {
+                *index += 1;
+                match (*index, ser.protocol) {
+                    (1, Protocol::Resp2) => Ok(()),
+                    (1, Protocol::Resp3) => {
+                        let mut map = Serializer::new(Vec::new(), ser.protocol);
+                        value.serialize(&mut map)?;
+                        match map.writer.first_mut() {
+                            Some(prefix @ b'%') => *prefix = b'|',
+                            _ => return Err(Error::ExpectedMapPairs),
+                        }
+                        ser.writer.write_all(&map.writer)?;
+                        Ok(())
+                    }
+                    _ => value.serialize(&mut **ser),
+                }
+            }
+        }
+    }
+
+    fn end_seq(self) -> Result<()> {
+        if let Compound::Buffered { ser, buffer, len } = self {
+            ser.write_seq_len(len)?;
+            ser.writer.write_all(&buffer.writer)?;
+        }
+        Ok(())
+    }
+
+    fn end_map(self) -> Result<()> {
+        if let Compound::Buffered { ser, buffer, len } = self {
+            ser.write_map_len(len / 2)?;
+            ser.writer.write_all(&buffer.writer)?;
+        }
+        Ok(())
+    }
+}
+
+// Return the data of a serialized bulk string.
+fn bulk_data(bulk: &[u8]) -> Result<&[u8]> {
+    if bulk.first() != Some(&b'$') {
+        return Err(Error::ExpectedString);
+    }
+    match bulk.iter().position(|&b| b == b'\n') {
+        Some(end) if end < bulk.len() - 2 => Ok(&bulk[end + 1..bulk.len() - 2]),
+        _ => Err(Error::ExpectedString),
     }
 }
 
@@ -285,7 +493,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 //
 // This impl is SerializeSeq so these methods are called after `serialize_seq`
 // is called on the Serializer.
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl<'a, W: io::Write> ser::SerializeSeq for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -294,17 +502,17 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     // Close the sequence.
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_seq()
     }
 }
 
 // Same thing but for tuples.
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl<'a, W: io::Write> ser::SerializeTuple for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -312,16 +520,16 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_seq()
     }
 }
 
 // Same thing but for tuple structs.
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl<'a, W: io::Write> ser::SerializeTupleStruct for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -329,24 +537,17 @@ impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_seq()
     }
 }
 
-// Tuple variants are a little different. Refer back to the
-// `serialize_tuple_variant` method above:
-//
-//    self.output += "{";
-//    variant.serialize(&mut *self)?;
-//    self.output += ":[";
-//
-// So the `end` method in this impl is responsible for closing both the `]` and
-// the `}`.
-impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
+// The name of a tuple variant was written by `serialize_tuple_variant`, so
+// this is just the array of fields after it.
+impl<'a, W: io::Write> ser::SerializeTupleVariant for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -354,68 +555,44 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        if !self.output.ends_with('[') {
-            self.output += ",";
-        }
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        self.output += "]}";
-        Ok(())
+        self.end_seq()
     }
 }
 
 // Some `Serialize` types are not able to hold a key and value in memory at the
 // same time so `SerializeMap` implementations are required to support
-// `serialize_key` and `serialize_value` individually.
-//
-// There is a third optional method on the `SerializeMap` trait. The
-// `serialize_entry` method allows serializers to optimize for the case where
-// key and value are both available simultaneously. In JSON it doesn't make a
-// difference so the default behavior for `serialize_entry` is fine.
-impl<'a> ser::SerializeMap for &'a mut Serializer {
+// `serialize_key` and `serialize_value` individually. RESP allows keys of any
+// type, and both are just the next element.
+impl<'a, W: io::Write> ser::SerializeMap for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
-    // The Serde data model allows map keys to be any serializable type. JSON
-    // only allows string keys so the implementation below will produce invalid
-    // JSON if the key serializes as something other than a string.
-    //
-    // A real JSON serializer would need to validate that map keys are strings.
-    // This can be done by using a different Serializer to serialize the key
-    // (instead of `&mut **self`) and having that other serializer only
-    // implement `serialize_str` and return an error on any other data type.
     fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        if !self.output.ends_with('{') {
-            self.output += ",";
-        }
-        key.serialize(&mut **self)
+        self.element(key)
     }
 
-    // It doesn't make a difference whether the colon is printed at the end of
-    // `serialize_key` or at the beginning of `serialize_value`. In this case
-    // the code is a bit simpler having it here.
     fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        self.output += ":";
-        value.serialize(&mut **self)
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        self.output += "}";
-        Ok(())
+        self.end_map()
     }
 }
 
 // Structs are like maps in which the keys are constrained to be compile-time
 // constant strings.
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl<'a, W: io::Write> ser::SerializeStruct for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -423,23 +600,18 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        if !self.output.ends_with('{') {
-            self.output += ",";
-        }
-        key.serialize(&mut **self)?;
-        self.output += ":";
-        value.serialize(&mut **self)
+        self.element(key)?;
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        self.output += "}";
-        Ok(())
+        self.end_map()
     }
 }
 
-// Similar to `SerializeTupleVariant`, here the `end` method is responsible for
-// closing both of the curly braces opened by `serialize_struct_variant`.
-impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+// Similar to `SerializeTupleVariant`, the name was written by
+// `serialize_struct_variant` and this is the map of fields after it.
+impl<'a, W: io::Write> ser::SerializeStructVariant for Compound<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -447,22 +619,22 @@ impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        if !self.output.ends_with('{') {
-            self.output += ",";
-        }
-        key.serialize(&mut **self)?;
-        self.output += ":";
-        value.serialize(&mut **self)
+        self.element(key)?;
+        self.element(value)
     }
 
     fn end(self) -> Result<()> {
-        self.output += "}}";
-        Ok(())
+        self.end_map()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use crate::serde_resp::{from_slice, from_str};
     use crate::RedisType;
 
     use super::*;
@@ -488,4 +660,141 @@ mod tests {
         .unwrap();
         assert_eq!(actual, "*2\r\n+foo\r\n:64\r\n");
     }
+
+    #[test]
+    fn test_serialize_redis_type_error() {
+        let actual = to_string(&RedisType::Error("ERR no such key".to_string())).unwrap();
+        assert_eq!(actual, "-ERR no such key\r\n");
+        let actual = to_string(&RedisType::SimpleString("a\r\nb".to_string()));
+        assert_eq!(actual, Err(Error::InvalidSimpleString));
+    }
+
+    #[test]
+    fn test_serialize_primitives() {
+        assert_eq!(to_string(&true).unwrap(), ":1\r\n");
+        assert_eq!(
+            to_string(&u64::MAX).unwrap(),
+            "$20\r\n18446744073709551615\r\n"
+        );
+        assert_eq!(to_string(&1.5f64).unwrap(), "$3\r\n1.5\r\n");
+        assert_eq!(to_string(&f64::NEG_INFINITY).unwrap(), "$4\r\n-inf\r\n");
+        assert_eq!(to_string(&'x').unwrap(), "$1\r\nx\r\n");
+        assert_eq!(to_string(&None::<i64>).unwrap(), "$-1\r\n");
+        assert_eq!(
+            from_str::<u64>(&to_string(&u64::MAX).unwrap()),
+            Ok(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_serialize_map() {
+        let mut map = BTreeMap::new();
+        map.insert("a", 1);
+        map.insert("b", 2);
+        let expected = "*4\r\n$1\r\na\r\n:1\r\n$1\r\nb\r\n:2\r\n";
+        assert_eq!(to_string(&map).unwrap(), expected);
+
+        let mut serializer = Serializer::new(Vec::new(), Protocol::Resp3);
+        map.serialize(&mut serializer).unwrap();
+        let expected = b"%2\r\n$1\r\na\r\n:1\r\n$1\r\nb\r\n:2\r\n";
+        assert_eq!(serializer.into_inner(), expected);
+    }
+
+    #[test]
+    fn test_serialize_unknown_length() {
+        struct Evens(u8);
+        impl Serialize for Evens {
+            fn serialize<S: ser::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.collect_seq((0..self.0).filter(|n| n % 2 == 0))
+            }
+        }
+        let expected = "*3\r\n:0\r\n:2\r\n:4\r\n";
+        assert_eq!(to_string(&Evens(6)).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_serialize_struct_and_enum() {
+        #[derive(Serialize)]
+        struct Point {
+            x: i64,
+            y: i64,
+        }
+        #[derive(Serialize)]
+        enum Shape {
+            Empty,
+            Dot(Point),
+            Line(Point, Point),
+            Circle { r: u8 },
+        }
+        assert_eq!(
+            to_string(&Point { x: 1, y: 2 }).unwrap(),
+            "*4\r\n$1\r\nx\r\n:1\r\n$1\r\ny\r\n:2\r\n"
+        );
+        assert_eq!(to_string(&Shape::Empty).unwrap(), "$5\r\nEmpty\r\n");
+        assert_eq!(
+            to_string(&Shape::Dot(Point { x: 1, y: 2 })).unwrap(),
+            "*2\r\n$3\r\nDot\r\n*4\r\n$1\r\nx\r\n:1\r\n$1\r\ny\r\n:2\r\n"
+        );
+        assert_eq!(
+            to_string(&Shape::Line(Point { x: 1, y: 2 }, Point { x: 3, y: 4 })).unwrap(),
+            "*2\r\n$4\r\nLine\r\n*2\r\n\
+             *4\r\n$1\r\nx\r\n:1\r\n$1\r\ny\r\n:2\r\n\
+             *4\r\n$1\r\nx\r\n:3\r\n$1\r\ny\r\n:4\r\n"
+        );
+        assert_eq!(
+            to_string(&Shape::Circle { r: 3 }).unwrap(),
+            "*2\r\n$6\r\nCircle\r\n*2\r\n$1\r\nr\r\n:3\r\n"
+        );
+    }
+
+    #[test]
+    fn test_serialize_redis_type_binary() {
+        let actual = to_vec(&RedisType::BulkString(vec![0, 0xff, b'\r'])).unwrap();
+        assert_eq!(actual, b"$3\r\n\x00\xff\r\r\n");
+        assert_eq!(
+            from_slice::<RedisType>(&actual),
+            Ok(RedisType::BulkString(vec![0, 0xff, b'\r']))
+        );
+    }
+
+    #[test]
+    fn test_to_writer() {
+        struct Limited(usize);
+        impl io::Write for Limited {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if buf.len() > self.0 {
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                self.0 -= buf.len();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        assert_eq!(to_writer(Limited(9), &"foo"), Ok(()));
+        assert_eq!(
+            to_writer(Limited(8), &"foo"),
+            Err(Error::Io(io::ErrorKind::WriteZero))
+        );
+    }
+
+    #[test]
+    fn test_serialize_bytes() {
+        struct Bytes<'a>(&'a [u8]);
+        impl<'a> Serialize for Bytes<'a> {
+            fn serialize<S: ser::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(self.0)
+            }
+        }
+        assert_eq!(to_string(&Bytes(b"a\r\nb")).unwrap(), "$4\r\na\r\nb\r\n");
+        assert_eq!(to_vec(&Bytes(b"\xff\x00")).unwrap(), b"$2\r\n\xff\x00\r\n");
+        assert_eq!(to_string(&Bytes(b"\xff")), Err(Error::InvalidUtf8));
+    }
 }
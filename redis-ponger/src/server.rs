@@ -0,0 +1,802 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::redis::RedisType;
+use crate::serde_resp::{self, Decoded, Decoder};
+
+// The number of databases that can be chosen with `SELECT`, like the default
+// `databases` setting of Redis.
+const DATABASES: usize = 16;
+// The most expired keys removed before a command, so the cost of expiring keys
+// that are not accessed again is spread over the commands.
+const EXPIRE_BATCH: usize = 20;
+
+// A small in-process Redis server for tests. It keeps string keys in memory
+// and understands PING, ECHO, GET, MGET, SET, DEL, EXISTS, INCR, EXPIRE, KEYS
+// and SELECT, as well as PUBLISH and the (un)subscribe commands of pub/sub.
+//
+// Every connection is served by its own thread. Dropping the server stops
+// accepting connections and closes the open ones.
+pub struct Server {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    accept: Option<JoinHandle<()>>,
+}
+
+// The state shared by the connections of a server.
+struct Shared {
+    keyspace: Mutex<Keyspace>,
+    broker: Mutex<Broker>,
+    // The open connections by id, so they can be closed with the server.
+    connections: Mutex<HashMap<u64, TcpStream>>,
+    next_id: AtomicU64,
+    stopped: AtomicBool,
+}
+
+impl Server {
+    // Listen on `addr` and serve connections in the background. Bind to
+    // `127.0.0.1:0` to get an ephemeral port, which `addr` returns.
+    pub fn start<A: ToSocketAddrs>(addr: A) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            keyspace: Mutex::new(Keyspace::new()),
+            broker: Mutex::new(Broker::default()),
+            connections: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+        });
+        let accept_shared = Arc::clone(&shared);
+        let accept = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accept_shared.stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let shared = Arc::clone(&accept_shared);
+                thread::spawn(move || {
+                    let _ = serve(stream, shared);
+                });
+            }
+        });
+        Ok(Server {
+            addr,
+            shared,
+            accept: Some(accept),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        // wake up the accept loop so it sees the flag, and wait until it closed
+        // the listener so the address can be bound again
+        let _ = TcpStream::connect(self.addr);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+        for stream in self.shared.connections.lock().unwrap().values() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// A string value and the time it expires at.
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+// The keys of all databases.
+struct Keyspace {
+    dbs: Vec<Db>,
+}
+
+impl Keyspace {
+    fn new() -> Self {
+        Keyspace {
+            dbs: (0..DATABASES).map(|_| Db::default()).collect(),
+        }
+    }
+
+    // Return the database `db`, after removing a batch of its expired keys.
+    fn db(&mut self, db: usize) -> &mut Db {
+        let db = &mut self.dbs[db];
+        db.remove_expired(EXPIRE_BATCH);
+        db
+    }
+}
+
+// The keys of a database. A key that has expired is removed when it is
+// accessed, and the keys that are not accessed again are removed in batches
+// in the order they expire.
+#[derive(Default)]
+struct Db {
+    entries: HashMap<Vec<u8>, Entry>,
+    // The keys with a timeout by the time they expire at.
+    expiring: BTreeSet<(Instant, Vec<u8>)>,
+}
+
+impl Db {
+    // Return the entry of `key` if it has not expired.
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        if self.entries.get(key)?.is_expired(Instant::now()) {
+            self.remove(key);
+            return None;
+        }
+        self.entries.get_mut(key)
+    }
+
+    fn contains_key(&mut self, key: &[u8]) -> bool {
+        self.get_mut(key).is_some()
+    }
+
+    fn insert(&mut self, key: Vec<u8>, entry: Entry) {
+        self.remove(&key);
+        if let Some(at) = entry.expires_at {
+            self.expiring.insert((at, key.clone()));
+        }
+        self.entries.insert(key, entry);
+    }
+
+    // Remove `key` and return whether it existed and had not expired.
+    fn remove(&mut self, key: &[u8]) -> bool {
+        match self.entries.remove_entry(key) {
+            Some((key, entry)) => {
+                if let Some(at) = entry.expires_at {
+                    self.expiring.remove(&(at, key));
+                }
+                !entry.is_expired(Instant::now())
+            }
+            None => false,
+        }
+    }
+
+    // Set the time `key` expires at. The key must exist.
+    fn set_expiry(&mut self, key: &[u8], expires_at: Option<Instant>) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            if let Some(at) = std::mem::replace(&mut entry.expires_at, expires_at) {
+                self.expiring.remove(&(at, key.to_vec()));
+            }
+            if let Some(at) = expires_at {
+                self.expiring.insert((at, key.to_vec()));
+            }
+        }
+    }
+
+    // The keys that have not expired, in no particular order.
+    fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        let now = Instant::now();
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(now))
+            .map(|(key, _)| key)
+    }
+
+    // Remove up to `limit` keys that have expired, the ones that expired
+    // first.
+    fn remove_expired(&mut self, limit: usize) {
+        let now = Instant::now();
+        for _ in 0..limit {
+            match self.expiring.first() {
+                Some((at, _)) if *at <= now => {}
+                _ => return,
+            }
+            if let Some((_, key)) = self.expiring.pop_first() {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+// The pub/sub subscriptions of the connections by id.
+#[derive(Default)]
+struct Broker {
+    subscribers: HashMap<u64, Subscriptions>,
+}
+
+struct Subscriptions {
+    writer: Arc<Mutex<TcpStream>>,
+    channels: BTreeSet<Vec<u8>>,
+    patterns: BTreeSet<Vec<u8>>,
+}
+
+// The state of a single connection.
+struct Connection {
+    shared: Arc<Shared>,
+    id: u64,
+    db: usize,
+    // Messages are published to the connection from the threads of other
+    // connections, so writes go through a lock.
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+fn serve(mut stream: TcpStream, shared: Arc<Shared>) -> io::Result<()> {
+    let id = shared.next_id.fetch_add(1, Ordering::SeqCst);
+    shared
+        .connections
+        .lock()
+        .unwrap()
+        .insert(id, stream.try_clone()?);
+    let mut conn = Connection {
+        writer: Arc::new(Mutex::new(stream.try_clone()?)),
+        shared,
+        id,
+        db: 0,
+    };
+    let result = conn.serve(&mut stream);
+    conn.shared.broker.lock().unwrap().subscribers.remove(&id);
+    conn.shared.connections.lock().unwrap().remove(&id);
+    result
+}
+
+// Return the arguments of a command, which clients send as an array of bulk
+// strings.
+fn command_args(cmd: RedisType) -> Option<Vec<Vec<u8>>> {
+    match cmd {
+        RedisType::Array(items) if !items.is_empty() => items
+            .into_iter()
+            .map(|item| match item {
+                RedisType::BulkString(arg) => Some(arg),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn error(msg: &str) -> RedisType {
+    RedisType::Error(msg.to_string())
+}
+
+fn ok() -> RedisType {
+    RedisType::SimpleString("OK".to_string())
+}
+
+fn bulk(value: Option<&[u8]>) -> RedisType {
+    match value {
+        Some(value) => RedisType::BulkString(value.to_vec()),
+        None => RedisType::Null,
+    }
+}
+
+fn parse_int(arg: &[u8]) -> Option<i64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+// Return the time after `amount` units of `unit`, or `None` if the amount is
+// not a positive integer.
+fn expires_at(amount: &[u8], unit: Duration) -> Option<Instant> {
+    match parse_int(amount) {
+        Some(n) if n > 0 => Instant::now().checked_add(unit.checked_mul(u32::try_from(n).ok()?)?),
+        _ => None,
+    }
+}
+
+impl Connection {
+    fn serve(&mut self, stream: &mut TcpStream) -> io::Result<()> {
+        let mut decoder = Decoder::new();
+        let mut buf = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            match decoder.decode::<RedisType>(&buf) {
+                Ok(Decoded::Frame(cmd, len)) => {
+                    buf.drain(..len);
+                    let replies = match command_args(cmd) {
+                        Some(args) => self.handle(&args),
+                        None => vec![error(
+                            "ERR Protocol error: expected an array of bulk strings",
+                        )],
+                    };
+                    self.write(&replies)?;
+                }
+                Ok(Decoded::Incomplete) => {
+                    let n = stream.read(&mut chunk)?;
+                    if n == 0 {
+                        return Ok(());
+                    }
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                Err(e) => {
+                    return self.write(&[error(&format!("ERR Protocol error: {}", e))]);
+                }
+            }
+        }
+    }
+
+    fn write(&self, replies: &[RedisType]) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        for reply in replies {
+            serde_resp::to_writer(&mut bytes, reply).unwrap();
+        }
+        self.writer.lock().unwrap().write_all(&bytes)
+    }
+
+    // Run a command and return its replies. Only the (un)subscribe commands
+    // have more than one.
+    fn handle(&mut self, args: &[Vec<u8>]) -> Vec<RedisType> {
+        let name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+        let args = &args[1..];
+        let arity_ok = match name.as_str() {
+            "ping" => args.len() <= 1,
+            "echo" | "get" | "incr" | "keys" | "select" => args.len() == 1,
+            "set" => args.len() >= 2,
+            "del" | "exists" | "mget" | "subscribe" | "psubscribe" => !args.is_empty(),
+            "expire" | "publish" => args.len() == 2,
+            "unsubscribe" | "punsubscribe" => true,
+            _ => {
+                return vec![error(&format!("ERR unknown command '{}'", name))];
+            }
+        };
+        if !arity_ok {
+            return vec![error(&format!(
+                "ERR wrong number of arguments for '{}' command",
+                name
+            ))];
+        }
+        match name.as_str() {
+            "subscribe" | "psubscribe" | "unsubscribe" | "punsubscribe" => {
+                self.subscription(&name, args)
+            }
+            "publish" => vec![self.publish(&args[0], &args[1])],
+            _ => vec![self.dispatch(&name, args)],
+        }
+    }
+
+    // Update the subscriptions of the connection. The server confirms every
+    // channel or pattern with the number of subscriptions left.
+    fn subscription(&mut self, kind: &str, args: &[Vec<u8>]) -> Vec<RedisType> {
+        let mut broker = self.shared.broker.lock().unwrap();
+        let subscriptions = broker
+            .subscribers
+            .entry(self.id)
+            .or_insert_with(|| Subscriptions {
+                writer: Arc::clone(&self.writer),
+                channels: BTreeSet::new(),
+                patterns: BTreeSet::new(),
+            });
+        let patterns = kind.starts_with('p');
+        let subscribe = !kind.ends_with("unsubscribe");
+        let names: Vec<Vec<u8>> = match (args.is_empty(), patterns) {
+            // unsubscribing without arguments drops all subscriptions
+            (true, false) => subscriptions.channels.iter().cloned().collect(),
+            (true, true) => subscriptions.patterns.iter().cloned().collect(),
+            (false, _) => args.to_vec(),
+        };
+        let mut replies = Vec::new();
+        for name in &names {
+            let set = if patterns {
+                &mut subscriptions.patterns
+            } else {
+                &mut subscriptions.channels
+            };
+            if subscribe {
+                set.insert(name.clone());
+            } else {
+                set.remove(name);
+            }
+            replies.push(confirmation(kind, Some(name), subscriptions));
+        }
+        if replies.is_empty() {
+            replies.push(confirmation(kind, None, subscriptions));
+        }
+        replies
+    }
+
+    // Send `message` to the subscribers of `channel` and return their number.
+    fn publish(&self, channel: &[u8], message: &[u8]) -> RedisType {
+        let broker = self.shared.broker.lock().unwrap();
+        let mut receivers = 0;
+        for subscriptions in broker.subscribers.values() {
+            let mut frames = Vec::new();
+            if subscriptions.channels.contains(channel) {
+                frames.push(RedisType::Array(vec![
+                    bulk(Some(b"message")),
+                    bulk(Some(channel)),
+                    bulk(Some(message)),
+                ]));
+            }
+            for pattern in &subscriptions.patterns {
+                if glob_match(pattern, channel) {
+                    frames.push(RedisType::Array(vec![
+                        bulk(Some(b"pmessage")),
+                        bulk(Some(pattern)),
+                        bulk(Some(channel)),
+                        bulk(Some(message)),
+                    ]));
+                }
+            }
+            let mut bytes = Vec::new();
+            for frame in &frames {
+                serde_resp::to_writer(&mut bytes, frame).unwrap();
+            }
+            receivers += frames.len();
+            // a subscriber that went away is removed by its own thread
+            let _ = subscriptions.writer.lock().unwrap().write_all(&bytes);
+        }
+        RedisType::Integer(receivers as i64)
+    }
+
+    fn dispatch(&mut self, name: &str, args: &[Vec<u8>]) -> RedisType {
+        let mut keyspace = self.shared.keyspace.lock().unwrap();
+        let db = keyspace.db(self.db);
+        match name {
+            "ping" => match args.first() {
+                Some(msg) => bulk(Some(msg)),
+                None => RedisType::SimpleString("PONG".to_string()),
+            },
+            "echo" => bulk(Some(&args[0])),
+            "get" => bulk(db.get_mut(&args[0]).map(|entry| &entry.value[..])),
+            "mget" => RedisType::Array(
+                args.iter()
+                    .map(|key| bulk(db.get_mut(key).map(|entry| &entry.value[..])))
+                    .collect(),
+            ),
+            "set" => set(db, args),
+            "del" => {
+                let n = args.iter().filter(|key| db.remove(key)).count();
+                RedisType::Integer(n as i64)
+            }
+            "exists" => {
+                let n = args.iter().filter(|key| db.contains_key(key)).count();
+                RedisType::Integer(n as i64)
+            }
+            "incr" => {
+                let n = match db.get_mut(&args[0]) {
+                    Some(entry) => parse_int(&entry.value),
+                    None => Some(0),
+                };
+                let n = match n.and_then(|n| n.checked_add(1)) {
+                    Some(n) => n,
+                    None => return error("ERR value is not an integer or out of range"),
+                };
+                let value = n.to_string().into_bytes();
+                // the timeout of the key is kept
+                match db.get_mut(&args[0]) {
+                    Some(entry) => entry.value = value,
+                    None => db.insert(
+                        args[0].clone(),
+                        Entry {
+                            value,
+                            expires_at: None,
+                        },
+                    ),
+                }
+                RedisType::Integer(n)
+            }
+            "expire" => {
+                let seconds = match parse_int(&args[1]) {
+                    Some(seconds) => seconds,
+                    None => return error("ERR value is not an integer or out of range"),
+                };
+                // a non-positive timeout deletes the key right away
+                let at = if seconds > 0 {
+                    match expires_at(&args[1], Duration::from_secs(1)) {
+                        Some(at) => Some(at),
+                        None => return error("ERR invalid expire time in 'expire' command"),
+                    }
+                } else {
+                    None
+                };
+                if !db.contains_key(&args[0]) {
+                    return RedisType::Integer(0);
+                }
+                match at {
+                    Some(at) => db.set_expiry(&args[0], Some(at)),
+                    None => {
+                        db.remove(&args[0]);
+                    }
+                }
+                RedisType::Integer(1)
+            }
+            "keys" => {
+                let mut keys: Vec<&Vec<u8>> =
+                    db.keys().filter(|key| glob_match(&args[0], key)).collect();
+                keys.sort();
+                RedisType::Array(keys.into_iter().map(|key| bulk(Some(key))).collect())
+            }
+            "select" => match parse_int(&args[0]) {
+                Some(index) if (0..DATABASES as i64).contains(&index) => {
+                    self.db = index as usize;
+                    ok()
+                }
+                Some(_) => error("ERR DB index is out of range"),
+                None => error("ERR value is not an integer or out of range"),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn confirmation(kind: &str, name: Option<&[u8]>, subscriptions: &Subscriptions) -> RedisType {
+    let count = subscriptions.channels.len() + subscriptions.patterns.len();
+    RedisType::Array(vec![
+        bulk(Some(kind.as_bytes())),
+        bulk(name),
+        RedisType::Integer(count as i64),
+    ])
+}
+
+// `SET key value [EX seconds | PX milliseconds] [NX | XX]`
+fn set(db: &mut Db, args: &[Vec<u8>]) -> RedisType {
+    let mut expiry = None;
+    let mut nx = false;
+    let mut xx = false;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let option = option.to_ascii_uppercase();
+        match &option[..] {
+            b"EX" | b"PX" if expiry.is_none() => {
+                let unit = if option == b"EX" {
+                    Duration::from_secs(1)
+                } else {
+                    Duration::from_millis(1)
+                };
+                match options.next().and_then(|amount| expires_at(amount, unit)) {
+                    Some(at) => expiry = Some(at),
+                    None => return error("ERR invalid expire time in 'set' command"),
+                }
+            }
+            b"NX" if !xx => nx = true,
+            b"XX" if !nx => xx = true,
+            _ => return error("ERR syntax error"),
+        }
+    }
+    let exists = db.contains_key(&args[0]);
+    if (nx && exists) || (xx && !exists) {
+        return RedisType::Null;
+    }
+    let entry = Entry {
+        value: args[1].clone(),
+        expires_at: expiry,
+    };
+    db.insert(args[0].clone(), entry);
+    ok()
+}
+
+// Match `key` against a glob-style pattern as `KEYS` does: `*` matches any
+// bytes, `?` a single byte, `[abc]` and `[a-z]` a byte of a set, and `\`
+// escapes the next byte.
+fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => key.is_empty(),
+        Some((b'*', rest)) => (0..=key.len()).any(|i| glob_match(rest, &key[i..])),
+        Some((b'?', rest)) => !key.is_empty() && glob_match(rest, &key[1..]),
+        Some((b'[', rest)) => {
+            let end = match rest.iter().position(|&b| b == b']') {
+                Some(end) => end,
+                None => return key.first() == Some(&b'[') && glob_match(rest, &key[1..]),
+            };
+            let (set, rest) = (&rest[..end], &rest[end + 1..]);
+            let byte = match key.first() {
+                Some(&byte) => byte,
+                None => return false,
+            };
+            let (negate, set) = match set.split_first() {
+                Some((b'^', set)) => (true, set),
+                _ => (false, set),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == b'-' {
+                    found |= (set[i]..=set[i + 2]).contains(&byte);
+                    i += 3;
+                } else {
+                    found |= set[i] == byte;
+                    i += 1;
+                }
+            }
+            found != negate && glob_match(rest, &key[1..])
+        }
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            key.first() == Some(&rest[0]) && glob_match(&rest[1..], &key[1..])
+        }
+        Some((&byte, rest)) => key.first() == Some(&byte) && glob_match(rest, &key[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{Client, Cmd, ConnectOptions, Error, SetOptions};
+
+    fn client(server: &Server) -> Client {
+        Client::connect(server.addr()).unwrap()
+    }
+
+    #[test]
+    fn test_strings() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut client = client(&server);
+        assert_eq!(client.ping().unwrap(), "PONG");
+        let echo: String = client.query(&Cmd::new("ECHO").arg("hello")).unwrap();
+        assert_eq!(echo, "hello");
+        assert!(client.set("key", "value", &SetOptions::default()).unwrap());
+        assert!(!client
+            .set("key", "other", &SetOptions::default().nx())
+            .unwrap());
+        assert_eq!(
+            client.get::<String>("key").unwrap(),
+            Some("value".to_string())
+        );
+        assert_eq!(client.incr("counter").unwrap(), 1);
+        assert_eq!(client.incr("counter").unwrap(), 2);
+        let exists: i64 = client
+            .query(&Cmd::new("EXISTS").arg("key").arg("counter").arg("missing"))
+            .unwrap();
+        assert_eq!(exists, 2);
+        assert_eq!(client.del(&["key", "missing"]).unwrap(), 1);
+        assert_eq!(client.get::<String>("key").unwrap(), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut client = client(&server);
+        client.set("key", "value", &SetOptions::default()).unwrap();
+        match client.incr("key") {
+            Err(Error::Server(msg)) => {
+                assert_eq!(msg, "ERR value is not an integer or out of range")
+            }
+            other => panic!("expected a server error, got {:?}", other),
+        }
+        match client.query::<String>(&Cmd::new("FLUSHALL")) {
+            Err(Error::Server(msg)) => assert_eq!(msg, "ERR unknown command 'flushall'"),
+            other => panic!("expected a server error, got {:?}", other),
+        }
+        match client.query::<String>(&Cmd::new("GET")) {
+            Err(Error::Server(msg)) => {
+                assert_eq!(msg, "ERR wrong number of arguments for 'get' command")
+            }
+            other => panic!("expected a server error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_chained_attributes() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        // small enough to be read whole, so the connection closes cleanly
+        stream.write_all(&b"|0\r\n".repeat(1_000)).unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert!(reply.starts_with("-ERR Protocol error: "), "{:?}", reply);
+        assert_eq!(client(&server).ping().unwrap(), "PONG");
+    }
+
+    #[test]
+    fn test_expiry() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut client = client(&server);
+        let reply: String = client
+            .query(
+                &Cmd::new("SET")
+                    .arg("short")
+                    .arg("value")
+                    .arg("PX")
+                    .arg("20"),
+            )
+            .unwrap();
+        assert_eq!(reply, "OK");
+        client
+            .set("long", "value", &SetOptions::default().ex(60))
+            .unwrap();
+        client.set("gone", "value", &SetOptions::default()).unwrap();
+        assert!(client.expire("gone", 0).unwrap());
+        assert!(!client.expire("missing", 10).unwrap());
+        // a timeout out of range is refused instead of dropping the expiry
+        match client.expire("long", 5_000_000_000) {
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "server error: ERR invalid expire time in 'expire' command"
+            ),
+            Ok(_) => panic!("expected EXPIRE to fail"),
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(client.get::<String>("short").unwrap(), None);
+        assert_eq!(client.get::<String>("gone").unwrap(), None);
+        assert_eq!(
+            client.get::<String>("long").unwrap(),
+            Some("value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expire_in_batches() {
+        let mut db = Db::default();
+        let past = Instant::now();
+        let future = past + Duration::from_secs(60);
+        for i in 0..100 {
+            for (prefix, at) in [("expired", past), ("live", future)] {
+                let entry = Entry {
+                    value: Vec::new(),
+                    expires_at: Some(at),
+                };
+                db.insert(format!("{}:{}", prefix, i).into_bytes(), entry);
+            }
+        }
+        assert_eq!(db.keys().count(), 100);
+        db.remove_expired(EXPIRE_BATCH);
+        assert_eq!(db.entries.len(), 200 - EXPIRE_BATCH);
+        // an expired key is removed when it is accessed
+        assert!(db.get_mut(b"expired:99").is_none());
+        assert_eq!(db.entries.len(), 200 - EXPIRE_BATCH - 1);
+        db.set_expiry(b"live:0", None);
+        for _ in 0..10 {
+            db.remove_expired(EXPIRE_BATCH);
+        }
+        assert_eq!(db.entries.len(), 100);
+        assert_eq!(db.expiring.len(), 99);
+        assert!(db.get_mut(b"live:0").is_some());
+    }
+
+    #[test]
+    fn test_keys() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut client = client(&server);
+        for key in &["user:1", "user:2", "user:10", "session:1"] {
+            client.set(key, "value", &SetOptions::default()).unwrap();
+        }
+        let keys = |client: &mut Client, pattern: &str| -> Vec<String> {
+            client.query(&Cmd::new("KEYS").arg(pattern)).unwrap()
+        };
+        assert_eq!(keys(&mut client, "*").len(), 4);
+        assert_eq!(keys(&mut client, "user:?"), vec!["user:1", "user:2"]);
+        assert_eq!(keys(&mut client, "user:[^2]*"), vec!["user:1", "user:10"]);
+        assert_eq!(keys(&mut client, "*:1"), vec!["session:1", "user:1"]);
+    }
+
+    #[test]
+    fn test_select() {
+        let server = Server::start("127.0.0.1:0").unwrap();
+        let mut first = client(&server);
+        let mut second =
+            Client::connect_with(server.addr(), &ConnectOptions::default().db(1)).unwrap();
+        first.set("key", "db0", &SetOptions::default()).unwrap();
+        second.set("key", "db1", &SetOptions::default()).unwrap();
+        assert_eq!(first.get::<String>("key").unwrap(), Some("db0".to_string()));
+        assert_eq!(
+            second.get::<String>("key").unwrap(),
+            Some("db1".to_string())
+        );
+        match first.query::<String>(&Cmd::new("SELECT").arg("16")) {
+            Err(Error::Server(msg)) => assert_eq!(msg, "ERR DB index is out of range"),
+            other => panic!("expected a server error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h*llo", b"heeeello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+        assert!(!glob_match(b"h\\*llo", b"hello"));
+    }
+}
@@ -0,0 +1,461 @@
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+// An HTTP/1.x request.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    // The path of the request target, without the query.
+    pub path: String,
+    // The query of the request target, after `?`, if there is one.
+    pub query: Option<String>,
+    // The header fields in the order they came, with names as sent.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+// How much of a request is read before giving up on it.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    // The request line and the header fields, or the chunk trailers.
+    pub max_head: usize,
+    pub max_body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_head: 8 * 1024,
+            max_body: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // The request is malformed (400).
+    BadRequest(&'static str),
+    // The body is larger than `Limits::max_body` (413).
+    PayloadTooLarge,
+    // The request line and header fields are larger than `Limits::max_head`
+    // (431).
+    HeadersTooLarge,
+    // Reading failed or the connection closed before a request came.
+    Io(io::Error),
+}
+
+impl Error {
+    // The status line to answer with, or `None` if there is no one to
+    // answer.
+    pub fn status_line(&self) -> Option<&'static str> {
+        match self {
+            Error::BadRequest(_) => Some("HTTP/1.1 400 Bad Request"),
+            Error::PayloadTooLarge => Some("HTTP/1.1 413 Payload Too Large"),
+            Error::HeadersTooLarge => Some("HTTP/1.1 431 Request Header Fields Too Large"),
+            Error::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            Error::PayloadTooLarge => write!(f, "payload too large"),
+            Error::HeadersTooLarge => write!(f, "request header fields too large"),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl Request {
+    // Read a request from `reader`. Nothing after the request is read, so
+    // the next request on the connection can be read after it.
+    pub fn read_from<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Request, Error> {
+        let mut head_left = limits.max_head;
+        let request_line = match read_line(reader, &mut head_left)? {
+            Some(line) => line,
+            None => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        };
+        let (method, target, version) = parse_request_line(&request_line)?;
+        if !target.starts_with('/') {
+            return Err(Error::BadRequest("request target is not a path"));
+        }
+        let (path, query) = match target.find('?') {
+            Some(i) => (&target[..i], Some(target[i + 1..].to_string())),
+            None => (target, None),
+        };
+        let headers = read_fields(reader, &mut head_left)?;
+        if version == "HTTP/1.1"
+            && !headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("host"))
+        {
+            return Err(Error::BadRequest("missing Host header"));
+        }
+        let mut request = Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            headers,
+            body: Vec::new(),
+        };
+        request.body = request.read_body(reader, limits)?;
+        Ok(request)
+    }
+
+    // The value of the first header field called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn read_body<R: BufRead>(&self, reader: &mut R, limits: &Limits) -> Result<Vec<u8>, Error> {
+        let content_length = self.content_length()?;
+        let codings = self.transfer_codings();
+        if !codings.is_empty() {
+            // a message with both could be read two ways, which is how
+            // requests are smuggled past proxies
+            if content_length.is_some() {
+                return Err(Error::BadRequest(
+                    "both Transfer-Encoding and Content-Length",
+                ));
+            }
+            // every Transfer-Encoding field counts, a proxy may only look at
+            // the last one
+            if codings.len() != 1 || !codings[0].eq_ignore_ascii_case("chunked") {
+                return Err(Error::BadRequest("unsupported transfer coding"));
+            }
+            return read_chunked(reader, limits);
+        }
+        let len = content_length.unwrap_or(0);
+        if len > limits.max_body as u64 {
+            return Err(Error::PayloadTooLarge);
+        }
+        let mut body = vec![0; len as usize];
+        reader.read_exact(&mut body).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::BadRequest("body shorter than Content-Length"),
+            _ => Error::Io(e),
+        })?;
+        Ok(body)
+    }
+
+    // The transfer codings of all the Transfer-Encoding fields, in the order
+    // they were applied.
+    fn transfer_codings(&self) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("transfer-encoding"))
+            .flat_map(|(_, value)| value.split(','))
+            .map(str::trim)
+            .collect()
+    }
+
+    // Content-Length may be repeated, or be a list, as long as all the values
+    // are the same.
+    fn content_length(&self) -> Result<Option<u64>, Error> {
+        let mut length = None;
+        let values = self
+            .headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .flat_map(|(_, value)| value.split(','));
+        for value in values {
+            let value = value.trim();
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(Error::BadRequest("invalid Content-Length"));
+            }
+            // too many digits to fit is certainly too large
+            let value = value.parse().map_err(|_| Error::PayloadTooLarge)?;
+            if length.is_some_and(|length| length != value) {
+                return Err(Error::BadRequest("conflicting Content-Length"));
+            }
+            length = Some(value);
+        }
+        Ok(length)
+    }
+}
+
+// Split `METHOD target HTTP/1.x`.
+fn parse_request_line(line: &str) -> Result<(&str, &str, &str), Error> {
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(Error::BadRequest("malformed request line")),
+    };
+    if method.is_empty() || !method.bytes().all(is_token) {
+        return Err(Error::BadRequest("invalid method"));
+    }
+    if version != "HTTP/1.1" && version != "HTTP/1.0" {
+        return Err(Error::BadRequest("unsupported HTTP version"));
+    }
+    Ok((method, target, version))
+}
+
+// The longest chunk size line, with its chunk extensions.
+const MAX_CHUNK_LINE: usize = 1024;
+
+// Read `name: value` lines up to an empty line, for the header fields or the
+// trailers of a chunked body.
+fn read_fields<R: BufRead>(
+    reader: &mut R,
+    left: &mut usize,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut fields = Vec::new();
+    loop {
+        let line = read_line(reader, left)?.ok_or(Error::BadRequest("incomplete header"))?;
+        if line.is_empty() {
+            return Ok(fields);
+        }
+        let colon = line
+            .find(':')
+            .ok_or(Error::BadRequest("header field without colon"))?;
+        let name = &line[..colon];
+        // this also rejects continuation lines, which start with whitespace
+        if name.is_empty() || !name.bytes().all(is_token) {
+            return Err(Error::BadRequest("invalid header field name"));
+        }
+        let value = line[colon + 1..].trim_matches(|c| c == ' ' || c == '\t');
+        fields.push((name.to_string(), value.to_string()));
+    }
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R, limits: &Limits) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::new();
+    loop {
+        // each chunk size line has its own limit, so many small chunks are fine
+        let mut line_left = MAX_CHUNK_LINE;
+        let line = match read_line(reader, &mut line_left) {
+            Err(Error::HeadersTooLarge) => return Err(Error::BadRequest("chunk size too long")),
+            line => line?.ok_or(Error::BadRequest("incomplete chunk"))?,
+        };
+        // chunk extensions after `;` are ignored
+        let size = line.split(';').next().unwrap_or("").trim();
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(Error::BadRequest("invalid chunk size"));
+        }
+        let size = usize::from_str_radix(size, 16).map_err(|_| Error::PayloadTooLarge)?;
+        if size == 0 {
+            // trailers are read and dropped, only they count against
+            // `Limits::max_head`
+            let mut trailers_left = limits.max_head;
+            read_fields(reader, &mut trailers_left)?;
+            return Ok(body);
+        }
+        if size > limits.max_body - body.len() {
+            return Err(Error::PayloadTooLarge);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader
+            .read_exact(&mut body[start..])
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => Error::BadRequest("incomplete chunk"),
+                _ => Error::Io(e),
+            })?;
+        let mut crlf = [0; 2];
+        match reader.read_exact(&mut crlf) {
+            Ok(()) if &crlf == b"\r\n" => {}
+            Ok(()) => return Err(Error::BadRequest("chunk not followed by CRLF")),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(Error::BadRequest("incomplete chunk"))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Read a line ending in CRLF, or a bare LF, without its line ending. Reading
+// more than `left` bytes fails with 431. `None` means the input ended before
+// the line started.
+fn read_line<R: BufRead>(reader: &mut R, left: &mut usize) -> Result<Option<String>, Error> {
+    let mut line = Vec::new();
+    let len = reader
+        .by_ref()
+        .take(*left as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if len == 0 {
+        return Ok(None);
+    }
+    if len > *left {
+        return Err(Error::HeadersTooLarge);
+    }
+    *left -= len;
+    if line.pop() != Some(b'\n') {
+        return Err(Error::BadRequest("incomplete line"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| Error::BadRequest("header is not UTF-8"))
+}
+
+// Whether `b` may appear in a method or a header field name.
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Request, Error> {
+        Request::read_from(&mut input.as_bytes(), &Limits::default())
+    }
+
+    fn status(input: &str) -> &'static str {
+        parse(input).unwrap_err().status_line().unwrap()
+    }
+
+    #[test]
+    fn request() {
+        let request = parse(
+            "POST /search?q=rust&page=2 HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             Content-Type:text/plain \r\n\
+             Content-Length: 5\r\n\
+             \r\n\
+             hello",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/search");
+        assert_eq!(request.query.as_deref(), Some("q=rust&page=2"));
+        assert_eq!(request.header("content-type"), Some("text/plain"));
+        assert_eq!(request.headers.len(), 3);
+        assert_eq!(request.body, b"hello");
+
+        let request = parse("GET / HTTP/1.0\n\n").unwrap();
+        assert_eq!(request.path, "/");
+        assert_eq!(request.query, None);
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let mut input =
+            "GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n".as_bytes();
+        let limits = Limits::default();
+        assert_eq!(Request::read_from(&mut input, &limits).unwrap().path, "/a");
+        assert_eq!(Request::read_from(&mut input, &limits).unwrap().path, "/b");
+        assert!(matches!(
+            Request::read_from(&mut input, &limits),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn chunked() {
+        let request = parse(
+            "POST / HTTP/1.1\r\n\
+             Host: x\r\n\
+             Transfer-Encoding: chunked\r\n\
+             \r\n\
+             4;ext=1\r\nWiki\r\n\
+             5\r\npedia\r\n\
+             0\r\n\
+             Expires: never\r\n\
+             \r\n",
+        )
+        .unwrap();
+        assert_eq!(request.body, b"Wikipedia");
+
+        let head = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert_eq!(
+            status(&format!("{}z\r\n", head)),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status(&format!("{}4\r\nWikiXX", head)),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status(&format!("{}4\r\nWi", head)),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            status(&format!("{}200000\r\n", head)),
+            "HTTP/1.1 413 Payload Too Large"
+        );
+        assert_eq!(
+            status(&format!("{}1;{}\r\n", head, "x".repeat(2000))),
+            "HTTP/1.1 400 Bad Request"
+        );
+    }
+
+    #[test]
+    fn many_small_chunks() {
+        let head = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n";
+        // the chunk size lines add up to more than `Limits::max_head`
+        let request = parse(&format!("{}{}0\r\n\r\n", head, "1\r\nx\r\n".repeat(10_000))).unwrap();
+        assert_eq!(request.body, vec![b'x'; 10_000]);
+
+        let trailers = format!("0\r\nX-Long: {}\r\n\r\n", "x".repeat(9000));
+        assert_eq!(
+            status(&format!("{}1\r\nx\r\n{}", head, trailers)),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let bad = [
+            "GET /\r\n\r\n",
+            "GET / HTTP/1.1 extra\r\nHost: x\r\n\r\n",
+            "G(T / HTTP/1.1\r\nHost: x\r\n\r\n",
+            "GET / HTTP/2.0\r\nHost: x\r\n\r\n",
+            "GET http://x/ HTTP/1.1\r\nHost: x\r\n\r\n",
+            "GET / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost x\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\n folded\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nContent-Length: -1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nContent-Length: 1, 2\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nabc",
+            "GET / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: gzip\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: gzip\r\n\r\n0\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked, chunked\r\n\r\n0\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nContent-Length: 1\r\n\r\n",
+        ];
+        for input in &bad {
+            assert_eq!(status(input), "HTTP/1.1 400 Bad Request", "{:?}", input);
+        }
+        let request = parse("GET / HTTP/1.1\r\nHost: x\r\nContent-Length: 2, 2\r\n\r\nab").unwrap();
+        assert_eq!(request.body, b"ab");
+    }
+
+    #[test]
+    fn limits() {
+        let long_header = format!(
+            "GET / HTTP/1.1\r\nHost: x\r\nX: {}\r\n\r\n",
+            "a".repeat(9000)
+        );
+        assert_eq!(
+            status(&long_header),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        let long_target = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(9000));
+        assert_eq!(
+            status(&long_target),
+            "HTTP/1.1 431 Request Header Fields Too Large"
+        );
+        let large_body = "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 2000000\r\n\r\n";
+        assert_eq!(status(large_body), "HTTP/1.1 413 Payload Too Large");
+        let huge_body =
+            "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 99999999999999999999999\r\n\r\n";
+        assert_eq!(status(huge_body), "HTTP/1.1 413 Payload Too Large");
+    }
+}
@@ -1,3 +1,5 @@
+pub mod http;
+
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
@@ -1,5 +1,6 @@
+use restaurant::http::{Limits, Request};
 use restaurant::ThreadPool;
-use std::io::{Read, Write};
+use std::io::{BufReader, Write};
 use std::net::{TcpListener, TcpStream};
 use std::time::Duration;
 use std::{fs, thread};
@@ -16,23 +17,44 @@ fn main() {
     println!("Shutting down");
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let mut buffer = [0; 512];
-    stream.read(&mut buffer).unwrap();
-    //    println!("Request: {}", String::from_utf8_lossy(&buffer));
+fn handle_connection(stream: TcpStream) {
+    let mut reader = BufReader::new(&stream);
+    let response = match Request::read_from(&mut reader, &Limits::default()) {
+        Ok(request) => respond(&request),
+        Err(err) => match err.status_line() {
+            Some(status_line) => response(status_line, &format!("{}\n", err)),
+            // the connection closed or broke, there is no one to answer
+            None => return,
+        },
+    };
+    let mut stream = &stream;
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
 
-    let get = b"GET / HTTP/1.1\r\n";
-    let get_sleep = b"GET /sleep HTTP/1.1\r\n";
-    let (status_line, filename) = if buffer.starts_with(get) {
-        ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-    } else if buffer.starts_with(get_sleep) {
-        thread::sleep(Duration::from_secs(5));
-        ("HTTP/1.1 200 OK\r\n\r\n", "hello.html")
-    } else {
-        ("HTTP/1.1 404 NOT FOUND\r\n\r\n", "404.html")
+fn respond(request: &Request) -> String {
+    let (status_line, filename) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => ("HTTP/1.1 200 OK", "hello.html"),
+        ("GET", "/sleep") => {
+            thread::sleep(Duration::from_secs(5));
+            ("HTTP/1.1 200 OK", "hello.html")
+        }
+        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
     };
-    let contents = fs::read_to_string(filename).unwrap();
-    let response = format!("{}{}", status_line, contents);
-    stream.write(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+    match fs::read_to_string(filename) {
+        Ok(contents) => response(status_line, &contents),
+        Err(_) => response(
+            "HTTP/1.1 500 Internal Server Error",
+            "internal server error\n",
+        ),
+    }
+}
+
+fn response(status_line: &str, body: &str) -> String {
+    format!(
+        "{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status_line,
+        body.len(),
+        body
+    )
 }
@@ -0,0 +1,62 @@
+#[macro_use]
+extern crate criterion;
+
+use criterion::{Criterion, ParameterizedBenchmark};
+use kvs::thread_pool::RayonThreadPool;
+use kvs::{KvStore, KvsEngine};
+use rand::prelude::*;
+use tempfile::TempDir;
+use tokio::prelude::*;
+
+const KEYS: u32 = 1 << 12;
+const CONCURRENCY: u32 = 8;
+
+// Fills a store with `KEYS` keys. If `reopen` is true, the store is reopened so all
+// values are in immutable log files read through memory maps. Otherwise, all values
+// are in the log file being written and are read through file handles.
+fn prepare(temp_dir: &TempDir, reopen: bool) -> KvStore<RayonThreadPool> {
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), CONCURRENCY).unwrap();
+    for i in 0..KEYS {
+        store
+            .set(format!("key{}", i), "value".repeat(16))
+            .wait()
+            .unwrap();
+    }
+    if reopen {
+        drop(store);
+        KvStore::<RayonThreadPool>::open(temp_dir.path(), CONCURRENCY).unwrap()
+    } else {
+        store
+    }
+}
+
+// Issues `reads` concurrent gets of random keys and waits for all of them.
+fn random_reads(store: &KvStore<RayonThreadPool>, rng: &mut SmallRng, reads: usize) {
+    let gets: Vec<_> = (0..reads)
+        .map(|_| store.get(format!("key{}", rng.gen_range(0, KEYS))))
+        .collect();
+    future::join_all(gets).wait().unwrap();
+}
+
+fn read_heavy_bench(c: &mut Criterion) {
+    let bench = ParameterizedBenchmark::new(
+        "mmap",
+        |b, &reads| {
+            let temp_dir = TempDir::new().unwrap();
+            let store = prepare(&temp_dir, true);
+            let mut rng = SmallRng::from_seed([0; 16]);
+            b.iter(|| random_reads(&store, &mut rng, reads))
+        },
+        vec![1, 64, 1024],
+    )
+    .with_function("file", |b, &reads| {
+        let temp_dir = TempDir::new().unwrap();
+        let store = prepare(&temp_dir, false);
+        let mut rng = SmallRng::from_seed([0; 16]);
+        b.iter(|| random_reads(&store, &mut rng, reads))
+    });
+    c.bench("read_heavy_bench", bench);
+}
+
+criterion_group!(benches, read_heavy_bench);
+criterion_main!(benches);
@@ -1,5 +1,5 @@
 use clap::AppSettings;
-use kvs::{KvsClient, Result};
+use kvs::{Event, KvsClient, Result, ShardedKvsClient};
 use std::net::SocketAddr;
 use std::process::exit;
 use structopt::StructOpt;
@@ -31,6 +31,14 @@ enum Command {
             parse(try_from_str)
         )]
         addr: SocketAddr,
+        #[structopt(
+            long,
+            help = "Routes the key to one of the servers by consistent hashing instead of --addr",
+            value_name = "IP:PORT,...",
+            raw(use_delimiter = "true"),
+            parse(try_from_str)
+        )]
+        servers: Vec<SocketAddr>,
     },
     #[structopt(name = "set", about = "Set the value of a string key to a string")]
     Set {
@@ -46,6 +54,14 @@ enum Command {
             parse(try_from_str)
         )]
         addr: SocketAddr,
+        #[structopt(
+            long,
+            help = "Routes the key to one of the servers by consistent hashing instead of --addr",
+            value_name = "IP:PORT,...",
+            raw(use_delimiter = "true"),
+            parse(try_from_str)
+        )]
+        servers: Vec<SocketAddr>,
     },
     #[structopt(name = "rm", about = "Remove a given string key")]
     Remove {
@@ -59,6 +75,55 @@ enum Command {
             parse(try_from_str)
         )]
         addr: SocketAddr,
+        #[structopt(
+            long,
+            help = "Routes the key to one of the servers by consistent hashing instead of --addr",
+            value_name = "IP:PORT,...",
+            raw(use_delimiter = "true"),
+            parse(try_from_str)
+        )]
+        servers: Vec<SocketAddr>,
+    },
+    #[structopt(name = "watch", about = "Print changes of keys starting with a prefix")]
+    Watch {
+        #[structopt(name = "PREFIX", help = "A key prefix", default_value = "")]
+        prefix: String,
+        #[structopt(
+            long,
+            help = "Resumes from a sequence number retained by the server",
+            value_name = "SEQ"
+        )]
+        from: Option<u64>,
+        #[structopt(
+            long,
+            help = "Sets the server address",
+            value_name = "IP:PORT",
+            default_value = "127.0.0.1:4000",
+            parse(try_from_str)
+        )]
+        addr: SocketAddr,
+    },
+    #[structopt(name = "stats", about = "Print the statistics of the server")]
+    Stats {
+        #[structopt(
+            long,
+            help = "Sets the server address",
+            value_name = "IP:PORT",
+            default_value = "127.0.0.1:4000",
+            parse(try_from_str)
+        )]
+        addr: SocketAddr,
+    },
+    #[structopt(name = "promote", about = "Promote a replica to accept writes")]
+    Promote {
+        #[structopt(
+            long,
+            help = "Sets the server address",
+            value_name = "IP:PORT",
+            default_value = "127.0.0.1:4000",
+            parse(try_from_str)
+        )]
+        addr: SocketAddr,
     },
 }
 
@@ -72,23 +137,75 @@ fn main() {
 
 fn run(opt: Opt) -> Result<()> {
     match opt.command {
-        Command::Get { key, addr } => {
-            let client = KvsClient::connect(addr);
-            if let (Some(value), _) = client.and_then(move |client| client.get(key)).wait()? {
+        Command::Get { key, addr, servers } => {
+            let value = if servers.is_empty() {
+                let client = KvsClient::connect(addr);
+                client.and_then(move |client| client.get(key)).wait()?.0
+            } else {
+                ShardedKvsClient::new(&servers)?.get(key).wait()?
+            };
+            if let Some(value) = value {
                 println!("{}", value);
             } else {
                 println!("Key not found");
             }
         }
-        Command::Set { key, value, addr } => {
+        Command::Set {
+            key,
+            value,
+            addr,
+            servers,
+        } => {
+            if servers.is_empty() {
+                let client = KvsClient::connect(addr);
+                client
+                    .and_then(move |client| client.set(key, value))
+                    .wait()?;
+            } else {
+                ShardedKvsClient::new(&servers)?.set(key, value).wait()?;
+            }
+        }
+        Command::Remove { key, addr, servers } => {
+            if servers.is_empty() {
+                let client = KvsClient::connect(addr);
+                client.and_then(move |client| client.remove(key)).wait()?;
+            } else {
+                ShardedKvsClient::new(&servers)?.remove(key).wait()?;
+            }
+        }
+        Command::Watch { prefix, from, addr } => {
             let client = KvsClient::connect(addr);
             client
-                .and_then(move |client| client.set(key, value))
+                .map(move |client| client.watch(prefix, from))
+                .flatten_stream()
+                .for_each(|event| {
+                    match event {
+                        Event::Set { seq, key, value } => println!("{} set {} {}", seq, key, value),
+                        Event::Remove { seq, key } => println!("{} rm {}", seq, key),
+                    }
+                    Ok(())
+                })
                 .wait()?;
         }
-        Command::Remove { key, addr } => {
+        Command::Stats { addr } => {
+            let client = KvsClient::connect(addr);
+            let (stats, _) = client.and_then(move |client| client.stats()).wait()?;
+            println!("read_only: {}", stats.read_only);
+            if let Some(replication) = stats.replication {
+                println!("primary: {}", replication.primary);
+                println!("connected: {}", replication.connected);
+                println!("synced: {}", replication.synced);
+                println!("promoted: {}", replication.promoted);
+                println!(
+                    "applied: {}:{}",
+                    replication.applied.gen, replication.applied.offset
+                );
+                println!("lag_bytes: {}", replication.lag_bytes);
+            }
+        }
+        Command::Promote { addr } => {
             let client = KvsClient::connect(addr);
-            client.and_then(move |client| client.remove(key)).wait()?;
+            client.and_then(move |client| client.promote()).wait()?;
         }
     }
     Ok(())
@@ -4,7 +4,7 @@ extern crate log;
 extern crate clap;
 
 use kvs::thread_pool::*;
-use kvs::{KvStore, KvsEngine, KvsServer, Result, SledKvsEngine};
+use kvs::{KvStore, KvStoreOptions, KvsEngine, KvsServer, Result, SledKvsEngine};
 use log::LevelFilter;
 use std::env;
 use std::env::current_dir;
@@ -34,6 +34,20 @@ struct Opt {
         raw(possible_values = "&Engine::variants()")
     )]
     engine: Option<Engine>,
+    #[structopt(
+        long = "replica-of",
+        help = "Replicates the server at the given address as a read-only replica",
+        value_name = "IP:PORT",
+        parse(try_from_str)
+    )]
+    replica_of: Option<SocketAddr>,
+    #[structopt(
+        long = "cache-size",
+        help = "Sets the byte budget of the cache of hot values of the kvs engine",
+        value_name = "BYTES",
+        default_value = "0"
+    )]
+    cache_size: u64,
 }
 
 arg_enum! {
@@ -69,6 +83,15 @@ fn run(opt: Opt) -> Result<()> {
     info!("kvs-server {}", env!("CARGO_PKG_VERSION"));
     info!("Storage engine: {}", engine);
     info!("Listening on {}", opt.addr);
+    if let Some(primary) = opt.replica_of {
+        info!("Replica of {}", primary);
+    }
+    if opt.cache_size > 0 {
+        match engine {
+            Engine::kvs => info!("Value cache: {} bytes", opt.cache_size),
+            Engine::sled => warn!("The sled engine has no value cache, --cache-size is ignored"),
+        }
+    }
 
     // write engine to engine file
     fs::write(current_dir()?.join("engine"), format!("{}", engine))?;
@@ -76,8 +99,13 @@ fn run(opt: Opt) -> Result<()> {
     let concurrency = num_cpus::get() as u32;
     match engine {
         Engine::kvs => run_with(
-            KvStore::<RayonThreadPool>::open(env::current_dir()?, concurrency)?,
+            KvStore::<RayonThreadPool>::open_with(
+                env::current_dir()?,
+                concurrency,
+                KvStoreOptions::new().cache_size(opt.cache_size),
+            )?,
             opt.addr,
+            opt.replica_of,
         ),
         Engine::sled => run_with(
             SledKvsEngine::<RayonThreadPool>::new(
@@ -85,12 +113,20 @@ fn run(opt: Opt) -> Result<()> {
                 concurrency,
             )?,
             opt.addr,
+            opt.replica_of,
         ),
     }
 }
 
-pub fn run_with<E: KvsEngine>(engine: E, addr: SocketAddr) -> Result<()> {
-    let server = KvsServer::new(engine);
+pub fn run_with<E: KvsEngine>(
+    engine: E,
+    addr: SocketAddr,
+    replica_of: Option<SocketAddr>,
+) -> Result<()> {
+    let mut server = KvsServer::new(engine);
+    if let Some(primary) = replica_of {
+        server = server.replica_of(primary);
+    }
     server.run(addr)
 }
 
@@ -1,5 +1,5 @@
 use crate::common::{Request, Response};
-use crate::KvsError;
+use crate::{Event, KvsError, LogEntry, LogPos, Stats};
 use std::net::SocketAddr;
 use tokio::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
 use tokio::io::{ReadHalf, WriteHalf};
@@ -64,6 +64,85 @@ impl KvsClient {
             })
     }
 
+    /// Watch changes of keys starting with `prefix`.
+    ///
+    /// The connection is turned into a stream of events and can no longer be used
+    /// for other requests. If `from` is given, the events since that sequence number
+    /// still retained by the server are received first.
+    pub fn watch(
+        self,
+        prefix: String,
+        from: Option<u64>,
+    ) -> impl Stream<Item = Event, Error = KvsError> {
+        let read_json = self.read_json;
+        self.write_json
+            .send(Request::Watch { prefix, from })
+            .map_err(KvsError::from)
+            .map(move |_| {
+                read_json
+                    .map_err(KvsError::from)
+                    .and_then(|resp| match resp {
+                        Response::Event(event) => Ok(event),
+                        Response::Err(msg) => Err(KvsError::StringError(msg)),
+                        _ => Err(KvsError::StringError("Invalid response".to_owned())),
+                    })
+            })
+            .flatten_stream()
+    }
+
+    /// Get all the keys in the server.
+    pub fn keys(self) -> impl Future<Item = (Vec<String>, Self), Error = KvsError> {
+        self.send_request(Request::Keys)
+            .and_then(move |(resp, client)| match resp {
+                Some(Response::Keys(keys)) => Ok((keys, client)),
+                Some(Response::Err(msg)) => Err(KvsError::StringError(msg)),
+                Some(_) => Err(KvsError::StringError("Invalid response".to_owned())),
+                None => Err(KvsError::StringError("No response received".to_owned())),
+            })
+    }
+
+    /// Get the statistics of the server.
+    pub fn stats(self) -> impl Future<Item = (Stats, Self), Error = KvsError> {
+        self.send_request(Request::Stats)
+            .and_then(move |(resp, client)| match resp {
+                Some(Response::Stats(stats)) => Ok((stats, client)),
+                Some(Response::Err(msg)) => Err(KvsError::StringError(msg)),
+                Some(_) => Err(KvsError::StringError("Invalid response".to_owned())),
+                None => Err(KvsError::StringError("No response received".to_owned())),
+            })
+    }
+
+    /// Promote a replica server to a primary that accepts writes.
+    pub fn promote(self) -> impl Future<Item = Self, Error = KvsError> {
+        self.send_request(Request::Promote)
+            .and_then(move |(resp, client)| match resp {
+                Some(Response::Promote) => Ok(client),
+                Some(Response::Err(msg)) => Err(KvsError::StringError(msg)),
+                Some(_) => Err(KvsError::StringError("Invalid response".to_owned())),
+                None => Err(KvsError::StringError("No response received".to_owned())),
+            })
+    }
+
+    /// Stream the log of the server from the given position.
+    ///
+    /// Like `watch`, the connection can no longer be used for other requests.
+    pub fn replicate(self, from: LogPos) -> impl Stream<Item = LogEntry, Error = KvsError> {
+        let read_json = self.read_json;
+        self.write_json
+            .send(Request::Replicate { from })
+            .map_err(KvsError::from)
+            .map(move |_| {
+                read_json
+                    .map_err(KvsError::from)
+                    .and_then(|resp| match resp {
+                        Response::Replicate(entry) => Ok(entry),
+                        Response::Err(msg) => Err(KvsError::StringError(msg)),
+                        _ => Err(KvsError::StringError("Invalid response".to_owned())),
+                    })
+            })
+            .flatten_stream()
+    }
+
     fn send_request(
         self,
         req: Request,
@@ -1,3 +1,4 @@
+use crate::{Event, LogEntry, LogPos, Stats};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -5,6 +6,11 @@ pub enum Request {
     Get { key: String },
     Set { key: String, value: String },
     Remove { key: String },
+    Watch { prefix: String, from: Option<u64> },
+    Replicate { from: LogPos },
+    Stats,
+    Promote,
+    Keys,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,5 +18,10 @@ pub enum Response {
     Get(Option<String>),
     Set,
     Remove,
+    Event(Event),
+    Replicate(LogEntry),
+    Stats(Stats),
+    Promote,
+    Keys(Vec<String>),
     Err(String),
 }
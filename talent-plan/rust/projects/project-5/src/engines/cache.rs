@@ -0,0 +1,206 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::kvs::CommandPos;
+
+/// Number of independently locked shards of a `ValueCache`.
+const SHARDS: usize = 16;
+
+/// Statistics of the value cache of a `KvStore`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of gets served from the cache
+    pub hits: u64,
+    /// Number of gets that read the log
+    pub misses: u64,
+    /// Bytes of keys and values in the cache
+    pub size: u64,
+    /// Byte budget of the cache
+    pub capacity: u64,
+}
+
+/// A cache of recently read values with a byte budget.
+///
+/// Keys are spread over `SHARDS` shards to reduce lock contention, and each shard
+/// evicts with the CLOCK algorithm. Every value is stored with the position it was
+/// read from, and a lookup only hits if the position is still the one in the index,
+/// so a value racing with a write is never served.
+pub(crate) struct ValueCache {
+    shards: Vec<Mutex<Shard>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    capacity: u64,
+}
+
+impl ValueCache {
+    pub(crate) fn new(capacity: u64) -> ValueCache {
+        let shard_capacity = capacity / SHARDS as u64;
+        ValueCache {
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(Shard::new(shard_capacity)))
+                .collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            capacity,
+        }
+    }
+
+    /// Returns the cached value of `key` if it was read from `pos`.
+    pub(crate) fn get(&self, key: &str, pos: CommandPos) -> Option<String> {
+        let value = self.shard(key).lock().unwrap().get(key, pos);
+        if value.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    /// Caches the value of `key` read from `pos`.
+    pub(crate) fn insert(&self, key: String, pos: CommandPos, value: String) {
+        self.shard(&key).lock().unwrap().insert(key, pos, value);
+    }
+
+    /// Drops the cached value of `key`. It is called when the key is set or removed.
+    pub(crate) fn invalidate(&self, key: &str) {
+        self.shard(key).lock().unwrap().remove(key);
+    }
+
+    /// Updates the position of a cached value moved from `old` to `new` by a
+    /// compaction. Values cached from other positions are dropped.
+    pub(crate) fn relocate(&self, key: &str, old: CommandPos, new: CommandPos) {
+        let mut shard = self.shard(key).lock().unwrap();
+        if let Some(&slot) = shard.slots_by_key.get(key) {
+            let entry = shard.slots[slot].as_mut().expect("indexed slot is empty");
+            if entry.pos == old {
+                entry.pos = new;
+            } else {
+                shard.remove(key);
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            size: self.shards.iter().map(|s| s.lock().unwrap().size).sum(),
+            capacity: self.capacity,
+        }
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<Shard> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+}
+
+struct Entry {
+    key: String,
+    pos: CommandPos,
+    value: String,
+    // set on every hit and cleared when the clock hand passes
+    referenced: bool,
+}
+
+impl Entry {
+    fn size(&self) -> u64 {
+        (self.key.len() + self.value.len()) as u64
+    }
+}
+
+struct Shard {
+    // the clock; `None` for free slots
+    slots: Vec<Option<Entry>>,
+    slots_by_key: HashMap<String, usize>,
+    free: Vec<usize>,
+    hand: usize,
+    size: u64,
+    capacity: u64,
+}
+
+impl Shard {
+    fn new(capacity: u64) -> Shard {
+        Shard {
+            slots: Vec::new(),
+            slots_by_key: HashMap::new(),
+            free: Vec::new(),
+            hand: 0,
+            size: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: &str, pos: CommandPos) -> Option<String> {
+        let slot = *self.slots_by_key.get(key)?;
+        let entry = self.slots[slot].as_mut().expect("indexed slot is empty");
+        if entry.pos != pos {
+            return None;
+        }
+        entry.referenced = true;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: String, pos: CommandPos, value: String) {
+        self.remove(&key);
+        let entry = Entry {
+            key,
+            pos,
+            value,
+            referenced: false,
+        };
+        if entry.size() > self.capacity {
+            return;
+        }
+        while self.size + entry.size() > self.capacity {
+            self.evict();
+        }
+
+        self.size += entry.size();
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+        };
+        self.slots_by_key.insert(entry.key.clone(), slot);
+        self.slots[slot] = Some(entry);
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(slot) = self.slots_by_key.remove(key) {
+            self.free_slot(slot);
+        }
+    }
+
+    /// Advances the clock hand to the first entry not referenced since the hand
+    /// last passed it and evicts it.
+    fn evict(&mut self) {
+        loop {
+            let slot = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            if let Some(entry) = &mut self.slots[slot] {
+                if entry.referenced {
+                    entry.referenced = false;
+                } else {
+                    let key = entry.key.clone();
+                    self.slots_by_key.remove(&key);
+                    self.free_slot(slot);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn free_slot(&mut self, slot: usize) {
+        if let Some(entry) = self.slots[slot].take() {
+            self.size -= entry.size();
+            self.free.push(slot);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::prelude::*;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use crate::{KvsError, Result};
+
+/// How many of the most recent events are kept for resuming subscribers.
+const RETAINED_EVENTS: usize = 4096;
+
+/// The sequence numbers of a run of an engine start at its epoch shifted by this
+/// many bits.
+const EPOCH_SHIFT: u32 = 32;
+
+/// A change to a key observed through `KvsEngine::watch`.
+///
+/// Every event carries a sequence number. Sequence numbers are assigned in the
+/// order the changes are applied and increase monotonically, also across restarts:
+/// the first one after a restart is greater than all the ones before it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    /// The key is set to the value.
+    Set {
+        /// Sequence number of the event
+        seq: u64,
+        /// The changed key
+        key: String,
+        /// The new value of the key
+        value: String,
+    },
+    /// The key is removed.
+    Remove {
+        /// Sequence number of the event
+        seq: u64,
+        /// The removed key
+        key: String,
+    },
+}
+
+impl Event {
+    /// Returns the sequence number of the event.
+    pub fn seq(&self) -> u64 {
+        match *self {
+            Event::Set { seq, .. } | Event::Remove { seq, .. } => seq,
+        }
+    }
+
+    /// Returns the key changed by the event.
+    pub fn key(&self) -> &str {
+        match self {
+            Event::Set { key, .. } | Event::Remove { key, .. } => key,
+        }
+    }
+}
+
+/// The kind of change passed to `ChangeFeed::publish`.
+///
+/// The sequence number is assigned by the feed.
+pub(crate) enum Change {
+    Set { key: String, value: String },
+    Remove { key: String },
+}
+
+/// A log of recent changes shared by an engine and its subscribers.
+///
+/// The feed assigns sequence numbers, retains the last `RETAINED_EVENTS` events so
+/// subscribers can resume after a reconnection, and fans out new events to every
+/// subscriber whose prefix matches the changed key.
+#[derive(Clone)]
+pub(crate) struct ChangeFeed {
+    inner: Arc<Mutex<FeedInner>>,
+}
+
+struct FeedInner {
+    next_seq: u64,
+    retained: VecDeque<Event>,
+    subscribers: Vec<Subscriber>,
+}
+
+struct Subscriber {
+    prefix: String,
+    sender: UnboundedSender<Event>,
+}
+
+impl ChangeFeed {
+    /// Creates a feed whose sequence numbers start at `epoch << EPOCH_SHIFT`.
+    ///
+    /// The epoch must be greater than the epochs of the previous runs of the engine,
+    /// so a subscriber resuming from a sequence number of a previous run is rejected
+    /// with `KvsError::SeqNotRetained` instead of getting unrelated events.
+    pub(crate) fn new(epoch: u64) -> ChangeFeed {
+        ChangeFeed {
+            inner: Arc::new(Mutex::new(FeedInner {
+                next_seq: epoch << EPOCH_SHIFT,
+                retained: VecDeque::with_capacity(RETAINED_EVENTS),
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Assigns the next sequence number to the change and delivers it.
+    ///
+    /// Subscribers whose receiving end is dropped are removed.
+    pub(crate) fn publish(&self, change: Change) {
+        let mut inner = self.inner.lock().unwrap();
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        let event = match change {
+            Change::Set { key, value } => Event::Set { seq, key, value },
+            Change::Remove { key } => Event::Remove { seq, key },
+        };
+
+        let subscribers = mem::replace(&mut inner.subscribers, Vec::new());
+        inner.subscribers = subscribers
+            .into_iter()
+            .filter_map(|mut sub| {
+                if event.key().starts_with(&sub.prefix)
+                    && sub.sender.try_send(event.clone()).is_err()
+                {
+                    return None;
+                }
+                Some(sub)
+            })
+            .collect();
+
+        if inner.retained.len() == RETAINED_EVENTS {
+            inner.retained.pop_front();
+        }
+        inner.retained.push_back(event);
+    }
+
+    /// Subscribes to changes of keys starting with `prefix`.
+    ///
+    /// If `from` is given, retained events with a sequence number not less than
+    /// `from` are replayed before any new event.
+    fn subscribe(
+        &self,
+        prefix: String,
+        from: Option<u64>,
+    ) -> Result<mpsc::UnboundedReceiver<Event>> {
+        let mut inner = self.inner.lock().unwrap();
+        let (mut tx, rx) = mpsc::unbounded_channel();
+        if let Some(from) = from {
+            // The oldest sequence number a subscriber can still resume from
+            let oldest = inner.retained.front().map_or(inner.next_seq, Event::seq);
+            if from < oldest {
+                return Err(KvsError::SeqNotRetained(from));
+            }
+            for event in inner.retained.iter() {
+                if event.seq() >= from && event.key().starts_with(&prefix) {
+                    // the receiver is still held here so sending cannot fail
+                    tx.try_send(event.clone()).expect("receiver dropped");
+                }
+            }
+        }
+        inner.subscribers.push(Subscriber { prefix, sender: tx });
+        Ok(rx)
+    }
+
+    /// Returns a stream of events for keys starting with `prefix`.
+    ///
+    /// The stream yields a single `KvsError::SeqNotRetained` error if `from` is
+    /// older than the retained log.
+    pub(crate) fn watch(
+        &self,
+        prefix: String,
+        from: Option<u64>,
+    ) -> Box<Stream<Item = Event, Error = KvsError> + Send> {
+        match self.subscribe(prefix, from) {
+            Ok(rx) => Box::new(rx.map_err(|e| KvsError::StringError(format!("{}", e)))),
+            Err(e) => Box::new(stream::once(Err(e))),
+        }
+    }
+}
@@ -3,30 +3,46 @@ use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
 
 use crossbeam::queue::ArrayQueue;
 use crossbeam_skiplist::SkipMap;
+use memmap::Mmap;
 use serde::{Deserialize, Serialize};
 use serde_json::Deserializer;
 use tokio::prelude::*;
+use tokio::sync::mpsc::{self, Sender};
 use tokio::sync::oneshot;
 
+use super::cache::{CacheStats, ValueCache};
+use super::feed::{Change, ChangeFeed, Event};
 use super::KvsEngine;
 use crate::thread_pool::ThreadPool;
-use crate::{KvsError, Result};
+use crate::{KvsError, LogEntry, LogPos, Result};
 
 const COMPACTION_THRESHOLD: u64 = 1024 * 1024;
 
+/// How many log entries read from files are buffered for a replica.
+const REPLICATION_BUFFER: usize = 1024;
+
+/// How many newly written log entries can wait for a replica. A replica falling
+/// further behind is resynchronized from the whole log.
+const LIVE_REPLICATION_BUFFER: usize = 4096;
+
 /// The `KvStore` stores string key/value pairs.
 ///
 /// Key/value pairs are persisted to disk in log files. Log files are named after
 /// monotonically increasing generation numbers with a `log` extension name.
 /// A skip list in memory stores the keys and the value locations for fast query.
 ///
+/// Only the log file of the current generation is written. The other log files are
+/// immutable and are read through memory maps shared by all readers.
+///
 /// ```rust
 /// # use kvs::{KvStore, Result};
 /// # use kvs::thread_pool::{ThreadPool, RayonThreadPool};
@@ -50,6 +66,31 @@ pub struct KvStore<P: ThreadPool> {
     writer: Arc<Mutex<KvStoreWriter>>,
     thread_pool: P,
     reader_pool: Arc<ArrayQueue<KvStoreReader>>,
+    // read-only memory maps of the immutable log files
+    mmaps: Arc<SkipMap<u64, Mmap>>,
+    cache: Option<Arc<ValueCache>>,
+    feed: ChangeFeed,
+}
+
+/// Options for opening a `KvStore`.
+#[derive(Debug, Clone, Default)]
+pub struct KvStoreOptions {
+    cache_size: u64,
+}
+
+impl KvStoreOptions {
+    /// Creates the default options. The value cache is disabled.
+    pub fn new() -> Self {
+        KvStoreOptions::default()
+    }
+
+    /// Sets the byte budget of the cache of recently read values.
+    ///
+    /// The cache is disabled if it is 0.
+    pub fn cache_size(mut self, bytes: u64) -> Self {
+        self.cache_size = bytes;
+        self
+    }
 }
 
 impl<P: ThreadPool> KvStore<P> {
@@ -63,11 +104,23 @@ impl<P: ThreadPool> KvStore<P> {
     ///
     /// It propagates I/O or deserialization errors during the log replay.
     pub fn open(path: impl Into<PathBuf>, concurrency: u32) -> Result<Self> {
+        KvStore::open_with(path, concurrency, KvStoreOptions::new())
+    }
+
+    /// Opens a `KvStore` with the given path and options.
+    ///
+    /// See `KvStore::open`.
+    pub fn open_with(
+        path: impl Into<PathBuf>,
+        concurrency: u32,
+        options: KvStoreOptions,
+    ) -> Result<Self> {
         let path = Arc::new(path.into());
         fs::create_dir_all(&*path)?;
 
         let mut readers = BTreeMap::new();
         let index = Arc::new(SkipMap::new());
+        let mmaps = Arc::new(SkipMap::new());
 
         let gen_list = sorted_gen_list(&path)?;
         let mut uncompacted = 0;
@@ -76,11 +129,22 @@ impl<P: ThreadPool> KvStore<P> {
             let mut reader = BufReaderWithPos::new(File::open(log_path(&path, gen))?)?;
             uncompacted += load(gen, &mut reader, &*index)?;
             readers.insert(gen, reader);
+            // log files of previous runs are never written again
+            if let Some(mmap) = map_log_file(&path, gen)? {
+                mmaps.insert(gen, mmap);
+            }
         }
 
         let current_gen = gen_list.last().unwrap_or(&0) + 1;
         let writer = new_log_file(&path, current_gen)?;
         let safe_point = Arc::new(AtomicU64::new(0));
+        // every open writes a new generation, greater than all the ones before
+        let feed = ChangeFeed::new(current_gen);
+        let cache = if options.cache_size > 0 {
+            Some(Arc::new(ValueCache::new(options.cache_size)))
+        } else {
+            None
+        };
 
         let reader = KvStoreReader {
             path: Arc::clone(&path),
@@ -95,6 +159,11 @@ impl<P: ThreadPool> KvStore<P> {
             uncompacted,
             path: Arc::clone(&path),
             index: Arc::clone(&index),
+            mmaps: Arc::clone(&mmaps),
+            cache: cache.clone(),
+            feed: feed.clone(),
+            written: Arc::new(AtomicU64::new(0)),
+            replicas: Vec::new(),
         };
 
         let thread_pool = P::new(concurrency)?;
@@ -110,8 +179,108 @@ impl<P: ThreadPool> KvStore<P> {
             writer: Arc::new(Mutex::new(writer)),
             thread_pool,
             reader_pool,
+            mmaps,
+            cache,
+            feed,
         })
     }
+
+    /// Returns the statistics of the value cache, or `None` if it is disabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
+    /// Opens the log files to stream from `from` and registers the replica for the
+    /// entries written afterwards.
+    ///
+    /// The writer is locked meanwhile, so no entry is missed or sent twice and no
+    /// log file is removed by a compaction before it is opened.
+    fn start_replication(
+        &self,
+        from: LogPos,
+    ) -> Result<Box<Stream<Item = LogEntry, Error = KvsError> + Send>> {
+        let mut writer = self.writer.lock().unwrap();
+        let head = LogPos {
+            gen: writer.current_gen,
+            offset: writer.writer.pos,
+        };
+        let gen_list = sorted_gen_list(&self.path)?;
+        // The files before the latest compaction are removed, so a replica that has
+        // not read them must start over from the compaction file.
+        let reset = !gen_list.contains(&from.gen);
+        let start = if reset {
+            LogPos {
+                gen: gen_list[0],
+                offset: 0,
+            }
+        } else {
+            from
+        };
+
+        let mut files = Vec::new();
+        for &gen in gen_list
+            .iter()
+            .filter(|&&gen| gen >= start.gen && gen <= head.gen)
+        {
+            let file = File::open(log_path(&self.path, gen))?;
+            let end = if gen == head.gen {
+                head.offset
+            } else {
+                file.metadata()?.len()
+            };
+            files.push((gen, file, end));
+        }
+        let (live_tx, live_rx) = mpsc::channel(LIVE_REPLICATION_BUFFER);
+        let overflowed = Arc::new(AtomicBool::new(false));
+        writer.replicas.push(LiveReplica {
+            sender: live_tx,
+            overflowed: Arc::clone(&overflowed),
+        });
+        let written = Arc::clone(&writer.written);
+        drop(writer);
+
+        let (tx, rx) = mpsc::channel(REPLICATION_BUFFER);
+        thread::Builder::new()
+            .name("kvs-replication".to_owned())
+            .spawn(move || {
+                let res = read_log(files, start.offset, |entry| {
+                    tx.clone().send(Ok(entry)).wait().is_ok()
+                });
+                if let Err(e) = res {
+                    let _ = tx.send(Err(e)).wait();
+                }
+            })?;
+
+        let reset = stream::iter_ok(if reset { Some(LogEntry::Reset) } else { None });
+        let catch_up = rx
+            .map_err(|e| KvsError::StringError(format!("{}", e)))
+            .and_then(|entry| entry);
+        // the replica is behind by the bytes written after the entry it takes
+        let live = live_rx
+            .map_err(|e| KvsError::StringError(format!("{}", e)))
+            .map(move |(entry, written_after)| {
+                with_behind(entry, written.load(Ordering::SeqCst) - written_after)
+            });
+        let store = self.clone();
+        let resync = future::lazy(
+            move || -> Result<Box<Stream<Item = LogEntry, Error = KvsError> + Send>> {
+                if overflowed.load(Ordering::SeqCst) {
+                    // no log file has generation 0, so the replica is reset
+                    store.start_replication(LogPos::default())
+                } else {
+                    Ok(Box::new(stream::empty()))
+                }
+            },
+        )
+        .flatten_stream();
+        Ok(Box::new(
+            reset
+                .chain(catch_up)
+                .chain(stream::once(Ok(LogEntry::Synced)))
+                .chain(live)
+                .chain(resync),
+        ))
+    }
 }
 
 impl<P: ThreadPool> KvsEngine for KvStore<P> {
@@ -140,23 +309,40 @@ impl<P: ThreadPool> KvsEngine for KvStore<P> {
     /// Gets the string value of a given string key.
     ///
     /// Returns `None` if the given key does not exist.
+    ///
+    /// Values in immutable log files are copied from the shared memory maps without
+    /// taking a reader from the reader pool.
     fn get(&self, key: String) -> Box<Future<Item = Option<String>, Error = KvsError> + Send> {
         let reader_pool = self.reader_pool.clone();
         let index = self.index.clone();
+        let mmaps = self.mmaps.clone();
+        let cache = self.cache.clone();
         let (tx, rx) = oneshot::channel();
         self.thread_pool.spawn(move || {
             let res = (|| {
                 if let Some(cmd_pos) = index.get(&key) {
-                    let reader = reader_pool.pop().unwrap();
-                    let res = if let Command::Set { value, .. } =
-                        reader.read_command(*cmd_pos.value())?
-                    {
+                    let cmd_pos = *cmd_pos.value();
+                    if let Some(value) = cache.as_ref().and_then(|c| c.get(&key, cmd_pos)) {
+                        return Ok(Some(value));
+                    }
+                    let cmd = if let Some(mmap) = mmaps.get(&cmd_pos.gen) {
+                        let start = cmd_pos.pos as usize;
+                        let end = start + cmd_pos.len as usize;
+                        serde_json::from_slice(&mmap.value()[start..end])?
+                    } else {
+                        let reader = reader_pool.pop().unwrap();
+                        let res = reader.read_command(cmd_pos);
+                        reader_pool.push(reader).unwrap();
+                        res?
+                    };
+                    if let Command::Set { value, .. } = cmd {
+                        if let Some(cache) = &cache {
+                            cache.insert(key, cmd_pos, value.clone());
+                        }
                         Ok(Some(value))
                     } else {
                         Err(KvsError::UnexpectedCommandType)
-                    };
-                    reader_pool.push(reader).unwrap();
-                    res
+                    }
                 } else {
                     Ok(None)
                 }
@@ -192,6 +378,33 @@ impl<P: ThreadPool> KvsEngine for KvStore<P> {
                 .flatten(),
         )
     }
+
+    /// Subscribes to changes of keys starting with `prefix`.
+    ///
+    /// The sequence numbers of each time the store is opened start from its first
+    /// log generation, so they are greater than those of the times before.
+    fn watch(
+        &self,
+        prefix: String,
+        from: Option<u64>,
+    ) -> Box<Stream<Item = Event, Error = KvsError> + Send> {
+        self.feed.watch(prefix, from)
+    }
+
+    fn keys(&self) -> Box<Future<Item = Vec<String>, Error = KvsError> + Send> {
+        let keys = self.index.iter().map(|entry| entry.key().clone()).collect();
+        Box::new(future::ok(keys))
+    }
+
+    /// Streams the log from the given position to a replica.
+    ///
+    /// The replica is reset if the log before the latest compaction is requested.
+    fn replicate(&self, from: LogPos) -> Box<Stream<Item = LogEntry, Error = KvsError> + Send> {
+        match self.start_replication(from) {
+            Ok(stream) => stream,
+            Err(e) => Box::new(stream::once(Err(e))),
+        }
+    }
 }
 
 /// A single thread reader.
@@ -273,6 +486,23 @@ struct KvStoreWriter {
     uncompacted: u64,
     path: Arc<PathBuf>,
     index: Arc<SkipMap<String, CommandPos>>,
+    mmaps: Arc<SkipMap<u64, Mmap>>,
+    cache: Option<Arc<ValueCache>>,
+    feed: ChangeFeed,
+    // bytes written to the log since the store was opened
+    written: Arc<AtomicU64>,
+    // live log streams of connected replicas
+    replicas: Vec<LiveReplica>,
+}
+
+/// The stream of newly written log entries to a replica.
+///
+/// Each entry is sent with the number of bytes written to the log up to its end,
+/// so `behind` can be worked out when the replica takes it.
+struct LiveReplica {
+    sender: Sender<(LogEntry, u64)>,
+    // set when the replica fell too far behind and its stream was dropped
+    overflowed: Arc<AtomicBool>,
 }
 
 impl KvStoreWriter {
@@ -281,12 +511,15 @@ impl KvStoreWriter {
         let pos = self.writer.pos;
         serde_json::to_writer(&mut self.writer, &cmd)?;
         self.writer.flush()?;
-        if let Command::Set { key, .. } = cmd {
+        if let Command::Set { key, value } = cmd {
             if let Some(old_cmd) = self.index.get(&key) {
                 self.uncompacted += old_cmd.value().len;
             }
             self.index
-                .insert(key, (self.current_gen, pos..self.writer.pos).into());
+                .insert(key.clone(), (self.current_gen, pos..self.writer.pos).into());
+            self.written
+                .fetch_add(self.writer.pos - pos, Ordering::SeqCst);
+            self.publish(key, Some(value));
         }
 
         if self.uncompacted > COMPACTION_THRESHOLD {
@@ -307,6 +540,9 @@ impl KvStoreWriter {
                 // the "remove" command itself can be deleted in the next compaction
                 // so we add its length to `uncompacted`
                 self.uncompacted += self.writer.pos - pos;
+                self.written
+                    .fetch_add(self.writer.pos - pos, Ordering::SeqCst);
+                self.publish(key, None);
             }
 
             if self.uncompacted > COMPACTION_THRESHOLD {
@@ -318,6 +554,59 @@ impl KvStoreWriter {
         }
     }
 
+    /// Publishes the command just written to the log to watchers and replicas.
+    ///
+    /// `value` is `None` for a remove command.
+    fn publish(&mut self, key: String, value: Option<String>) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&key);
+        }
+        if !self.replicas.is_empty() {
+            let pos = LogPos {
+                gen: self.current_gen,
+                offset: self.writer.pos,
+            };
+            // `behind` is set when a replica takes the entry
+            let entry = match &value {
+                Some(value) => LogEntry::Set {
+                    pos,
+                    behind: 0,
+                    key: key.clone(),
+                    value: value.clone(),
+                },
+                None => LogEntry::Remove {
+                    pos,
+                    behind: 0,
+                    key: key.clone(),
+                },
+            };
+            let written = self.written.load(Ordering::SeqCst);
+            // drop the streams of disconnected replicas and of replicas too far
+            // behind, which are resynchronized
+            let replicas = mem::replace(&mut self.replicas, Vec::new());
+            self.replicas = replicas
+                .into_iter()
+                .filter_map(
+                    |mut replica| match replica.sender.try_send((entry.clone(), written)) {
+                        Ok(()) => Some(replica),
+                        Err(e) => {
+                            if e.is_full() {
+                                warn!("A replica is too far behind, resynchronizing it");
+                                replica.overflowed.store(true, Ordering::SeqCst);
+                            }
+                            None
+                        }
+                    },
+                )
+                .collect();
+        }
+
+        self.feed.publish(match value {
+            Some(value) => Change::Set { key, value },
+            None => Change::Remove { key },
+        });
+    }
+
     /// Clears stale entries in the log.
     fn compact(&mut self) -> Result<()> {
         // increase current gen by 2. current_gen + 1 is for the compaction file
@@ -332,18 +621,29 @@ impl KvStoreWriter {
             let len = self.reader.read_and(*entry.value(), |mut entry_reader| {
                 Ok(io::copy(&mut entry_reader, &mut compaction_writer)?)
             })?;
-            self.index.insert(
-                entry.key().clone(),
-                (compaction_gen, new_pos..new_pos + len).into(),
-            );
+            let cmd_pos = (compaction_gen, new_pos..new_pos + len).into();
+            if let Some(cache) = &self.cache {
+                cache.relocate(entry.key(), *entry.value(), cmd_pos);
+            }
+            self.index.insert(entry.key().clone(), cmd_pos);
             new_pos += len;
         }
         compaction_writer.flush()?;
+        if let Some(mmap) = map_log_file(&self.path, compaction_gen)? {
+            self.mmaps.insert(compaction_gen, mmap);
+        }
 
         self.reader
             .safe_point
             .store(compaction_gen, Ordering::SeqCst);
         self.reader.close_stale_handles();
+        // Readers holding an entry keep its memory map alive until they finish.
+        while let Some(entry) = self.mmaps.front() {
+            if *entry.key() >= compaction_gen {
+                break;
+            }
+            entry.remove();
+        }
 
         // remove stale log files
         // Note that actually these files are not deleted immediately because `KvStoreReader`s
@@ -382,6 +682,20 @@ fn new_log_file(path: &Path, gen: u64) -> Result<BufWriterWithPos<File>> {
     Ok(writer)
 }
 
+/// Map the log file with the given generation number into memory.
+///
+/// Returns `None` for an empty file, which cannot be mapped on every platform.
+fn map_log_file(path: &Path, gen: u64) -> Result<Option<Mmap>> {
+    let file = File::open(log_path(path, gen))?;
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // It is only called for log files that are no longer written, and log files are
+    // never truncated, so the mapped memory does not change.
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(Some(mmap))
+}
+
 /// Returns sorted generation numbers in the given directory
 fn sorted_gen_list(path: &Path) -> Result<Vec<u64>> {
     let mut gen_list: Vec<u64> = fs::read_dir(&path)?
@@ -434,6 +748,61 @@ fn load(
     Ok(uncompacted)
 }
 
+/// Read the commands in the log files for a replica.
+///
+/// `files` holds the generation number, the opened file and the length to read of
+/// each log file. Reading starts from `offset` of the first file and stops when `f`
+/// returns `false`.
+fn read_log<F>(files: Vec<(u64, File, u64)>, mut offset: u64, mut f: F) -> Result<()>
+where
+    F: FnMut(LogEntry) -> bool,
+{
+    // number of bytes after the command read last
+    let mut behind = files.iter().map(|&(_, _, end)| end).sum::<u64>() - offset;
+    for (gen, file, end) in files {
+        let mut reader = BufReaderWithPos::new(file)?;
+        let mut pos = reader.seek(SeekFrom::Start(offset))?;
+        let mut stream =
+            Deserializer::from_reader(reader.take(end - offset)).into_iter::<Command>();
+        while let Some(cmd) = stream.next() {
+            let new_pos = offset + stream.byte_offset() as u64;
+            behind -= new_pos - pos;
+            let log_pos = LogPos {
+                gen,
+                offset: new_pos,
+            };
+            let entry = match cmd? {
+                Command::Set { key, value } => LogEntry::Set {
+                    pos: log_pos,
+                    behind,
+                    key,
+                    value,
+                },
+                Command::Remove { key } => LogEntry::Remove {
+                    pos: log_pos,
+                    behind,
+                    key,
+                },
+            };
+            if !f(entry) {
+                return Ok(());
+            }
+            pos = new_pos;
+        }
+        offset = 0;
+    }
+    Ok(())
+}
+
+/// Sets the number of bytes of the log after a `Set` or `Remove` entry.
+fn with_behind(mut entry: LogEntry, bytes: u64) -> LogEntry {
+    match &mut entry {
+        LogEntry::Set { behind, .. } | LogEntry::Remove { behind, .. } => *behind = bytes,
+        LogEntry::Reset | LogEntry::Synced => {}
+    }
+    entry
+}
+
 fn log_path(dir: &Path, gen: u64) -> PathBuf {
     dir.join(format!("{}.log", gen))
 }
@@ -456,8 +825,8 @@ impl Command {
 }
 
 /// Represents the position and length of a json-serialized command in the log
-#[derive(Debug, Clone, Copy)]
-struct CommandPos {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CommandPos {
     gen: u64,
     pos: u64,
     len: u64,
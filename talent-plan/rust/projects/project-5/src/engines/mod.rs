@@ -1,9 +1,13 @@
-pub use self::kvs::KvStore;
+pub use self::cache::CacheStats;
+pub use self::feed::Event;
+pub use self::kvs::{KvStore, KvStoreOptions};
 pub use self::sled::SledKvsEngine;
-use crate::KvsError;
+use crate::{KvsError, LogEntry, LogPos};
 
-use tokio::prelude::Future;
+use tokio::prelude::{Future, Stream};
 
+mod cache;
+mod feed;
 mod kvs;
 mod sled;
 
@@ -25,4 +29,36 @@ pub trait KvsEngine: Clone + Send + 'static {
     ///
     /// It returns `KvsError::KeyNotFound` if the given key is not found.
     fn remove(&self, key: String) -> Box<Future<Item = (), Error = KvsError> + Send>;
+
+    /// Subscribes to changes of keys starting with `prefix`.
+    ///
+    /// The returned stream yields an `Event` for every following `set` or `remove`
+    /// of a matching key. If `from` is given, the retained events with a sequence
+    /// number not less than `from` are replayed first.
+    ///
+    /// # Errors
+    ///
+    /// The stream yields `KvsError::SeqNotRetained` if `from` is older than the
+    /// retained log.
+    fn watch(
+        &self,
+        prefix: String,
+        from: Option<u64>,
+    ) -> Box<Stream<Item = Event, Error = KvsError> + Send>;
+
+    /// Gets all the keys in the storage.
+    fn keys(&self) -> Box<Future<Item = Vec<String>, Error = KvsError> + Send>;
+
+    /// Streams the log from the given position to a replica.
+    ///
+    /// If the position is no longer in the log, the stream starts with
+    /// `LogEntry::Reset` and replays the whole log. `LogEntry::Synced` follows the
+    /// entries existing in the log at the time of the call; the entries after it are
+    /// sent as they are written. A replica falling too far behind them gets
+    /// `LogEntry::Reset` and the whole log again.
+    ///
+    /// # Errors
+    ///
+    /// The stream yields an error if the engine cannot be a replication primary.
+    fn replicate(&self, from: LogPos) -> Box<Stream<Item = LogEntry, Error = KvsError> + Send>;
 }
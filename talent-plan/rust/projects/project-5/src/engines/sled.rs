@@ -1,6 +1,8 @@
+use super::feed::{Change, ChangeFeed, Event};
 use crate::thread_pool::ThreadPool;
-use crate::{KvsEngine, KvsError, Result};
+use crate::{KvsEngine, KvsError, LogEntry, LogPos, Result};
 use sled::Db;
+use std::thread;
 use tokio::prelude::*;
 use tokio::sync::oneshot;
 
@@ -9,6 +11,7 @@ use tokio::sync::oneshot;
 pub struct SledKvsEngine<P: ThreadPool> {
     pool: P,
     db: Db,
+    feed: ChangeFeed,
 }
 
 impl<P: ThreadPool> SledKvsEngine<P> {
@@ -16,9 +19,34 @@ impl<P: ThreadPool> SledKvsEngine<P> {
     ///
     /// Operations are run in the given thread pool. `concurrency` specifies the number of
     /// threads in the thread pool.
+    ///
+    /// A background thread follows the sled subscriber of the whole keyspace to
+    /// feed `watch` subscribers. Their sequence numbers start from an ID generated
+    /// by sled, which is greater than the IDs generated before a restart.
     pub fn new(db: Db, concurrency: u32) -> Result<Self> {
         let pool = P::new(concurrency)?;
-        Ok(SledKvsEngine { pool, db })
+        let feed = ChangeFeed::new(db.generate_id()?);
+        let subscriber = db.watch_prefix(vec![]);
+        let publisher = feed.clone();
+        thread::Builder::new()
+            .name("sled-watcher".to_owned())
+            .spawn(move || {
+                for event in subscriber {
+                    let change = match event {
+                        sled::Event::Set(key, value) => Change::Set {
+                            key: String::from_utf8_lossy(&key).into_owned(),
+                            value: String::from_utf8_lossy(&value).into_owned(),
+                        },
+                        sled::Event::Del(key) => Change::Remove {
+                            key: String::from_utf8_lossy(&key).into_owned(),
+                        },
+                        // `SledKvsEngine` never merges
+                        sled::Event::Merge(..) => continue,
+                    };
+                    publisher.publish(change);
+                }
+            })?;
+        Ok(SledKvsEngine { pool, db, feed })
     }
 }
 
@@ -81,4 +109,38 @@ impl<P: ThreadPool> KvsEngine for SledKvsEngine<P> {
                 .flatten(),
         )
     }
+
+    fn watch(
+        &self,
+        prefix: String,
+        from: Option<u64>,
+    ) -> Box<Stream<Item = Event, Error = KvsError> + Send> {
+        self.feed.watch(prefix, from)
+    }
+
+    fn keys(&self) -> Box<Future<Item = Vec<String>, Error = KvsError> + Send> {
+        let db = self.db.clone();
+        let (tx, rx) = oneshot::channel();
+        self.pool.spawn(move || {
+            let res = db
+                .iter()
+                .keys()
+                .map(|key| -> Result<String> { Ok(String::from_utf8(key?)?) })
+                .collect::<Result<Vec<_>>>();
+            if tx.send(res).is_err() {
+                error!("Receiving end is dropped");
+            }
+        });
+        Box::new(
+            rx.map_err(|e| KvsError::StringError(format!("{}", e)))
+                .flatten(),
+        )
+    }
+
+    /// Sled does not expose its log, so `SledKvsEngine` cannot be a replication primary.
+    fn replicate(&self, _from: LogPos) -> Box<Stream<Item = LogEntry, Error = KvsError> + Send> {
+        Box::new(stream::once(Err(KvsError::StringError(
+            "The sled engine cannot be a replication primary".to_owned(),
+        ))))
+    }
 }
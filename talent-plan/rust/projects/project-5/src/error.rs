@@ -24,6 +24,12 @@ pub enum KvsError {
     /// Sled error
     #[fail(display = "sled error: {}", _0)]
     Sled(#[cause] sled::Error),
+    /// The requested sequence number is older than the retained change log
+    #[fail(display = "Sequence number {} is no longer retained", _0)]
+    SeqNotRetained(u64),
+    /// Writing to a replica that has not been promoted
+    #[fail(display = "Read-only replica")]
+    ReadOnly,
     /// Error with a string message
     #[fail(display = "{}", _0)]
     StringError(String),
@@ -5,13 +5,17 @@
 extern crate log;
 
 pub use client::KvsClient;
-pub use engines::{KvStore, KvsEngine, SledKvsEngine};
+pub use engines::{CacheStats, Event, KvStore, KvStoreOptions, KvsEngine, SledKvsEngine};
 pub use error::{KvsError, Result};
+pub use replication::{LogEntry, LogPos, ReplicationStats, Stats};
 pub use server::KvsServer;
+pub use sharded::ShardedKvsClient;
 
 mod client;
 mod common;
 mod engines;
 mod error;
+mod replication;
 mod server;
+mod sharded;
 pub mod thread_pool;
@@ -0,0 +1,243 @@
+use crate::{KvsClient, KvsEngine, KvsError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::prelude::future::{Either, Loop};
+use tokio::prelude::*;
+use tokio::timer::Delay;
+
+/// How long a replica waits before reconnecting to its primary.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A position in the log of a `KvStore`: right after `offset` bytes of the log
+/// file with generation number `gen`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogPos {
+    /// Generation number of the log file
+    pub gen: u64,
+    /// Byte offset in the log file
+    pub offset: u64,
+}
+
+/// An entry of the log streamed from a primary to a replica.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogEntry {
+    /// The requested position is no longer in the log and the whole log follows.
+    ///
+    /// The replica must drop the keys not set again before `Synced`.
+    Reset,
+    /// The key is set to the value.
+    Set {
+        /// Position right after the command
+        pos: LogPos,
+        /// Bytes of the log after the command when it was read
+        behind: u64,
+        /// The key
+        key: String,
+        /// The value
+        value: String,
+    },
+    /// The key is removed.
+    Remove {
+        /// Position right after the command
+        pos: LogPos,
+        /// Bytes of the log after the command when it was read
+        behind: u64,
+        /// The key
+        key: String,
+    },
+    /// The existing log has been sent and following entries are sent as they
+    /// are written.
+    Synced,
+}
+
+/// Statistics of a `KvsServer`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stats {
+    /// Whether the server rejects writes
+    pub read_only: bool,
+    /// Replication status if the server was started as a replica
+    pub replication: Option<ReplicationStats>,
+}
+
+/// Replication status of a replica.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationStats {
+    /// Address of the primary
+    pub primary: SocketAddr,
+    /// Whether the replica is connected to the primary
+    pub connected: bool,
+    /// Whether the replica has applied the whole log existing when it connected
+    pub synced: bool,
+    /// Whether the replica has been promoted and stopped replicating
+    pub promoted: bool,
+    /// Position in the primary's log applied by the replica
+    pub applied: LogPos,
+    /// Bytes of the primary's log not applied yet
+    pub lag_bytes: u64,
+}
+
+/// State of a replica shared by the replication task and the connections.
+pub(crate) struct Replica {
+    promoted: AtomicBool,
+    stats: Mutex<ReplicationStats>,
+}
+
+impl Replica {
+    pub(crate) fn new(primary: SocketAddr) -> Replica {
+        Replica {
+            promoted: AtomicBool::new(false),
+            stats: Mutex::new(ReplicationStats {
+                primary,
+                connected: false,
+                synced: false,
+                promoted: false,
+                applied: LogPos::default(),
+                lag_bytes: 0,
+            }),
+        }
+    }
+
+    /// Returns whether the replica still follows its primary and rejects writes.
+    pub(crate) fn is_read_only(&self) -> bool {
+        !self.promoted.load(Ordering::SeqCst)
+    }
+
+    /// Stops replication. The replica accepts writes afterwards.
+    pub(crate) fn promote(&self) {
+        self.promoted.store(true, Ordering::SeqCst);
+        let mut stats = self.stats.lock().unwrap();
+        stats.promoted = true;
+        stats.connected = false;
+    }
+
+    pub(crate) fn stats(&self) -> ReplicationStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    fn update<F: FnOnce(&mut ReplicationStats)>(&self, f: F) {
+        f(&mut self.stats.lock().unwrap())
+    }
+}
+
+/// Follows the primary of `replica` until it is promoted, applying its log to `engine`.
+///
+/// The replica reconnects after `RETRY_INTERVAL` if the connection fails.
+pub(crate) fn follow<E: KvsEngine>(
+    engine: E,
+    replica: Arc<Replica>,
+) -> impl Future<Item = (), Error = ()> {
+    future::loop_fn((), move |()| {
+        let replica = Arc::clone(&replica);
+        replicate_once(engine.clone(), Arc::clone(&replica)).then(move |res| {
+            replica.update(|stats| stats.connected = false);
+            if let Err(e) = res {
+                error!("Replication from {} failed: {}", replica.stats().primary, e);
+            }
+            if replica.is_read_only() {
+                Either::A(
+                    Delay::new(Instant::now() + RETRY_INTERVAL)
+                        .map(|_| Loop::Continue(()))
+                        .map_err(|e| error!("Timer error: {}", e)),
+                )
+            } else {
+                info!("Promoted, replication stopped");
+                Either::B(future::ok(Loop::Break(())))
+            }
+        })
+    })
+}
+
+/// Connects to the primary and applies its log until the connection ends or the
+/// replica is promoted.
+fn replicate_once<E: KvsEngine>(
+    engine: E,
+    replica: Arc<Replica>,
+) -> impl Future<Item = (), Error = KvsError> {
+    let stats = replica.stats();
+    let from = stats.applied;
+    KvsClient::connect(stats.primary)
+        .map(move |client| client.replicate(from))
+        .flatten_stream()
+        .take_while({
+            let replica = Arc::clone(&replica);
+            move |_| Ok(replica.is_read_only())
+        })
+        .fold(HashSet::new(), move |stale, entry| {
+            replica.update(|stats| stats.connected = true);
+            apply(engine.clone(), Arc::clone(&replica), stale, entry)
+        })
+        .map(|_| ())
+}
+
+/// Applies a log entry to `engine`.
+///
+/// `stale` holds the keys to drop at the next `Synced` after a `Reset`.
+fn apply<E: KvsEngine>(
+    engine: E,
+    replica: Arc<Replica>,
+    mut stale: HashSet<String>,
+    entry: LogEntry,
+) -> Box<dyn Future<Item = HashSet<String>, Error = KvsError> + Send> {
+    match entry {
+        LogEntry::Reset => {
+            warn!("Replica is too far behind, resynchronizing");
+            replica.update(|stats| stats.synced = false);
+            Box::new(engine.keys().map(|keys| keys.into_iter().collect()))
+        }
+        LogEntry::Set {
+            pos,
+            behind,
+            key,
+            value,
+        } => {
+            stale.remove(&key);
+            Box::new(engine.set(key, value).map(move |_| {
+                replica.update(|stats| {
+                    stats.applied = pos;
+                    stats.lag_bytes = behind;
+                });
+                stale
+            }))
+        }
+        LogEntry::Remove { pos, behind, key } => {
+            stale.remove(&key);
+            Box::new(remove_if_exists(&engine, key).map(move |_| {
+                replica.update(|stats| {
+                    stats.applied = pos;
+                    stats.lag_bytes = behind;
+                });
+                stale
+            }))
+        }
+        LogEntry::Synced => {
+            let removals: Vec<_> = stale
+                .into_iter()
+                .map(|key| remove_if_exists(&engine, key))
+                .collect();
+            Box::new(future::join_all(removals).map(move |_| {
+                replica.update(|stats| {
+                    stats.synced = true;
+                    stats.lag_bytes = 0;
+                });
+                HashSet::new()
+            }))
+        }
+    }
+}
+
+/// Removes the key, ignoring `KvsError::KeyNotFound`.
+///
+/// The replica may apply a part of the log twice after reconnecting.
+fn remove_if_exists<E: KvsEngine>(
+    engine: &E,
+    key: String,
+) -> impl Future<Item = (), Error = KvsError> {
+    engine.remove(key).then(|res| match res {
+        Ok(()) | Err(KvsError::KeyNotFound) => Ok(()),
+        Err(e) => Err(e),
+    })
+}
@@ -1,6 +1,8 @@
 use crate::common::{Request, Response};
-use crate::{KvsEngine, KvsError, Result};
+use crate::replication::{self, Replica};
+use crate::{KvsEngine, KvsError, Result, Stats};
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::prelude::*;
@@ -9,47 +11,99 @@ use tokio_serde_json::{ReadJson, WriteJson};
 /// The server of a key value store.
 pub struct KvsServer<E: KvsEngine> {
     engine: E,
+    replica: Option<Arc<Replica>>,
 }
 
 impl<E: KvsEngine> KvsServer<E> {
     /// Create a `KvsServer` with a given storage engine.
     pub fn new(engine: E) -> Self {
-        KvsServer { engine }
+        KvsServer {
+            engine,
+            replica: None,
+        }
+    }
+
+    /// Make the server a read-only replica of the server at `primary`.
+    ///
+    /// The replica applies the log of the primary to its engine and rejects writes
+    /// until it is promoted.
+    pub fn replica_of(mut self, primary: SocketAddr) -> Self {
+        self.replica = Some(Arc::new(Replica::new(primary)));
+        self
     }
 
     /// Run the server listening on the given address
     pub fn run(self, addr: SocketAddr) -> Result<()> {
         let listener = TcpListener::bind(&addr)?;
-        let server = listener
-            .incoming()
-            .map_err(|e| error!("IO error: {}", e))
-            .for_each(move |tcp| {
-                let engine = self.engine.clone();
-                serve(engine, tcp).map_err(|e| error!("Error on serving client: {}", e))
-            });
+        let engine = self.engine;
+        let replica = self.replica;
+        let server = future::lazy(move || {
+            if let Some(replica) = &replica {
+                tokio::spawn(replication::follow(engine.clone(), Arc::clone(replica)));
+            }
+            listener
+                .incoming()
+                .map_err(|e| error!("IO error: {}", e))
+                .for_each(move |tcp| {
+                    serve(engine.clone(), replica.clone(), tcp)
+                        .map_err(|e| error!("Error on serving client: {}", e))
+                })
+        });
         tokio::run(server);
         Ok(())
     }
 }
 
-fn serve<E: KvsEngine>(engine: E, tcp: TcpStream) -> impl Future<Item = (), Error = KvsError> {
+fn serve<E: KvsEngine>(
+    engine: E,
+    replica: Option<Arc<Replica>>,
+    tcp: TcpStream,
+) -> impl Future<Item = (), Error = KvsError> {
     let (read_half, write_half) = tcp.split();
     let read_json = ReadJson::new(FramedRead::new(read_half, LengthDelimitedCodec::new()));
+    // Every request is answered by a stream of responses. A `Watch` request never
+    // finishes, so it turns the rest of the connection into a stream of events.
     let resp_stream = read_json
         .map_err(KvsError::from)
-        .and_then(
-            move |req| -> Box<dyn Future<Item = Response, Error = KvsError> + Send> {
+        .map(
+            move |req| -> Box<dyn Stream<Item = Response, Error = KvsError> + Send> {
+                let read_only = replica.as_ref().map_or(false, |r| r.is_read_only());
                 match req {
-                    Request::Get { key } => Box::new(engine.get(key).map(Response::Get)),
+                    Request::Set { .. } | Request::Remove { .. } if read_only => {
+                        Box::new(stream::once(Err(KvsError::ReadOnly)))
+                    }
+                    Request::Get { key } => {
+                        Box::new(engine.get(key).map(Response::Get).into_stream())
+                    }
                     Request::Set { key, value } => {
-                        Box::new(engine.set(key, value).map(|_| Response::Set))
+                        Box::new(engine.set(key, value).map(|_| Response::Set).into_stream())
                     }
                     Request::Remove { key } => {
-                        Box::new(engine.remove(key).map(|_| Response::Remove))
+                        Box::new(engine.remove(key).map(|_| Response::Remove).into_stream())
+                    }
+                    Request::Watch { prefix, from } => {
+                        Box::new(engine.watch(prefix, from).map(Response::Event))
+                    }
+                    Request::Keys => Box::new(engine.keys().map(Response::Keys).into_stream()),
+                    Request::Replicate { from } => {
+                        Box::new(engine.replicate(from).map(Response::Replicate))
                     }
+                    Request::Stats => Box::new(stream::once(Ok(Response::Stats(Stats {
+                        read_only,
+                        replication: replica.as_ref().map(|r| r.stats()),
+                    })))),
+                    Request::Promote => Box::new(stream::once(match &replica {
+                        Some(replica) => {
+                            replica.promote();
+                            info!("Promoted to primary");
+                            Ok(Response::Promote)
+                        }
+                        None => Err(KvsError::StringError("Not a replica".to_owned())),
+                    })),
                 }
             },
         )
+        .flatten()
         .then(|resp| -> Result<Response> {
             match resp {
                 Ok(resp) => Ok(resp),
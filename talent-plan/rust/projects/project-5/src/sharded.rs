@@ -0,0 +1,251 @@
+use crate::{KvsClient, KvsError, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use tokio::prelude::future::Either;
+use tokio::prelude::*;
+
+/// Number of points each server has on the hash ring.
+const VIRTUAL_NODES: u32 = 160;
+
+/// Key value store client of multiple `KvsServer`s.
+///
+/// Keys are spread over the servers by consistent hashing. Every server owns
+/// `VIRTUAL_NODES` points on a hash ring and a key belongs to the server owning
+/// the first point at or after the hash of the key. Adding a server only moves
+/// the keys between its points and the preceding ones.
+#[derive(Clone)]
+pub struct ShardedKvsClient {
+    ring: HashRing,
+}
+
+impl ShardedKvsClient {
+    /// Create a client routing keys to the given servers.
+    ///
+    /// # Errors
+    ///
+    /// It returns an error if `servers` is empty.
+    pub fn new(servers: &[SocketAddr]) -> Result<Self> {
+        if servers.is_empty() {
+            return Err(KvsError::StringError("No server is given".to_owned()));
+        }
+        let mut ring = HashRing::default();
+        for &addr in servers {
+            ring.add(addr);
+        }
+        Ok(ShardedKvsClient { ring })
+    }
+
+    /// Returns the address of the server owning `key`.
+    pub fn locate(&self, key: &str) -> SocketAddr {
+        self.ring.locate(key)
+    }
+
+    /// Get the value of a given key from its server.
+    pub fn get(&self, key: String) -> impl Future<Item = Option<String>, Error = KvsError> {
+        KvsClient::connect(self.locate(&key))
+            .and_then(move |client| client.get(key))
+            .map(|(value, _)| value)
+    }
+
+    /// Set the value of a string key in its server.
+    pub fn set(&self, key: String, value: String) -> impl Future<Item = (), Error = KvsError> {
+        KvsClient::connect(self.locate(&key))
+            .and_then(move |client| client.set(key, value))
+            .map(|_| ())
+    }
+
+    /// Remove a string key in its server.
+    pub fn remove(&self, key: String) -> impl Future<Item = (), Error = KvsError> {
+        KvsClient::connect(self.locate(&key))
+            .and_then(move |client| client.remove(key))
+            .map(|_| ())
+    }
+
+    /// Get the values of the given keys.
+    ///
+    /// The servers are accessed concurrently with one connection each. The values are
+    /// returned in the order of `keys`.
+    pub fn get_many(
+        &self,
+        keys: Vec<String>,
+    ) -> impl Future<Item = Vec<Option<String>>, Error = KvsError> {
+        let len = keys.len();
+        let shards = self.group(keys.into_iter().enumerate().collect(), |(_, key)| key);
+        let requests = shards.into_iter().map(|(addr, keys)| {
+            KvsClient::connect(addr)
+                .and_then(move |client| {
+                    stream::iter_ok(keys).fold(
+                        (client, Vec::new()),
+                        |(client, mut values), (i, key)| {
+                            client.get(key).map(move |(value, client)| {
+                                values.push((i, value));
+                                (client, values)
+                            })
+                        },
+                    )
+                })
+                .map(|(_, values)| values)
+        });
+        future::join_all(requests).map(move |shards| {
+            let mut values = vec![None; len];
+            for (i, value) in shards.into_iter().flatten() {
+                values[i] = value;
+            }
+            values
+        })
+    }
+
+    /// Set the values of the given keys.
+    ///
+    /// The servers are accessed concurrently with one connection each.
+    pub fn set_many(
+        &self,
+        pairs: Vec<(String, String)>,
+    ) -> impl Future<Item = (), Error = KvsError> {
+        let shards = self.group(pairs, |(key, _)| key);
+        let requests = shards.into_iter().map(|(addr, pairs)| {
+            KvsClient::connect(addr)
+                .and_then(move |client| {
+                    stream::iter_ok(pairs)
+                        .fold(client, |client, (key, value)| client.set(key, value))
+                })
+                .map(|_| ())
+        });
+        future::join_all(requests).map(|_| ())
+    }
+
+    /// Remove the given keys.
+    ///
+    /// The servers are accessed concurrently with one connection each.
+    ///
+    /// # Errors
+    ///
+    /// It returns `KvsError::KeyNotFound` if any key is not found. The other keys
+    /// in the same server after it are not removed.
+    pub fn remove_many(&self, keys: Vec<String>) -> impl Future<Item = (), Error = KvsError> {
+        let shards = self.group(keys, |key| key);
+        let requests = shards.into_iter().map(|(addr, keys)| {
+            KvsClient::connect(addr)
+                .and_then(move |client| {
+                    stream::iter_ok(keys).fold(client, |client, key| client.remove(key))
+                })
+                .map(|_| ())
+        });
+        future::join_all(requests).map(|_| ())
+    }
+
+    /// Add a server to the ring and migrate the keys it now owns from the other
+    /// servers.
+    ///
+    /// Only the keys whose owner changes are moved. Other clients must not write
+    /// to the servers until the returned future completes.
+    pub fn add_shard(self, addr: SocketAddr) -> impl Future<Item = Self, Error = KvsError> {
+        let old_servers = self.ring.servers();
+        let mut ring = self.ring;
+        ring.add(addr);
+        let migrations = old_servers.into_iter().filter(|&from| from != addr).map({
+            let ring = ring.clone();
+            move |from| migrate(ring.clone(), from, addr)
+        });
+        future::join_all(migrations).map(move |_| ShardedKvsClient { ring })
+    }
+
+    /// Group items by the servers owning their keys.
+    fn group<T, F>(&self, items: Vec<T>, key: F) -> HashMap<SocketAddr, Vec<T>>
+    where
+        F: Fn(&T) -> &String,
+    {
+        let mut shards: HashMap<SocketAddr, Vec<T>> = HashMap::new();
+        for item in items {
+            shards
+                .entry(self.locate(key(&item)))
+                .or_default()
+                .push(item);
+        }
+        shards
+    }
+}
+
+/// Move the keys of the server `from` that `to` owns in `ring`.
+fn migrate(
+    ring: HashRing,
+    from: SocketAddr,
+    to: SocketAddr,
+) -> impl Future<Item = (), Error = KvsError> {
+    KvsClient::connect(from)
+        .and_then(|client| client.keys())
+        .and_then(move |(keys, source)| {
+            let moved: Vec<String> = keys
+                .into_iter()
+                .filter(|key| ring.locate(key) == to)
+                .collect();
+            info!("Moving {} keys from {} to {}", moved.len(), from, to);
+            KvsClient::connect(to).and_then(move |target| {
+                stream::iter_ok(moved).fold((source, target), |(source, target), key| {
+                    source
+                        .get(key.clone())
+                        .and_then(move |(value, source)| match value {
+                            Some(value) => {
+                                Either::A(target.set(key.clone(), value).and_then(move |target| {
+                                    source.remove(key).map(|source| (source, target))
+                                }))
+                            }
+                            // removed since the keys were listed
+                            None => Either::B(future::ok((source, target))),
+                        })
+                })
+            })
+        })
+        .map(|_| ())
+}
+
+/// A consistent hash ring of servers.
+#[derive(Clone, Default)]
+struct HashRing {
+    // map the points on the ring to the servers owning them
+    nodes: BTreeMap<u64, SocketAddr>,
+}
+
+impl HashRing {
+    fn add(&mut self, addr: SocketAddr) {
+        for i in 0..VIRTUAL_NODES {
+            self.nodes
+                .insert(hash(format!("{}#{}", addr, i).as_bytes()), addr);
+        }
+    }
+
+    fn locate(&self, key: &str) -> SocketAddr {
+        let point = hash(key.as_bytes());
+        self.nodes
+            .range(point..)
+            .chain(self.nodes.iter())
+            .map(|(_, &addr)| addr)
+            .next()
+            .expect("hash ring is empty")
+    }
+
+    fn servers(&self) -> Vec<SocketAddr> {
+        let mut servers: Vec<_> = self.nodes.values().cloned().collect();
+        servers.sort();
+        servers.dedup();
+        servers
+    }
+}
+
+/// 64-bit FNV-1a hash followed by the finalizer of MurmurHash3.
+///
+/// The hash of a key must not differ between clients, so `DefaultHasher` is not used.
+/// The finalizer spreads the points of similar strings such as `addr#1` and `addr#2`
+/// over the whole ring.
+fn hash(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash = bytes.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
@@ -1,4 +1,5 @@
 use assert_cmd::prelude::*;
+use predicates::prelude::*;
 use predicates::str::{contains, is_empty};
 use std::fs::{self, File};
 use std::process::Command;
@@ -335,3 +336,74 @@ fn cli_access_server_kvs_engine() {
 fn cli_access_server_sled_engine() {
     cli_access_server("sled", "127.0.0.1:4005");
 }
+
+#[test]
+fn cli_replica_of() {
+    let (sender, receiver) = mpsc::sync_channel(0);
+    let primary_dir = TempDir::new().unwrap();
+    let replica_dir = TempDir::new().unwrap();
+    let primary_addr = "127.0.0.1:4006";
+    let replica_addr = "127.0.0.1:4007";
+    let mut primary = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(&["--engine", "kvs", "--addr", primary_addr])
+        .current_dir(&primary_dir)
+        .spawn()
+        .unwrap();
+    let mut replica = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(&["--engine", "kvs", "--addr", replica_addr])
+        .args(&["--replica-of", primary_addr])
+        .current_dir(&replica_dir)
+        .spawn()
+        .unwrap();
+    let handle = thread::spawn(move || {
+        let _ = receiver.recv(); // wait for main thread to finish
+        primary.kill().expect("primary exited before killed");
+        replica.kill().expect("replica exited before killed");
+    });
+    thread::sleep(Duration::from_secs(1));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(&["set", "key1", "value1", "--addr", primary_addr])
+        .assert()
+        .success();
+    thread::sleep(Duration::from_millis(200));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(&["get", "key1", "--addr", replica_addr])
+        .assert()
+        .success()
+        .stdout("value1\n");
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(&["set", "key2", "value2", "--addr", replica_addr])
+        .assert()
+        .failure()
+        .stderr(contains("Read-only replica"));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(&["stats", "--addr", replica_addr])
+        .assert()
+        .success()
+        .stdout(contains("read_only: true").and(contains("lag_bytes: 0")));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(&["promote", "--addr", replica_addr])
+        .assert()
+        .success();
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(&["set", "key2", "value2", "--addr", replica_addr])
+        .assert()
+        .success();
+
+    sender.send(()).unwrap();
+    handle.join().unwrap();
+}
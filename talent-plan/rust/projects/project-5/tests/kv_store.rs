@@ -1,5 +1,5 @@
 use kvs::thread_pool::RayonThreadPool;
-use kvs::{KvStore, KvsEngine, KvsError, Result};
+use kvs::{Event, KvStore, KvStoreOptions, KvsEngine, KvsError, LogEntry, LogPos, Result};
 use tempfile::TempDir;
 use tokio::prelude::*;
 use tokio::runtime::Runtime;
@@ -106,6 +106,149 @@ fn remove_key() -> Result<()> {
     Ok(())
 }
 
+// Should only receive events of keys with the watched prefix
+#[test]
+fn watch_prefix() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 1)?;
+    let mut events = store.watch("user:".to_owned(), None).wait();
+
+    store.set("user:1".to_owned(), "alice".to_owned()).wait()?;
+    store.set("order:1".to_owned(), "book".to_owned()).wait()?;
+    store.remove("user:1".to_owned()).wait()?;
+
+    let first = events.next().unwrap()?;
+    let seq = first.seq();
+    assert_eq!(
+        first,
+        Event::Set {
+            seq,
+            key: "user:1".to_owned(),
+            value: "alice".to_owned()
+        }
+    );
+    assert_eq!(
+        events.next().unwrap()?,
+        Event::Remove {
+            seq: seq + 2,
+            key: "user:1".to_owned()
+        }
+    );
+    Ok(())
+}
+
+// Should replay retained events when resuming from a sequence number
+#[test]
+fn watch_resume() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 1)?;
+    let mut all = store.watch("".to_owned(), None).wait();
+    for i in 0..10 {
+        store
+            .set(format!("key{}", i), format!("value{}", i))
+            .wait()?;
+    }
+    let first = all.next().unwrap()?.seq();
+
+    let mut events = store.watch("key".to_owned(), Some(first + 7)).wait();
+    store.remove("key0".to_owned()).wait()?;
+    let seqs = (0..4)
+        .map(|_| events.next().unwrap().map(|event| event.seq() - first))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(seqs, vec![7, 8, 9, 10]);
+
+    // Nothing is lost when resuming from the oldest retained event
+    let mut events = store.watch("".to_owned(), Some(first)).wait();
+    assert_eq!(events.next().unwrap()?.seq(), first);
+    Ok(())
+}
+
+// Should reject resuming from a sequence number of before a restart
+#[test]
+fn watch_resume_after_restart() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 1)?;
+    let mut events = store.watch("".to_owned(), None).wait();
+    store.set("key1".to_owned(), "value1".to_owned()).wait()?;
+    let before = events.next().unwrap()?.seq();
+    drop(events);
+    drop(store);
+
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 1)?;
+    let mut events = store.watch("".to_owned(), None).wait();
+    store.set("key2".to_owned(), "value2".to_owned()).wait()?;
+    assert!(events.next().unwrap()?.seq() > before);
+    let mut resumed = store.watch("".to_owned(), Some(before)).wait();
+    match resumed.next().unwrap() {
+        Err(KvsError::SeqNotRetained(seq)) => assert_eq!(seq, before),
+        other => panic!("expected SeqNotRetained, got {:?}", other),
+    }
+    Ok(())
+}
+
+// Should report how far behind the primary the replica is
+#[test]
+fn replicate_behind() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 1)?;
+    let mut entries = store.replicate(LogPos::default()).wait();
+    assert_eq!(entries.next().unwrap()?, LogEntry::Reset);
+    assert_eq!(entries.next().unwrap()?, LogEntry::Synced);
+
+    for i in 0..3 {
+        store
+            .set(format!("key{}", i), format!("value{}", i))
+            .wait()?;
+    }
+    let behind = (0..3)
+        .map(|_| match entries.next().unwrap()? {
+            LogEntry::Set { behind, .. } => Ok(behind),
+            other => panic!("expected Set, got {:?}", other),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // the entries have the same length
+    let len = behind[1] - behind[2];
+    assert!(len > 0);
+    assert_eq!(behind, vec![2 * len, len, 0]);
+    Ok(())
+}
+
+// Should resynchronize a replica that does not keep up
+#[test]
+fn replicate_overflow() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 1)?;
+    let mut entries = store.replicate(LogPos::default()).wait();
+    assert_eq!(entries.next().unwrap()?, LogEntry::Reset);
+    assert_eq!(entries.next().unwrap()?, LogEntry::Synced);
+
+    for i in 0..5000 {
+        store
+            .set(format!("key{}", i), format!("value{}", i))
+            .wait()?;
+    }
+    let mut live = 0;
+    loop {
+        match entries.next().unwrap()? {
+            LogEntry::Set { .. } => live += 1,
+            LogEntry::Reset => break,
+            other => panic!("expected Set or Reset, got {:?}", other),
+        }
+    }
+    assert!(live < 5000);
+    // The whole log is sent again
+    let mut replayed = 0;
+    loop {
+        match entries.next().unwrap()? {
+            LogEntry::Set { .. } => replayed += 1,
+            LogEntry::Synced => break,
+            other => panic!("expected Set or Synced, got {:?}", other),
+        }
+    }
+    assert_eq!(replayed, 5000);
+    Ok(())
+}
+
 // Insert data until total size of the directory decreases.
 // Test data correctness after compaction.
 #[test]
@@ -152,6 +295,74 @@ fn compaction() -> Result<()> {
     panic!("No compaction detected");
 }
 
+// Values moved to the compaction file should be read without reopening the store.
+#[test]
+fn get_after_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 4)?;
+
+    // overwriting the keys 20 times makes more than 1 MB stale and triggers compactions
+    for iter in 0..20 {
+        for key_id in 0..1000 {
+            let key = format!("key{}", key_id);
+            let value = format!("{}-{}", iter, "x".repeat(100));
+            store.set(key, value).wait()?;
+        }
+        for key_id in 0..1000 {
+            let key = format!("key{}", key_id);
+            let value = format!("{}-{}", iter, "x".repeat(100));
+            assert_eq!(store.get(key).wait()?, Some(value));
+        }
+    }
+    Ok(())
+}
+
+// Cached values should be served until they are overwritten, removed or relocated.
+#[test]
+fn value_cache() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = KvStoreOptions::new().cache_size(1024 * 1024);
+    let store = KvStore::<RayonThreadPool>::open_with(temp_dir.path(), 4, options)?;
+
+    store.set("key1".to_owned(), "value1".to_owned()).wait()?;
+    assert_eq!(
+        store.get("key1".to_owned()).wait()?,
+        Some("value1".to_owned())
+    );
+    assert_eq!(
+        store.get("key1".to_owned()).wait()?,
+        Some("value1".to_owned())
+    );
+    let stats = store.cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+
+    store.set("key1".to_owned(), "value2".to_owned()).wait()?;
+    assert_eq!(
+        store.get("key1".to_owned()).wait()?,
+        Some("value2".to_owned())
+    );
+    store.remove("key1".to_owned()).wait()?;
+    assert_eq!(store.get("key1".to_owned()).wait()?, None);
+
+    // keep a hot key cached while compactions relocate it
+    store.set("hot".to_owned(), "value".to_owned()).wait()?;
+    for iter in 0..20 {
+        for key_id in 0..1000 {
+            let key = format!("key{}", key_id);
+            let value = format!("{}-{}", iter, "x".repeat(100));
+            store.set(key, value).wait()?;
+        }
+        assert_eq!(
+            store.get("hot".to_owned()).wait()?,
+            Some("value".to_owned())
+        );
+    }
+    let stats = store.cache_stats().unwrap();
+    assert!(stats.hits >= 20);
+    assert!(stats.size <= stats.capacity);
+    Ok(())
+}
+
 #[test]
 fn concurrent_set() -> Result<()> {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
@@ -0,0 +1,61 @@
+use kvs::thread_pool::RayonThreadPool;
+use kvs::{KvStore, KvsClient, KvsServer, Result, ShardedKvsClient};
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::prelude::*;
+
+// Start a `KvsServer` with a `KvStore` in a background thread.
+fn start_server(addr: SocketAddr) -> TempDir {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<RayonThreadPool>::open(temp_dir.path(), 1).unwrap();
+    thread::spawn(move || KvsServer::new(store).run(addr).unwrap());
+    temp_dir
+}
+
+fn keys_of(addr: SocketAddr) -> Result<Vec<String>> {
+    let (keys, _) = KvsClient::connect(addr)
+        .and_then(|client| client.keys())
+        .wait()?;
+    Ok(keys)
+}
+
+// Keys should be spread over the servers and only the keys owned by a new
+// server should be moved to it.
+#[test]
+fn add_shard() -> Result<()> {
+    let addrs: Vec<SocketAddr> = (4010..4014)
+        .map(|port| format!("127.0.0.1:{}", port).parse().unwrap())
+        .collect();
+    let _dirs: Vec<_> = addrs.iter().map(|&addr| start_server(addr)).collect();
+    thread::sleep(Duration::from_secs(1));
+
+    let client = ShardedKvsClient::new(&addrs[..3])?;
+    let pairs: Vec<_> = (0..300)
+        .map(|i| (format!("key{}", i), format!("value{}", i)))
+        .collect();
+    client.set_many(pairs.clone()).wait()?;
+    for &addr in &addrs[..3] {
+        assert!(!keys_of(addr)?.is_empty());
+    }
+
+    let before: Vec<_> = pairs.iter().map(|(key, _)| client.locate(key)).collect();
+    let client = client.add_shard(addrs[3]).wait()?;
+    for ((key, _), &old) in pairs.iter().zip(&before) {
+        let new = client.locate(key);
+        assert!(new == old || new == addrs[3]);
+    }
+    assert!(!keys_of(addrs[3])?.is_empty());
+    for &addr in &addrs {
+        for key in keys_of(addr)? {
+            assert_eq!(client.locate(&key), addr);
+        }
+    }
+
+    let keys = pairs.iter().map(|(key, _)| key.clone()).collect();
+    let values = client.get_many(keys).wait()?;
+    let expected: Vec<_> = pairs.into_iter().map(|(_, value)| Some(value)).collect();
+    assert_eq!(values, expected);
+    Ok(())
+}